@@ -0,0 +1,933 @@
+// Un CancelableLatch è un tratto di sincronizzazione che permette a uno o più thread di attendere, senza
+// consumare cicli di CPU, che altri thread eseguano i propri compiti e ne segnalino l'esito.
+// All'atto della creazione occorre indicare il numero di compiti da attendere.
+// Il tratto oﬀre il metodo count_down() che permette di indicare che uno dei compiti è terminato con successo:
+// se non restano altri compiti da attendere, le attese vengono sbloccate con successo, altrimenti proseguono.
+// Il metodo cancel() permette di segnalare che uno dei compiti è fallito: in questo caso, le attese vengono
+// subito sbloccate indicando l'avvenuta cancellazione.
+// Il tratto oﬀre due metodi di attesa: uno incondizionato (ovvero, l'attesa si protrae fino a che tutti i compiti
+// sono stati terminati con successo o è stata richiesta una cancellazione) e uno con timeout (in questo caso,
+// l'attesa può terminare anche se entro il tempo indicato non si raggiungono le condizioni precedenti: in tale
+// caso viene segnalato che il tempo è scaduto).
+// Si realizzi, usando il linguaggio Rust, una struttura che implementi tale tratto.
+
+use std::{sync::{Arc, Condvar, Mutex}, time::{Duration, Instant}};
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum WaitResult {
+    Success,
+    Timeout,
+    Canceled
+}
+
+// identifica una callback registrata con on_complete, per poterla rimuovere prima che venga invocata
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct CallbackId(u64);
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum ResetError {
+    InProgress,     // il latch non è ancora né completato né cancellato
+    WaitersBlocked, // ci sono ancora thread in attesa sul round corrente
+}
+
+pub trait CancelableLatch {
+    fn new(count: usize) -> Self;
+    fn count_down(&self);
+    fn count_down_by(&self, n: usize);
+    fn add_count(&self, n: usize) -> bool;
+    fn cancel(&self);
+    fn wait(&self) -> WaitResult;
+    fn wait_timeout(&self, d: Duration) -> WaitResult;
+    fn reset(&self, count: usize) -> Result<(), ResetError>;
+    fn on_complete(&self, f: impl FnOnce(WaitResult) + Send + 'static) -> CallbackId;
+    fn remove_callback(&self, id: CallbackId) -> bool;
+    fn remaining(&self) -> usize;
+    fn is_canceled(&self) -> bool;
+    fn is_complete(&self) -> bool;
+    fn stats(&self) -> LatchStats;
+
+    // attesa con scadenza assoluta: utile per attendere più latch rispetto a un'unica scadenza comune
+    fn wait_deadline(&self, deadline: Instant) -> WaitResult {
+        self.wait_timeout(deadline.saturating_duration_since(Instant::now()))
+    }
+}
+
+type Callback = Box<dyn FnOnce(WaitResult) + Send>;
+
+struct LatchState {
+    count: usize,
+    canceled: bool,
+    waiting: usize, // thread attualmente bloccati in wait/wait_timeout
+    callbacks: Vec<(CallbackId, Callback)>, // da invocare una sola volta al completamento o alla cancellazione
+    next_callback: u64,                     // non azzerato da reset, così un id vecchio non rimuove una callback nuova
+    started: Instant,         // creazione o ultimo reset
+    stats: LatchStats,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatchStats {
+    pub count_downs: usize,                // compiti segnalati come terminati (anche oltre lo zero)
+    pub waits: usize,                      // attese concluse, con qualunque esito
+    pub total_wait: Duration,
+    pub max_wait: Duration,
+    pub completion_time: Option<Duration>, // da creazione/reset a completamento o cancellazione
+}
+
+impl LatchState {
+    fn new(count: usize) -> Self {
+        let mut state = LatchState {
+            count,
+            canceled: false,
+            waiting: 0,
+            callbacks: Vec::new(),
+            next_callback: 0,
+            started: Instant::now(),
+            stats: LatchStats {
+                count_downs: 0,
+                waits: 0,
+                total_wait: Duration::ZERO,
+                max_wait: Duration::ZERO,
+                completion_time: None,
+            },
+        };
+        if count == 0 {
+            state.mark_finished();
+        }
+        state
+    }
+
+    fn mark_finished(&mut self) {
+        if self.stats.completion_time.is_none() {
+            self.stats.completion_time = Some(self.started.elapsed());
+        }
+    }
+
+    fn record_wait(&mut self, start: Instant) {
+        let waited = start.elapsed();
+        self.stats.waits += 1;
+        self.stats.total_wait += waited;
+        self.stats.max_wait = self.stats.max_wait.max(waited);
+    }
+
+    fn outcome(&self) -> Option<WaitResult> {
+        if self.canceled {
+            Some(WaitResult::Canceled)
+        } else if self.count == 0 {
+            Some(WaitResult::Success)
+        } else {
+            None
+        }
+    }
+}
+
+fn run_callbacks(callbacks: Vec<(CallbackId, Callback)>, outcome: WaitResult) {
+    for (_, f) in callbacks {
+        f(outcome);
+    }
+}
+
+pub struct Counter {
+    state: Arc<Mutex<LatchState>>,
+    cv: Condvar
+}
+
+impl CancelableLatch for Counter {
+    fn new(count: usize) -> Self {
+        Counter {
+            state: Arc::new(Mutex::new(LatchState::new(count))),
+            cv: Condvar::new()
+        }
+    }
+
+    fn count_down(&self) {
+        self.count_down_by(1);
+    }
+
+    fn count_down_by(&self, n: usize) {
+        // segnala in un colpo solo la fine di n compiti (il conteggio non scende sotto zero)
+        let mut state = self.state.lock().unwrap();
+        state.stats.count_downs += n;
+        if state.count > 0 {
+            state.count = state.count.saturating_sub(n);
+            if state.count == 0 {
+                state.mark_finished();
+                self.cv.notify_all();
+                if !state.canceled {
+                    let callbacks = std::mem::take(&mut state.callbacks);
+                    drop(state);
+                    run_callbacks(callbacks, WaitResult::Success);
+                }
+            }
+        } else {
+            self.cv.notify_all();
+        }
+    }
+
+    fn add_count(&self, n: usize) -> bool {
+        // registra n nuovi compiti da attendere; fallisce se il latch è già completato o cancellato
+        let mut state = self.state.lock().unwrap();
+        if state.count == 0 || state.canceled {
+            return false;
+        }
+        state.count += n;
+        true
+    }
+
+    fn cancel(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.canceled = true;
+        state.mark_finished();
+        self.cv.notify_all();
+        let callbacks = std::mem::take(&mut state.callbacks);
+        drop(state);
+        run_callbacks(callbacks, WaitResult::Canceled);
+    }
+
+    fn wait(&self) -> WaitResult {
+        let start = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        state.waiting += 1;
+        while state.count > 0 && !state.canceled {
+            state = self.cv.wait(state).unwrap();
+        }
+        state.waiting -= 1;
+        state.record_wait(start);
+        if state.canceled {
+            WaitResult::Canceled
+        } else {
+            WaitResult::Success
+        }
+    }
+
+    fn wait_timeout(&self, d: Duration) -> WaitResult {
+        let start = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        state.waiting += 1;
+        let result = self.cv.wait_timeout_while(state, d, |s| {
+            s.count > 0 && !s.canceled
+        }).unwrap();
+        let mut state = result.0;
+        state.waiting -= 1;
+        state.record_wait(start);
+        if state.canceled {
+            WaitResult::Canceled
+        } else if state.count == 0 {
+            WaitResult::Success
+        } else if result.1.timed_out() {
+            WaitResult::Timeout
+        } else {
+            WaitResult::Timeout // fallback, should not happen
+        }
+    }
+
+    fn reset(&self, count: usize) -> Result<(), ResetError> {
+        // riarma un latch completato o cancellato per un nuovo round, senza riallocarlo
+        let mut state = self.state.lock().unwrap();
+        if state.count > 0 && !state.canceled {
+            return Err(ResetError::InProgress);
+        }
+        if state.waiting > 0 {
+            return Err(ResetError::WaitersBlocked);
+        }
+        // le callback del round concluso sono già state consumate: si riparte da uno stato nuovo
+        let next_callback = state.next_callback;
+        *state = LatchState::new(count);
+        state.next_callback = next_callback;
+        Ok(())
+    }
+
+    fn on_complete(&self, f: impl FnOnce(WaitResult) + Send + 'static) -> CallbackId {
+        // registra una callback invocata una sola volta con l'esito del latch; se il latch
+        // è già completato o cancellato viene invocata subito, nel thread chiamante
+        let mut state = self.state.lock().unwrap();
+        let id = CallbackId(state.next_callback);
+        state.next_callback += 1;
+        match state.outcome() {
+            Some(outcome) => {
+                drop(state);
+                f(outcome);
+            }
+            None => state.callbacks.push((id, Box::new(f))),
+        }
+        id
+    }
+
+    fn remove_callback(&self, id: CallbackId) -> bool {
+        // rimuove una callback non ancora invocata; false se è già stata eseguita (o rimossa)
+        let mut state = self.state.lock().unwrap();
+        let before = state.callbacks.len();
+        state.callbacks.retain(|(registered, _)| *registered != id);
+        state.callbacks.len() < before
+    }
+
+    fn remaining(&self) -> usize {
+        // numero di compiti non ancora terminati
+        self.state.lock().unwrap().count
+    }
+
+    fn is_canceled(&self) -> bool {
+        self.state.lock().unwrap().canceled
+    }
+
+    fn stats(&self) -> LatchStats {
+        // statistiche del round corrente (azzerate da reset)
+        self.state.lock().unwrap().stats.clone()
+    }
+
+    fn is_complete(&self) -> bool {
+        // vero solo se tutti i compiti sono terminati con successo (un latch cancellato non è completo)
+        self.state.lock().unwrap().outcome() == Some(WaitResult::Success)
+    }
+}
+
+// ---------------------- ATTESA SU PIÙ LATCH ----------------------
+// Le attese combinate registrano su ciascun latch una callback di completamento che annota l'esito
+// in uno stato condiviso, e poi si bloccano su un'unica Condvar: nessun polling dei singoli latch.
+// Al ritorno le callback non ancora invocate vengono rimosse, altrimenti un ciclo di attese con timeout
+// le accumulerebbe sui latch ancora in corso.
+
+fn wait_group<L: CancelableLatch>(
+    latches: &[&L],
+    deadline: Option<Instant>,
+    done: impl Fn(&[Option<WaitResult>]) -> bool,
+) -> Vec<Option<WaitResult>> {
+    let group = Arc::new((Mutex::new(vec![None; latches.len()]), Condvar::new()));
+    let ids: Vec<CallbackId> = latches.iter().enumerate().map(|(i, latch)| {
+        let group = group.clone();
+        latch.on_complete(move |outcome| {
+            let (lock, cv) = &*group;
+            lock.lock().unwrap()[i] = Some(outcome);
+            cv.notify_all();
+        })
+    }).collect();
+
+    let (lock, cv) = &*group;
+    let mut outcomes = lock.lock().unwrap();
+    while !done(&outcomes) {
+        match deadline {
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                outcomes = cv.wait_timeout(outcomes, deadline - now).unwrap().0;
+            }
+            None => outcomes = cv.wait(outcomes).unwrap(),
+        }
+    }
+    let outcomes = outcomes.clone();
+    for (latch, id) in latches.iter().zip(ids) {
+        latch.remove_callback(id);
+    }
+    outcomes
+}
+
+fn all_done(outcomes: &[Option<WaitResult>]) -> bool {
+    outcomes.iter().all(|o| o.is_some()) || outcomes.contains(&Some(WaitResult::Canceled))
+}
+
+fn all_outcome(outcomes: &[Option<WaitResult>]) -> WaitResult {
+    if outcomes.contains(&Some(WaitResult::Canceled)) {
+        WaitResult::Canceled
+    } else if outcomes.iter().all(|o| o.is_some()) {
+        WaitResult::Success
+    } else {
+        WaitResult::Timeout
+    }
+}
+
+fn any_outcome(outcomes: &[Option<WaitResult>]) -> Option<(usize, WaitResult)> {
+    // a parità di condizioni una cancellazione ha la precedenza su un completamento
+    outcomes.iter().position(|o| *o == Some(WaitResult::Canceled))
+        .or_else(|| outcomes.iter().position(|o| o.is_some()))
+        .map(|i| (i, outcomes[i].unwrap()))
+}
+
+// attende che tutti i latch siano completati; ritorna Canceled appena uno di essi viene cancellato
+pub fn wait_all<L: CancelableLatch>(latches: &[&L]) -> WaitResult {
+    all_outcome(&wait_group(latches, None, all_done))
+}
+
+pub fn wait_all_timeout<L: CancelableLatch>(latches: &[&L], d: Duration) -> WaitResult {
+    all_outcome(&wait_group(latches, Some(Instant::now() + d), all_done))
+}
+
+// attende che almeno un latch sia completato o cancellato, e ne ritorna l'indice con l'esito
+pub fn wait_any<L: CancelableLatch>(latches: &[&L]) -> (usize, WaitResult) {
+    assert!(!latches.is_empty(), "wait_any requires at least one latch");
+    any_outcome(&wait_group(latches, None, |o| o.iter().any(|o| o.is_some()))).unwrap()
+}
+
+// come wait_any, ma ritorna None se entro d nessun latch è terminato
+pub fn wait_any_timeout<L: CancelableLatch>(latches: &[&L], d: Duration) -> Option<(usize, WaitResult)> {
+    any_outcome(&wait_group(latches, Some(Instant::now() + d), |o| o.iter().any(|o| o.is_some())))
+}
+
+
+
+// ------------------------- TESTS ------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_count_down_to_zero() {
+        let latch = Counter::new(2);
+        
+        // Count down twice
+        latch.count_down();
+        latch.count_down();
+        
+        // Should succeed immediately since count is 0
+        let result = latch.wait();
+        assert_eq!(result, WaitResult::Success);
+    }
+
+    #[test]
+    fn test_wait_with_timeout_success() {
+        let latch = Counter::new(1);
+        latch.count_down(); // Count down immediately
+        
+        let result = latch.wait_timeout(Duration::from_millis(100));
+        assert_eq!(result, WaitResult::Success);
+    }
+
+    #[test]
+    fn test_wait_with_timeout_expires() {
+        let latch = Counter::new(1);
+        
+        // Wait with a short timeout, should timeout
+        let start = Instant::now();
+        let result = latch.wait_timeout(Duration::from_millis(50));
+        let elapsed = start.elapsed();
+        
+        assert_eq!(result, WaitResult::Timeout);
+        assert!(elapsed >= Duration::from_millis(45)); // Allow some tolerance
+    }
+
+    #[test]
+    fn test_cancel_before_wait() {
+        let latch = Counter::new(2);
+        
+        // Cancel before waiting
+        latch.cancel();
+        
+        let result = latch.wait();
+        assert_eq!(result, WaitResult::Canceled);
+    }
+
+    #[test]
+    fn test_cancel_during_wait() {
+        let latch = Arc::new(Counter::new(2));
+        let latch_clone = latch.clone();
+        
+        // Spawn a thread that cancels after a short delay
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(30));
+            latch_clone.cancel();
+        });
+        
+        let result = latch.wait();
+        assert_eq!(result, WaitResult::Canceled);
+    }
+
+    #[test]
+    fn test_multiple_waiters_success() {
+        let latch = Arc::new(Counter::new(2));
+        let mut handles = vec![];
+        
+        // Spawn multiple waiting threads
+        for _ in 0..3 {
+            let latch_clone = latch.clone();
+            let handle = thread::spawn(move || {
+                latch_clone.wait()
+            });
+            handles.push(handle);
+        }
+        
+        // Count down to zero
+        thread::sleep(Duration::from_millis(10));
+        latch.count_down();
+        latch.count_down();
+        
+        // All waiters should succeed
+        for handle in handles {
+            let result = handle.join().unwrap();
+            assert_eq!(result, WaitResult::Success);
+        }
+    }
+
+    #[test]
+    fn test_multiple_waiters_cancel() {
+        let latch = Arc::new(Counter::new(2));
+        let mut handles = vec![];
+        
+        // Spawn multiple waiting threads
+        for _ in 0..3 {
+            let latch_clone = latch.clone();
+            let handle = thread::spawn(move || {
+                latch_clone.wait()
+            });
+            handles.push(handle);
+        }
+        
+        // Cancel after a short delay
+        thread::sleep(Duration::from_millis(10));
+        latch.cancel();
+        
+        // All waiters should be canceled
+        for handle in handles {
+            let result = handle.join().unwrap();
+            assert_eq!(result, WaitResult::Canceled);
+        }
+    }
+
+    #[test]
+    fn test_count_down_more_than_initial() {
+        let latch = Counter::new(2);
+        
+        // Count down more times than initial count
+        latch.count_down();
+        latch.count_down();
+        latch.count_down(); // Extra count down
+        
+        let result = latch.wait();
+        assert_eq!(result, WaitResult::Success);
+    }
+
+    #[test]
+    fn test_zero_initial_count() {
+        let latch = Counter::new(0);
+        
+        // Should succeed immediately
+        let result = latch.wait();
+        assert_eq!(result, WaitResult::Success);
+    }
+
+    #[test]
+    fn test_timeout_with_partial_countdown() {
+        let latch = Counter::new(2);
+        
+        // Count down only once
+        latch.count_down();
+        
+        // Should timeout since count is still 1
+        let start = Instant::now();
+        let result = latch.wait_timeout(Duration::from_millis(50));
+        let elapsed = start.elapsed();
+        
+        assert_eq!(result, WaitResult::Timeout);
+        assert!(elapsed >= Duration::from_millis(45));
+    }
+
+    #[test]
+    fn test_concurrent_count_down() {
+        let latch = Arc::new(Counter::new(4));
+        let mut handles = vec![];
+        
+        // Spawn multiple threads that count down
+        for _ in 0..4 {
+            let latch_clone = latch.clone();
+            let handle = thread::spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                latch_clone.count_down();
+            });
+            handles.push(handle);
+        }
+        
+        // Wait for completion
+        let result = latch.wait();
+        assert_eq!(result, WaitResult::Success);
+        
+        // Wait for all threads to complete
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_timeout_vs_cancel_race() {
+        let latch = Arc::new(Counter::new(1));
+        let latch_clone = latch.clone();
+        
+        // Spawn a thread that cancels after a delay
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(25));
+            latch_clone.cancel();
+        });
+        
+        // Wait with timeout that should expire around the same time
+        let result = latch.wait_timeout(Duration::from_millis(30));
+        
+        // Should be either Canceled or Timeout, but not Success
+        assert!(result == WaitResult::Canceled || result == WaitResult::Timeout);
+    }
+
+    #[test]
+    fn test_wait_after_cancel() {
+        let latch = Counter::new(2);
+        
+        // Cancel first
+        latch.cancel();
+        
+        // Multiple waits should all return Canceled
+        assert_eq!(latch.wait(), WaitResult::Canceled);
+        assert_eq!(latch.wait(), WaitResult::Canceled);
+        assert_eq!(latch.wait_timeout(Duration::from_millis(10)), WaitResult::Canceled);
+    }
+
+    #[test]
+    fn test_count_down_after_cancel() {
+        let latch = Counter::new(2);
+        
+        // Cancel first
+        latch.cancel();
+        
+        // Count down should not change the canceled state
+        latch.count_down();
+        latch.count_down();
+        
+        let result = latch.wait();
+        assert_eq!(result, WaitResult::Canceled);
+    }
+
+    #[test]
+    fn test_add_count_extends_the_wait() {
+        let latch = Arc::new(Counter::new(1));
+        assert!(latch.add_count(2));
+
+        latch.count_down();
+        assert_eq!(latch.wait_timeout(Duration::from_millis(20)), WaitResult::Timeout);
+
+        // fork-join: i sottocompiti si registrano prima che il padre segnali la propria fine
+        let latch_clone = latch.clone();
+        let handle = thread::spawn(move || {
+            assert!(latch_clone.add_count(1));
+            latch_clone.count_down();
+            latch_clone.count_down();
+            latch_clone.count_down();
+        });
+        handle.join().unwrap();
+
+        assert_eq!(latch.wait(), WaitResult::Success);
+    }
+
+    #[test]
+    fn test_add_count_after_completion_or_cancel_fails() {
+        let latch = Counter::new(1);
+        latch.count_down();
+        assert!(!latch.add_count(1));
+        assert_eq!(latch.wait(), WaitResult::Success);
+
+        let latch = Counter::new(1);
+        latch.cancel();
+        assert!(!latch.add_count(1));
+        assert_eq!(latch.wait(), WaitResult::Canceled);
+    }
+
+    #[test]
+    fn test_wait_deadline_shared_between_latches() {
+        let first = Arc::new(Counter::new(1));
+        let second = Counter::new(1);
+
+        let first_clone = first.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            first_clone.count_down();
+        });
+
+        let start = Instant::now();
+        let deadline = start + Duration::from_millis(100);
+        assert_eq!(first.wait_deadline(deadline), WaitResult::Success);
+        assert_eq!(second.wait_deadline(deadline), WaitResult::Timeout);
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(95));
+        assert!(elapsed < Duration::from_millis(180)); // la scadenza non si somma tra le attese
+    }
+
+    #[test]
+    fn test_wait_deadline_in_the_past() {
+        let latch = Counter::new(1);
+        assert_eq!(latch.wait_deadline(Instant::now()), WaitResult::Timeout);
+        latch.count_down();
+        assert_eq!(latch.wait_deadline(Instant::now()), WaitResult::Success);
+    }
+
+    #[test]
+    fn test_reset_rearms_latch_for_a_new_round() {
+        let latch = Arc::new(Counter::new(2));
+        for _round in 0..3 {
+            let latch_clone = latch.clone();
+            let handle = thread::spawn(move || {
+                latch_clone.count_down();
+                latch_clone.count_down();
+            });
+            assert_eq!(latch.wait(), WaitResult::Success);
+            handle.join().unwrap();
+            assert_eq!(latch.reset(2), Ok(()));
+        }
+        assert_eq!(latch.wait_timeout(Duration::from_millis(10)), WaitResult::Timeout);
+    }
+
+    #[test]
+    fn test_reset_clears_cancellation() {
+        let latch = Counter::new(1);
+        latch.cancel();
+        assert_eq!(latch.wait(), WaitResult::Canceled);
+        assert_eq!(latch.reset(1), Ok(()));
+        latch.count_down();
+        assert_eq!(latch.wait(), WaitResult::Success);
+    }
+
+    #[test]
+    fn test_reset_fails_while_in_progress() {
+        let latch = Counter::new(1);
+        assert_eq!(latch.reset(3), Err(ResetError::InProgress));
+    }
+
+    #[test]
+    fn test_reset_fails_with_blocked_waiters() {
+        let latch = Arc::new(Counter::new(1));
+        let latch_clone = latch.clone();
+        let handle = thread::spawn(move || latch_clone.wait());
+
+        thread::sleep(Duration::from_millis(30));
+        {
+            // simula un completamento che i waiter non hanno ancora osservato
+            let mut state = latch.state.lock().unwrap();
+            state.count = 0;
+            assert!(state.waiting > 0);
+        }
+        assert_eq!(latch.reset(1), Err(ResetError::WaitersBlocked));
+
+        latch.cv.notify_all();
+        assert_eq!(handle.join().unwrap(), WaitResult::Success);
+        assert_eq!(latch.reset(1), Ok(()));
+    }
+
+    #[test]
+    fn test_on_complete_called_once_on_success() {
+        use std::sync::mpsc::channel;
+
+        let latch = Counter::new(2);
+        let (tx, rx) = channel();
+        latch.on_complete(move |outcome| tx.send(outcome).unwrap());
+
+        latch.count_down();
+        assert!(rx.try_recv().is_err());
+        latch.count_down();
+        latch.count_down();
+        latch.cancel();
+
+        assert_eq!(rx.recv().unwrap(), WaitResult::Success);
+        assert!(rx.recv().is_err()); // la callback è stata consumata
+    }
+
+    #[test]
+    fn test_on_complete_called_on_cancel() {
+        use std::sync::mpsc::channel;
+
+        let latch = Arc::new(Counter::new(2));
+        let (tx, rx) = channel();
+        latch.on_complete(move |outcome| tx.send(outcome).unwrap());
+
+        let latch_clone = latch.clone();
+        thread::spawn(move || latch_clone.cancel()).join().unwrap();
+
+        assert_eq!(rx.recv().unwrap(), WaitResult::Canceled);
+    }
+
+    #[test]
+    fn test_on_complete_after_completion_runs_immediately() {
+        use std::sync::mpsc::channel;
+
+        let latch = Counter::new(0);
+        let (tx, rx) = channel();
+        latch.on_complete(move |outcome| tx.send(outcome).unwrap());
+        assert_eq!(rx.try_recv(), Ok(WaitResult::Success));
+    }
+
+    #[test]
+    fn test_introspection_reports_progress() {
+        let latch = Counter::new(3);
+        assert_eq!(latch.remaining(), 3);
+        assert!(!latch.is_complete());
+        assert!(!latch.is_canceled());
+
+        latch.count_down();
+        assert_eq!(latch.remaining(), 2);
+
+        latch.count_down();
+        latch.count_down();
+        assert_eq!(latch.remaining(), 0);
+        assert!(latch.is_complete());
+        assert!(!latch.is_canceled());
+    }
+
+    #[test]
+    fn test_introspection_after_cancel() {
+        let latch = Counter::new(2);
+        latch.count_down();
+        latch.cancel();
+        assert_eq!(latch.remaining(), 1);
+        assert!(latch.is_canceled());
+        assert!(!latch.is_complete());
+    }
+
+    #[test]
+    fn test_wait_all_succeeds_when_every_latch_completes() {
+        let first = Arc::new(Counter::new(1));
+        let second = Arc::new(Counter::new(2));
+
+        let (f, sc) = (first.clone(), second.clone());
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            sc.count_down();
+            f.count_down();
+            thread::sleep(Duration::from_millis(10));
+            sc.count_down();
+        });
+
+        assert_eq!(wait_all(&[&*first, &*second]), WaitResult::Success);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_wait_all_is_canceled_by_any_latch() {
+        let first = Counter::new(1);
+        let second = Arc::new(Counter::new(1));
+
+        let sc = second.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            sc.cancel();
+        });
+
+        assert_eq!(wait_all(&[&first, &*second]), WaitResult::Canceled);
+    }
+
+    #[test]
+    fn test_wait_all_timeout_expires() {
+        let first = Counter::new(0);
+        let second = Counter::new(1);
+        assert_eq!(wait_all_timeout(&[&first, &second], Duration::from_millis(30)), WaitResult::Timeout);
+        assert_eq!(wait_all::<Counter>(&[]), WaitResult::Success);
+    }
+
+    #[test]
+    fn test_wait_any_returns_first_finished_latch() {
+        let first = Counter::new(1);
+        let second = Arc::new(Counter::new(1));
+
+        let sc = second.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            sc.count_down();
+        });
+
+        assert_eq!(wait_any(&[&first, &*second]), (1, WaitResult::Success));
+        assert_eq!(wait_any_timeout(&[&first], Duration::from_millis(20)), None);
+        first.cancel();
+        assert_eq!(wait_any_timeout(&[&first, &*second], Duration::from_millis(20)), Some((0, WaitResult::Canceled)));
+    }
+
+    #[test]
+    fn test_count_down_by_completes_in_batches() {
+        use std::sync::mpsc::channel;
+
+        let latch = Arc::new(Counter::new(10));
+        let (tx, rx) = channel();
+        latch.on_complete(move |outcome| tx.send(outcome).unwrap());
+
+        let latch_clone = latch.clone();
+        let waiter = thread::spawn(move || latch_clone.wait());
+
+        latch.count_down_by(4);
+        assert_eq!(latch.remaining(), 6);
+        latch.count_down_by(6);
+
+        assert_eq!(waiter.join().unwrap(), WaitResult::Success);
+        assert_eq!(rx.recv().unwrap(), WaitResult::Success);
+    }
+
+    #[test]
+    fn test_count_down_by_more_than_remaining() {
+        let latch = Counter::new(3);
+        latch.count_down_by(5);
+        assert_eq!(latch.remaining(), 0);
+        assert_eq!(latch.wait(), WaitResult::Success);
+    }
+
+    #[test]
+    fn test_stats_report_wait_latency_and_count_downs() {
+        let latch = Arc::new(Counter::new(2));
+        let latch_clone = latch.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(40));
+            latch_clone.count_down_by(2);
+            latch_clone.count_down();
+        });
+
+        assert_eq!(latch.wait(), WaitResult::Success);
+        handle.join().unwrap();
+        assert_eq!(latch.wait_timeout(Duration::from_millis(10)), WaitResult::Success);
+
+        let stats = latch.stats();
+        assert_eq!(stats.count_downs, 3);
+        assert_eq!(stats.waits, 2);
+        assert!(stats.max_wait >= Duration::from_millis(35));
+        assert!(stats.total_wait >= stats.max_wait);
+        assert!(stats.completion_time.unwrap() >= Duration::from_millis(35));
+    }
+
+    #[test]
+    fn test_stats_restart_after_reset() {
+        let latch = Counter::new(1);
+        assert_eq!(latch.stats().completion_time, None);
+        latch.cancel();
+        assert_eq!(latch.wait_timeout(Duration::from_millis(10)), WaitResult::Canceled);
+        assert!(latch.stats().completion_time.is_some());
+
+        latch.reset(1).unwrap();
+        let stats = latch.stats();
+        assert_eq!(stats.waits, 0);
+        assert_eq!(stats.count_downs, 0);
+        assert_eq!(stats.completion_time, None);
+    }
+
+    #[test]
+    fn test_remove_callback_before_completion() {
+        use std::sync::mpsc::channel;
+
+        let latch = Counter::new(1);
+        let (tx, rx) = channel();
+        let kept_tx = tx.clone();
+        let removed = latch.on_complete(move |outcome| tx.send((0, outcome)).unwrap());
+        latch.on_complete(move |outcome| kept_tx.send((1, outcome)).unwrap());
+
+        assert!(latch.remove_callback(removed));
+        assert!(!latch.remove_callback(removed));
+        latch.count_down();
+        assert_eq!(rx.recv().unwrap(), (1, WaitResult::Success));
+        assert!(rx.recv().is_err());
+    }
+
+    #[test]
+    fn test_polling_with_timeout_does_not_accumulate_callbacks() {
+        let first = Counter::new(1);
+        let second = Counter::new(1);
+        for _ in 0..1000 {
+            assert_eq!(wait_any_timeout(&[&first, &second], Duration::ZERO), None);
+            assert_eq!(wait_all_timeout(&[&first, &second], Duration::ZERO), WaitResult::Timeout);
+        }
+        assert!(first.state.lock().unwrap().callbacks.is_empty());
+        assert!(second.state.lock().unwrap().callbacks.is_empty());
+
+        // anche wait_any che ritorna per il primo latch rimuove la callback dal secondo
+        first.count_down();
+        assert_eq!(wait_any(&[&first, &second]), (0, WaitResult::Success));
+        assert!(second.state.lock().unwrap().callbacks.is_empty());
+    }
+}
@@ -10,11 +10,16 @@
 // terminato in modo sicuro. Per implementare tale sistema, si richiede di realizzare la struct Aggregator che
 // oﬀre i seguenti metodi thread-safe:
 
-use std::collections::HashMap;
-use std::sync::{Arc, Condvar, Mutex};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
 use std::thread::JoinHandle;
 use std::time::{Duration, Instant};
 
+#[derive(Clone)]
 struct Measurement {
     id: usize,
     timestamp: Instant,
@@ -23,15 +28,168 @@ struct Measurement {
 
 struct InnerState {
     running: bool,
-    measurements: Vec<Measurement>,
-    sample_time: Instant,
+    sample_time: Instant, // fine dell'ultima finestra chiusa
+    sample_period: Duration,
+    period_changed: bool, // sveglia il thread interno per ricalcolare la scadenza
+    paused: bool,
     recent_averages: Vec<Average>,
+    recent_stats: Vec<WindowStats>,
+    history: VecDeque<(Instant, Vec<Average>)>, // ultime finestre calcolate (con la loro fine), dalla più vecchia alla più recente
+    subscribers: Vec<Sender<Vec<Average>>>,
+    alerts: Vec<Alert>,
+    sensors: HashMap<usize, SensorInfo>,
+    interval_averages: HashMap<Duration, Vec<Average>>, // ultime medie degli intervalli aggiuntivi
+}
+
+// metadati di un sensore registrato con register_sensor
+#[derive(PartialEq, Clone, Debug)]
+pub struct SensorInfo {
+    pub name: String,
+    pub unit: String,
+    pub calibration_offset: f64, // correzione sommata a ogni misura grezza prima dell'aggregazione
+}
+
+// buffer di ingresso delle misure: ogni thread produttore scrive in uno shard scelto in base al suo id,
+// così che add_measure non contenda il lock con gli altri produttori né con il thread interno,
+// che svuota gli shard a ogni chiusura di finestra
+struct Shard {
+    measurements: VecDeque<Measurement>,
+    closed_until: Instant, // copia di sample_time: le misure precedenti sono in ritardo
+    running: bool,
+    paused: bool,
+    dropped_late: usize,  // misure arrivate dopo la chiusura della loro finestra
+    dropped_overflow: usize, // misure scartate per backlog pieno
+}
+
+type Shards = Arc<Vec<(Mutex<Shard>, Condvar)>>;
+
+// verso dell'attraversamento di una soglia
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Crossing {
+    Above, // la media è salita sopra la soglia
+    Below, // la media è tornata sotto (o alla) soglia
+}
+
+pub type AlertFn = dyn FnMut(&Average, Crossing) + Send;
+
+struct Alert {
+    sensor_id: Option<usize>, // None = soglia globale, valida per tutti i sensori
+    threshold: f64,
+    callback: Box<AlertFn>,
+    above: HashSet<usize>, // sensori che all'ultima finestra erano sopra la soglia
+}
+
+impl Alert {
+    fn check(&mut self, averages: &[Average]) {
+        for avg in averages {
+            if self.sensor_id.is_some_and(|id| id != avg.sensor_id) {
+                continue;
+            }
+            let is_above = avg.average_temperature > self.threshold;
+            let was_above = self.above.contains(&avg.sensor_id);
+            if is_above && !was_above {
+                self.above.insert(avg.sensor_id);
+                (self.callback)(avg, Crossing::Above);
+            } else if !is_above && was_above {
+                self.above.remove(&avg.sensor_id);
+                (self.callback)(avg, Crossing::Below);
+            }
+        }
+    }
+}
+
+// funzione di aggregazione: riceve l'id del sensore e le misure della finestra (mai vuote)
+// e restituisce il valore riportato in `average_temperature`. È eseguita dal thread interno,
+// quindi può mantenere uno stato tra una finestra e l'altra (es. EWMA)
+pub type AggregationFn = dyn FnMut(usize, &[f64]) -> f64 + Send;
+
+// parametri di costruzione dell'Aggregator
+pub struct AggregatorConfig {
+    pub sample_time_millis: u64,
+    pub history_windows: usize, // numero di finestre conservate nello storico (0 = nessuno storico)
+    pub aggregation: Box<AggregationFn>,
+    pub lateness_millis: u64, // ritardo massimo accettato per le misure con istante fornito dal chiamante
+    pub while_paused: PausedMeasures,
+    pub max_pending: Option<usize>, // limite alle misure in attesa di campionamento (None = illimitato)
+    pub overflow: OverflowPolicy,
+    // intervalli aggiuntivi (es. 1 min, 15 min) calcolati sullo stesso flusso di misure; si chiudono
+    // alla prima finestra base che termina dopo la loro scadenza e usano la stessa funzione di aggregazione.
+    // Un intervallo inferiore a MIN_SAMPLE_PERIOD (es. zero) viene portato al minimo
+    pub extra_intervals_millis: Vec<u64>,
+    pub ingestion_shards: usize, // numero di buffer di ingresso indipendenti (almeno 1)
+    pub retention_millis: Option<u64>, // le finestre dello storico più vecchie di così vengono scartate
+    pub downsampling: Vec<Downsample>,
+}
+
+// regola di sottocampionamento dello storico: le finestre più vecchie di `after_millis` vengono
+// fuse in intervalli di `resolution_millis` (es. per-secondo per 1 h, poi per-minuto)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Downsample {
+    pub after_millis: u64,
+    pub resolution_millis: u64,
+}
+
+// cosa fare quando il backlog delle misure in attesa è pieno
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OverflowPolicy {
+    DropOldest, // scarta la misura in attesa più vecchia dello shard del chiamante (o quella in arrivo, se è vuoto)
+    DropNewest, // scarta la misura in arrivo
+    Block,      // blocca il chiamante finché il thread interno non svuota il backlog
+}
+
+// cosa fare delle misure ricevute mentre il campionamento è sospeso
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PausedMeasures {
+    Buffer, // conservate e aggregate nella prima finestra dopo resume()
+    Drop,   // scartate
+}
+
+impl Default for AggregatorConfig {
+    fn default() -> Self {
+        AggregatorConfig {
+            sample_time_millis: 1000,
+            history_windows: 0,
+            aggregation: Box::new(|_, values| mean(values)),
+            lateness_millis: 0,
+            while_paused: PausedMeasures::Buffer,
+            max_pending: None,
+            overflow: OverflowPolicy::DropOldest,
+            extra_intervals_millis: vec![],
+            ingestion_shards: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            retention_millis: None,
+            downsampling: vec![],
+        }
+    }
+}
+
+// periodo minimo di campionamento: con un periodo nullo la scadenza non avanzerebbe mai e il thread
+// interno chiuderebbe finestre vuote senza sosta
+const MIN_SAMPLE_PERIOD: Duration = Duration::from_millis(1);
+
+pub fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+pub fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    }
 }
 
 pub struct Aggregator {
     // campi privati
     state: Arc<(Mutex<InnerState>, Condvar)>,
-    join_handle: Option<JoinHandle<()>>,
+    shards: Shards,
+    join_handle: Option<JoinHandle<Box<AggregationFn>>>, // il thread restituisce la funzione di aggregazione
+    while_paused: PausedMeasures,
+    max_pending: Option<usize>,
+    pending: Arc<AtomicUsize>, // misure in attesa in tutti gli shard, confrontate con max_pending
+    overflow: OverflowPolicy,
 }
 
 #[derive(PartialEq, Clone, Debug)]
@@ -39,22 +197,218 @@ pub struct Average {
     pub sensor_id: usize,
     pub reference_time: Instant, //indica l'istante temporale in cui è stata calcolata la media
     pub average_temperature: f64, 
+    pub sensor: Option<SensorInfo>, // metadati del sensore, None se non è stato registrato
+}
+
+// statistiche complete di un sensore in una finestra di campionamento
+#[derive(PartialEq, Clone, Debug)]
+pub struct WindowStats {
+    pub sensor_id: usize,
+    pub reference_time: Instant,
+    pub average_temperature: f64, // valore calcolato dalla funzione di aggregazione
+    pub min_temperature: f64,
+    pub max_temperature: f64,
+    pub count: usize,
+    pub std_dev: f64, // deviazione standard (di popolazione) delle misure della finestra
+    pub sensor: Option<SensorInfo>, // None se il sensore non è stato registrato
+}
+
+impl WindowStats {
+    fn from_values(
+        sensor_id: usize,
+        reference_time: Instant,
+        values: &[f64],
+        aggregated: f64,
+        sensor: Option<SensorInfo>,
+    ) -> Self {
+        let count = values.len();
+        let mean = mean(values);
+        let variance = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / count as f64;
+        WindowStats {
+            sensor_id,
+            reference_time,
+            average_temperature: aggregated,
+            min_temperature: values.iter().cloned().fold(f64::INFINITY, f64::min),
+            max_temperature: values.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+            count,
+            std_dev: variance.sqrt(),
+            sensor,
+        }
+    }
+
+    fn to_average(&self) -> Average {
+        Average {
+            sensor_id: self.sensor_id,
+            reference_time: self.reference_time,
+            average_temperature: self.average_temperature,
+            sensor: self.sensor.clone(),
+        }
+    }
+}
+
+// applica allo storico la retention e le regole di sottocampionamento. Un intervallo viene fuso solo
+// quando è interamente più vecchio della soglia della regola, così ogni finestra è fusa una volta
+// per regola; la media fusa è la media delle medie del sensore nell'intervallo
+fn compact_history(
+    history: &mut VecDeque<(Instant, Vec<Average>)>,
+    now: Instant,
+    anchor: Instant,
+    retention: Option<Duration>,
+    downsampling: &[Downsample],
+) {
+    if let Some(oldest) = retention.and_then(|r| now.checked_sub(r)) {
+        while history.front().is_some_and(|(t, _)| *t < oldest) {
+            history.pop_front();
+        }
+    }
+    for rule in downsampling {
+        let Some(limit) = now.checked_sub(Duration::from_millis(rule.after_millis)) else {
+            continue;
+        };
+        let resolution = Duration::from_millis(rule.resolution_millis.max(1));
+        let bucket = |t: Instant| t.saturating_duration_since(anchor).as_nanos() / resolution.as_nanos();
+        let bucket_end = |b: u128| anchor + resolution * (b + 1) as u32;
+
+        let mut compacted: VecDeque<(Instant, Vec<Average>)> = VecDeque::new();
+        let mut group: Vec<(Instant, Vec<Average>)> = vec![];
+        for window in history.drain(..) {
+            if group.first().is_some_and(|(t, _)| bucket(*t) != bucket(window.0)) {
+                compacted.push_back(merge_windows(std::mem::take(&mut group)));
+            }
+            if bucket_end(bucket(window.0)) <= limit {
+                group.push(window);
+            } else {
+                compacted.push_back(window);
+            }
+        }
+        if !group.is_empty() {
+            compacted.push_back(merge_windows(group));
+        }
+        *history = compacted;
+    }
+}
+
+fn merge_windows(windows: Vec<(Instant, Vec<Average>)>) -> (Instant, Vec<Average>) {
+    // la finestra fusa è riferita alla fine dell'ultima finestra del gruppo
+    let reference_time = windows.last().unwrap().0;
+    // i metadati sono quelli della finestra più recente del sensore nel gruppo
+    let mut by_sensor: Vec<(usize, Vec<f64>, Option<SensorInfo>)> = vec![];
+    for avg in windows.iter().flat_map(|(_, w)| w.iter()) {
+        match by_sensor.iter_mut().find(|(id, _, _)| *id == avg.sensor_id) {
+            Some((_, values, sensor)) => {
+                values.push(avg.average_temperature);
+                *sensor = avg.sensor.clone();
+            }
+            None => by_sensor.push((avg.sensor_id, vec![avg.average_temperature], avg.sensor.clone())),
+        }
+    }
+    let merged = by_sensor
+        .into_iter()
+        .map(|(sensor_id, values, sensor)| Average {
+            sensor_id,
+            reference_time,
+            average_temperature: mean(&values),
+            sensor,
+        })
+        .collect();
+    (reference_time, merged)
+}
+
+// intervallo aggiuntivo gestito dal thread interno
+struct Interval {
+    period: Duration,
+    deadline: Instant,
+    measurements: Vec<Measurement>,
+}
+
+// calcola le statistiche per sensore delle misure di una finestra,
+// correggendo le misure dei sensori registrati con il loro offset di calibrazione
+fn compute_window(
+    measurements: &[Measurement],
+    reference_time: Instant,
+    aggregation: &mut AggregationFn,
+    sensors: &HashMap<usize, SensorInfo>,
+) -> Vec<WindowStats> {
+    let mut by_sensor = HashMap::<usize, Vec<f64>>::new();
+    for m in measurements {
+        let offset = sensors.get(&m.id).map_or(0.0, |info| info.calibration_offset);
+        by_sensor.entry(m.id).or_default().push(m.measure + offset);
+    }
+    by_sensor
+        .into_iter()
+        .map(|(id, values)| {
+            let aggregated = aggregation(id, &values);
+            WindowStats::from_values(id, reference_time, &values, aggregated, sensors.get(&id).cloned())
+        })
+        .collect()
 }
 
 impl Aggregator {
     pub fn new(sample_time_millis: u64) -> Self {
         // implementazione del costruttore
+        Self::with_config(AggregatorConfig {
+            sample_time_millis,
+            ..Default::default()
+        })
+    }
+
+    pub fn with_config(config: AggregatorConfig) -> Self {
+        let history_windows = config.history_windows;
+        let retention = config.retention_millis.map(Duration::from_millis);
+        let downsampling = config.downsampling;
+        let lateness = Duration::from_millis(config.lateness_millis);
+        let mut aggregation = config.aggregation;
         let state = Arc::new((
             Mutex::new(InnerState {
                 running: true,
-                measurements: vec![],
                 sample_time: Instant::now(),
+                sample_period: Duration::from_millis(config.sample_time_millis).max(MIN_SAMPLE_PERIOD),
+                period_changed: false,
+                paused: false,
                 recent_averages: vec![],
+                recent_stats: vec![],
+                history: VecDeque::new(),
+                subscribers: vec![],
+                alerts: vec![],
+                sensors: HashMap::new(),
+                interval_averages: HashMap::new(),
             }),
             Condvar::new(),
         ));
 
         let thread_state = state.clone();
+        let start = state.0.lock().unwrap().sample_time;
+        let shards: Shards = Arc::new(
+            (0..config.ingestion_shards.max(1))
+                .map(|_| {
+                    let shard = Shard {
+                        measurements: VecDeque::new(),
+                        closed_until: start,
+                        running: true,
+                        paused: false,
+                        dropped_late: 0,
+                        dropped_overflow: 0,
+                    };
+                    (Mutex::new(shard), Condvar::new())
+                })
+                .collect(),
+        );
+        let thread_shards = shards.clone();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let thread_pending = pending.clone();
+        let mut intervals: Vec<Interval> = config
+            .extra_intervals_millis
+            .iter()
+            .map(|&millis| {
+                // con un periodo nullo la scadenza non avanzerebbe mai
+                let period = Duration::from_millis(millis).max(MIN_SAMPLE_PERIOD);
+                Interval {
+                    period,
+                    deadline: start + period,
+                    measurements: vec![],
+                }
+            })
+            .collect();
 
         let join_handle = std::thread::spawn(move || {
             let (mutex, condvar) = &*thread_state;
@@ -62,11 +416,21 @@ impl Aggregator {
             let mut inner_state = mutex.lock().unwrap();
 
             loop {
-                let next_wakeup = inner_state.sample_time + Duration::from_millis(sample_time_millis);
-                let sleep_time = next_wakeup.saturating_duration_since(Instant::now());
+                if inner_state.paused {
+                    // la finestra in corso viene abbandonata: si riparte da resume()
+                    inner_state = condvar.wait_while(inner_state, |s| s.running && s.paused).unwrap();
+                    if !inner_state.running {
+                        break;
+                    }
+                }
+
+                // la finestra termina in next_wakeup, ma viene chiusa solo dopo il ritardo
+                // ammesso, così che le misure in ritardo finiscano nella finestra giusta
+                let next_wakeup = inner_state.sample_time + inner_state.sample_period;
+                let sleep_time = (next_wakeup + lateness).saturating_duration_since(Instant::now());
 
                 let result = condvar
-                    .wait_timeout_while(inner_state, sleep_time, |s| s.running)
+                    .wait_timeout_while(inner_state, sleep_time, |s| s.running && !s.period_changed && !s.paused)
                     .unwrap();
 
                 inner_state = result.0;
@@ -75,56 +439,98 @@ impl Aggregator {
                     break;
                 }
 
+                if inner_state.period_changed || inner_state.paused {
+                    inner_state.period_changed = false;
+                    continue;
+                }
+
                 inner_state.sample_time = next_wakeup;
+                let sensors = inner_state.sensors.clone();
+                drop(inner_state); // Release the lock during computation
 
                 // Extract measurements up to the current sample time
                 let mut measurements: Vec<Measurement> = Vec::new();
-                inner_state.measurements.retain(|m| {
-                    if m.timestamp < next_wakeup {
-                        measurements.push(Measurement {
-                            id: m.id,
-                            timestamp: m.timestamp,
-                            measure: m.measure,
-                        });
-                        false // remove from measurements
-                    } else {
-                        true // keep in measurements
-                    }
-                });
-
-                drop(inner_state); // Release the lock during computation
+                for (shard, _) in thread_shards.iter() {
+                    let mut shard = shard.lock().unwrap();
+                    shard.closed_until = shard.closed_until.max(next_wakeup);
+                    let before = shard.measurements.len();
+                    shard.measurements.retain(|m| {
+                        if m.timestamp < next_wakeup {
+                            measurements.push(m.clone());
+                            false // remove from measurements
+                        } else {
+                            true // keep in measurements
+                        }
+                    });
+                    thread_pending.fetch_sub(before - shard.measurements.len(), Ordering::SeqCst);
+                }
+                // il limite è globale: c'è di nuovo spazio per i produttori bloccati su qualsiasi shard.
+                // Il lock viene preso prima della notifica, così un produttore che ha appena trovato
+                // il backlog pieno è già in attesa e non perde la sveglia
+                for (shard, space) in thread_shards.iter() {
+                    drop(shard.lock().unwrap());
+                    space.notify_all();
+                }
+                // gli shard mescolano i produttori: si ripristina l'ordine di arrivo
+                measurements.sort_by_key(|m| m.timestamp);
 
                 // Compute averages
-                let mut averages = HashMap::<usize, (f64, usize)>::new();
-
-                for m in &measurements {
-                    averages
-                        .entry(m.id)
-                        .and_modify(|(sum, count)| {
-                            *sum += m.measure;
-                            *count += 1;
-                        })
-                        .or_insert((m.measure, 1));
-                }
+                let new_stats = compute_window(&measurements, next_wakeup, &mut *aggregation, &sensors);
+                let new_averages: Vec<Average> = new_stats.iter().map(WindowStats::to_average).collect();
 
-                let new_averages: Vec<Average> = averages
-                    .into_iter()
-                    .map(|(id, (measure, count))| Average {
-                        sensor_id: id,
-                        reference_time: next_wakeup,
-                        average_temperature: measure / count as f64,
-                    })
-                    .collect();
+                // gli intervalli aggiuntivi accumulano le misure della finestra e si chiudono se scaduti
+                let mut closed_intervals = vec![];
+                for interval in intervals.iter_mut() {
+                    interval.measurements.extend(measurements.iter().cloned());
+                    if interval.deadline <= next_wakeup {
+                        let stats = compute_window(&interval.measurements, next_wakeup, &mut *aggregation, &sensors);
+                        closed_intervals.push((interval.period, stats.iter().map(WindowStats::to_average).collect()));
+                        interval.measurements.clear();
+                        while interval.deadline <= next_wakeup {
+                            interval.deadline += interval.period;
+                        }
+                    }
+                }
 
                 // Store the result
                 inner_state = mutex.lock().unwrap();
-                inner_state.recent_averages = new_averages;
+                if history_windows > 0 {
+                    if inner_state.history.len() == history_windows {
+                        inner_state.history.pop_front();
+                    }
+                    inner_state.history.push_back((next_wakeup, new_averages.clone()));
+                    compact_history(&mut inner_state.history, next_wakeup, start, retention, &downsampling);
+                }
+                // i ricevitori chiusi vengono rimossi
+                inner_state.subscribers.retain(|tx| tx.send(new_averages.clone()).is_ok());
+                inner_state.recent_averages = new_averages.clone();
+                inner_state.recent_stats = new_stats;
+                inner_state.interval_averages.extend(closed_intervals);
+
+                // le callback delle soglie sono invocate senza tenere il lock
+                let mut alerts = std::mem::take(&mut inner_state.alerts);
+                if !alerts.is_empty() {
+                    drop(inner_state);
+                    for alert in alerts.iter_mut() {
+                        alert.check(&new_averages);
+                    }
+                    inner_state = mutex.lock().unwrap();
+                    alerts.append(&mut inner_state.alerts); // registrate nel frattempo
+                }
+                inner_state.alerts = alerts;
             }
+
+            aggregation
         });
 
         Self {
             state,
+            shards,
             join_handle: Some(join_handle),
+            while_paused: config.while_paused,
+            max_pending: config.max_pending,
+            pending,
+            overflow: config.overflow,
         }
     }
 
@@ -132,16 +538,166 @@ impl Aggregator {
         // aggiunge una misura di temperatura per il sensore con id `sensor_id` e temperatura `temperature`.
         // Le misure sono automaticamente etichettate
         // con l'istante temporale in cui sono comunicate.
+        let (shard, space) = self.lock_shard();
+        if shard.paused && self.while_paused == PausedMeasures::Drop {
+            return;
+        }
+        // l'istante è preso sotto lock, quindi non può precedere la fine dell'ultima finestra chiusa
         let now = Instant::now();
-        let mut state = self.state.0.lock().unwrap();
 
-        state.measurements.push(Measurement {
+        self.enqueue(shard, space, Measurement {
             id: sensor_id,
             timestamp: now,
             measure: temperature,
         });
     }
 
+    pub fn add_measure_at(&self, sensor_id: usize, temperature: f64, timestamp: Instant) -> bool {
+        // aggiunge una misura etichettata dal chiamante (es. bufferizzata da un gateway): finisce nella
+        // finestra a cui appartiene `timestamp`, purché arrivi entro il ritardo ammesso dalla configurazione.
+        // Restituisce false (e la misura viene scartata) se la sua finestra è già stata chiusa
+        let (mut shard, space) = self.lock_shard();
+        if shard.paused && self.while_paused == PausedMeasures::Drop {
+            return false;
+        }
+        if timestamp < shard.closed_until {
+            shard.dropped_late += 1;
+            return false;
+        }
+        self.enqueue(shard, space, Measurement {
+            id: sensor_id,
+            timestamp,
+            measure: temperature,
+        })
+    }
+
+    fn lock_shard(&self) -> (MutexGuard<'_, Shard>, &Condvar) {
+        // blocca lo shard del thread chiamante
+        let mut hasher = DefaultHasher::new();
+        std::thread::current().id().hash(&mut hasher);
+        let (mutex, space) = &self.shards[hasher.finish() as usize % self.shards.len()];
+        (mutex.lock().unwrap(), space)
+    }
+
+    fn enqueue(&self, mut shard: MutexGuard<'_, Shard>, space: &Condvar, m: Measurement) -> bool {
+        // accoda la misura applicando la politica di overflow; false se la misura è stata scartata.
+        // Il limite vale per le misure in attesa in tutti gli shard: il posto viene prenotato sul
+        // contatore condiviso prima di accodare, così produttori su shard diversi non lo superano
+        let Some(max) = self.max_pending else {
+            self.pending.fetch_add(1, Ordering::SeqCst);
+            shard.measurements.push_back(m);
+            return true;
+        };
+        let reserve = || {
+            self.pending
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |p| (p < max).then_some(p + 1))
+                .is_ok()
+        };
+        let mut reserved = reserve();
+        if !reserved && self.overflow == OverflowPolicy::Block {
+            // attende che il thread interno svuoti il backlog (o la chiusura dell'Aggregator)
+            shard = space
+                .wait_while(shard, |s| {
+                    reserved = reserve();
+                    s.running && !reserved
+                })
+                .unwrap();
+        }
+        if !reserved {
+            shard.dropped_overflow += 1;
+            // gli altri shard non vengono toccati: se questo è vuoto si scarta la misura in arrivo
+            if self.overflow == OverflowPolicy::DropNewest || shard.measurements.pop_front().is_none() {
+                return false;
+            }
+            // la misura in arrivo prende il posto di quella scartata
+        }
+        shard.measurements.push_back(m);
+        true
+    }
+
+    pub fn dropped_measurements(&self) -> usize {
+        // misure scartate perché il backlog era pieno
+        self.shards.iter().map(|(shard, _)| shard.lock().unwrap().dropped_overflow).sum()
+    }
+
+    pub fn register_sensor(&self, sensor_id: usize, name: &str, unit: &str, calibration_offset: f64) {
+        // registra (o aggiorna) i metadati di un sensore: l'offset viene applicato a partire
+        // dalla prossima finestra calcolata, anche alle misure già in attesa
+        self.state.0.lock().unwrap().sensors.insert(sensor_id, SensorInfo {
+            name: name.to_string(),
+            unit: unit.to_string(),
+            calibration_offset,
+        });
+    }
+
+    pub fn sensor_info(&self, sensor_id: usize) -> Option<SensorInfo> {
+        self.state.0.lock().unwrap().sensors.get(&sensor_id).cloned()
+    }
+
+    pub fn on_threshold(
+        &self,
+        sensor_id: Option<usize>,
+        threshold: f64,
+        callback: impl FnMut(&Average, Crossing) + Send + 'static,
+    ) {
+        // registra una soglia per un sensore (o per tutti, con None): il thread interno invoca la callback
+        // quando la media di una finestra supera la soglia e quando vi ritorna sotto
+        self.state.0.lock().unwrap().alerts.push(Alert {
+            sensor_id,
+            threshold,
+            callback: Box::new(callback),
+            above: HashSet::new(),
+        });
+    }
+
+    pub fn pause(&self) {
+        // sospende il campionamento: finché non viene chiamato resume() non vengono calcolate medie
+        let mut state = self.state.0.lock().unwrap();
+        state.paused = true;
+        for (shard, _) in self.shards.iter() {
+            shard.lock().unwrap().paused = true;
+        }
+        drop(state);
+        self.state.1.notify_all();
+    }
+
+    pub fn resume(&self) {
+        // riprende il campionamento: la prima finestra parte da adesso
+        let mut state = self.state.0.lock().unwrap();
+        if state.paused {
+            state.paused = false;
+            state.sample_time = Instant::now();
+            for (shard, _) in self.shards.iter() {
+                let mut shard = shard.lock().unwrap();
+                shard.paused = false;
+                shard.closed_until = state.sample_time;
+            }
+        }
+        drop(state);
+        self.state.1.notify_all();
+    }
+
+    pub fn set_sample_period(&self, period: Duration) {
+        // cambia il periodo di campionamento senza perdere le misure in attesa: la finestra in corso
+        // termina a (inizio finestra + nuovo periodo), o subito se tale istante è già passato: in quel caso
+        // si chiude una sola volta, riferita ad adesso, senza recuperare le finestre (vuote) arretrate.
+        // Un periodo inferiore a MIN_SAMPLE_PERIOD (es. zero) viene portato al minimo
+        let mut state = self.state.0.lock().unwrap();
+        state.sample_period = period.max(MIN_SAMPLE_PERIOD);
+        let now = Instant::now();
+        if state.sample_time + state.sample_period < now {
+            // la finestra in corso scade adesso e la successiva parte da qui
+            state.sample_time = now - state.sample_period;
+        }
+        state.period_changed = true;
+        drop(state);
+        self.state.1.notify_all();
+    }
+
+    pub fn dropped_late_measurements(&self) -> usize {
+        self.shards.iter().map(|(shard, _)| shard.lock().unwrap().dropped_late).sum()
+    }
+
     pub fn get_averages(&self) -> Vec<Average> {
         // restituisce un vettore che riporta la temperatura media di ciascun sensore,
         // calcolata durante l'ultimo periodo di campionamento.
@@ -149,10 +705,51 @@ impl Aggregator {
         let state = self.state.0.lock().unwrap();
         state.recent_averages.clone()
     }
+
+    pub fn get_averages_for(&self, period: Duration) -> Option<Vec<Average>> {
+        // come get_averages, ma per uno degli intervalli aggiuntivi della configurazione (o per il
+        // periodo di campionamento corrente); None se il periodo non è calcolato
+        let state = self.state.0.lock().unwrap();
+        if period == state.sample_period {
+            return Some(state.recent_averages.clone());
+        }
+        state.interval_averages.get(&period).cloned()
+    }
+
+    pub fn subscribe(&self) -> Receiver<Vec<Average>> {
+        // restituisce un canale su cui il thread interno invia le medie di ogni finestra
+        // (anche vuota) appena calcolate; il canale si chiude alla distruzione dell'Aggregator
+        let (tx, rx) = channel();
+        self.state.0.lock().unwrap().subscribers.push(tx);
+        rx
+    }
+
+    pub fn get_stats(&self) -> Vec<WindowStats> {
+        // come get_averages, ma con minimo, massimo, numero di misure e deviazione standard
+        let state = self.state.0.lock().unwrap();
+        state.recent_stats.clone()
+    }
+
+    pub fn get_history(&self, sensor_id: usize) -> Vec<Average> {
+        // restituisce le medie del sensore indicato nelle finestre conservate, in ordine cronologico.
+        // Le finestre in cui il sensore non ha inviato misure non compaiono.
+        let state = self.state.0.lock().unwrap();
+        state.history
+            .iter()
+            .flat_map(|(_, window)| window.iter().filter(|a| a.sensor_id == sensor_id))
+            .cloned()
+            .collect()
+    }
+
+    pub fn get_all_history(&self) -> Vec<Vec<Average>> {
+        // restituisce tutte le finestre conservate, in ordine cronologico (anche quelle vuote)
+        let state = self.state.0.lock().unwrap();
+        state.history.iter().map(|(_, window)| window.clone()).collect()
+    }
 }
 
-impl Drop for Aggregator {
-    fn drop(&mut self) {
+impl Aggregator {
+    fn shutdown(&mut self) -> Option<Box<AggregationFn>> {
         // Signal the background thread to stop
         let mut state = self.state.0.lock().unwrap();
         state.running = false;
@@ -161,10 +758,37 @@ impl Drop for Aggregator {
         // Notify the background thread in case it's sleeping
         self.state.1.notify_all();
 
-        // Join the background thread to ensure clean shutdown
-        if let Some(join_handle) = self.join_handle.take() {
-            join_handle.join().unwrap();
+        // i produttori bloccati su uno shard pieno vengono liberati
+        for (shard, space) in self.shards.iter() {
+            shard.lock().unwrap().running = false;
+            space.notify_all();
         }
+
+        // Join the background thread to ensure clean shutdown
+        self.join_handle.take().map(|join_handle| join_handle.join().unwrap())
+    }
+
+    pub fn stop(mut self) -> Vec<Average> {
+        // termina il thread interno e restituisce le medie delle misure non ancora campionate
+        // (riferite all'istante dello stop), così che nessun dato vada perso alla chiusura
+        let mut aggregation = self.shutdown().unwrap();
+        let mut tail: Vec<Measurement> = self
+            .shards
+            .iter()
+            .flat_map(|(shard, _)| std::mem::take(&mut shard.lock().unwrap().measurements))
+            .collect();
+        tail.sort_by_key(|m| m.timestamp);
+        let state = self.state.0.lock().unwrap();
+        compute_window(&tail, Instant::now(), &mut *aggregation, &state.sensors)
+            .iter()
+            .map(WindowStats::to_average)
+            .collect()
+    }
+}
+
+impl Drop for Aggregator {
+    fn drop(&mut self) {
+        self.shutdown();
     }
 }
 
@@ -176,7 +800,10 @@ fn main() {
 
 #[cfg(test)]
 mod tests {
-    use super::{Aggregator, Average};
+    use super::{
+        median, Aggregator, AggregatorConfig, Average, Crossing, Downsample, OverflowPolicy, PausedMeasures, SensorInfo,
+        WindowStats,
+    };
     use std::time::Duration;
 
     #[test]
@@ -218,8 +845,8 @@ mod tests {
         let averages = aggregator.get_averages();
         assert_eq!(averages.len(),  2);
         let timestamp = averages.get(0).unwrap().reference_time;
-        assert!(averages.contains(&Average{ sensor_id:1, average_temperature:1.5, reference_time: timestamp }));
-        assert!(averages.contains(&Average{ sensor_id:2, average_temperature:1.5, reference_time: timestamp }));
+        assert!(averages.contains(&Average{ sensor_id:1, average_temperature:1.5, reference_time: timestamp, sensor: None }));
+        assert!(averages.contains(&Average{ sensor_id:2, average_temperature:1.5, reference_time: timestamp, sensor: None }));
     }
 
     #[test]
@@ -241,8 +868,8 @@ mod tests {
         let averages = aggregator.get_averages();
         assert_eq!(averages.len(),  2);
         let timestamp = averages.get(0).unwrap().reference_time;
-        assert!(averages.contains(&Average{ sensor_id:1, average_temperature:2.0, reference_time: timestamp }));
-        assert!(averages.contains(&Average{ sensor_id:2, average_temperature:5.0, reference_time: timestamp }));
+        assert!(averages.contains(&Average{ sensor_id:1, average_temperature:2.0, reference_time: timestamp, sensor: None }));
+        assert!(averages.contains(&Average{ sensor_id:2, average_temperature:5.0, reference_time: timestamp, sensor: None }));
     }
     #[test]
     fn an_aggregator_shuts_down_cleanly() {
@@ -251,4 +878,438 @@ mod tests {
         }
         assert!(true);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn history_keeps_the_last_windows() {
+        let aggregator = Aggregator::with_config(AggregatorConfig {
+            sample_time_millis: 50,
+            history_windows: 2,
+            ..Default::default()
+        });
+        aggregator.add_measure(1, 1.0);
+        std::thread::sleep(Duration::from_millis(60));
+        aggregator.add_measure(1, 2.0);
+        aggregator.add_measure(2, 5.0);
+        std::thread::sleep(Duration::from_millis(50));
+        aggregator.add_measure(1, 3.0);
+        std::thread::sleep(Duration::from_millis(50));
+
+        let all = aggregator.get_all_history();
+        assert_eq!(all.len(), 2); // la prima finestra è stata scartata
+        let history: Vec<f64> = aggregator.get_history(1).iter().map(|a| a.average_temperature).collect();
+        assert_eq!(history, vec![2.0, 3.0]);
+        assert_eq!(aggregator.get_history(2).len(), 1);
+        assert!(all[0][0].reference_time < all[1][0].reference_time);
+    }
+
+    #[test]
+    fn history_is_disabled_by_default() {
+        let aggregator = Aggregator::new(10);
+        aggregator.add_measure(1, 1.0);
+        std::thread::sleep(Duration::from_millis(25));
+        assert!(aggregator.get_all_history().is_empty());
+        assert!(aggregator.get_history(1).is_empty());
+    }
+
+    #[test]
+    fn window_stats_include_spread_information() {
+        let aggregator = Aggregator::new(50);
+        for t in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+            aggregator.add_measure(1, t);
+        }
+        aggregator.add_measure(2, 3.0);
+        std::thread::sleep(Duration::from_millis(60));
+
+        let stats = aggregator.get_stats();
+        assert_eq!(stats.len(), 2);
+        let s1 = stats.iter().find(|s| s.sensor_id == 1).unwrap();
+        assert!(matches!(s1, &WindowStats{ average_temperature: 5.0, min_temperature: 2.0, max_temperature: 9.0, count: 8, std_dev: 2.0, .. }));
+        let s2 = stats.iter().find(|s| s.sensor_id == 2).unwrap();
+        assert!(matches!(s2, &WindowStats{ average_temperature: 3.0, count: 1, std_dev: 0.0, .. }));
+        assert_eq!(aggregator.get_averages().len(), 2);
+    }
+
+    #[test]
+    fn a_custom_aggregation_function_is_used() {
+        let aggregator = Aggregator::with_config(AggregatorConfig {
+            sample_time_millis: 50,
+            aggregation: Box::new(|_, values| median(values)),
+            ..Default::default()
+        });
+        for t in [1.0, 2.0, 100.0] {
+            aggregator.add_measure(1, t);
+        }
+        std::thread::sleep(Duration::from_millis(60));
+        let averages = aggregator.get_averages();
+        assert!(matches!(averages.first(), Some(&Average{ sensor_id:1, average_temperature:2.0, .. })));
+        // le altre statistiche restano calcolate sulle misure grezze
+        assert_eq!(aggregator.get_stats()[0].max_temperature, 100.0);
+    }
+
+    #[test]
+    fn a_stateful_aggregation_function_spans_windows() {
+        // EWMA con alpha = 0.5 sulla media di ciascuna finestra
+        let mut last = std::collections::HashMap::<usize, f64>::new();
+        let aggregator = Aggregator::with_config(AggregatorConfig {
+            sample_time_millis: 50,
+            aggregation: Box::new(move |id, values| {
+                let m = values.iter().sum::<f64>() / values.len() as f64;
+                let ewma = last.get(&id).map_or(m, |prev| 0.5 * prev + 0.5 * m);
+                last.insert(id, ewma);
+                ewma
+            }),
+            ..Default::default()
+        });
+        aggregator.add_measure(1, 10.0);
+        std::thread::sleep(Duration::from_millis(60));
+        aggregator.add_measure(1, 20.0);
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(matches!(aggregator.get_averages().first(), Some(&Average{ average_temperature:15.0, .. })));
+    }
+
+    #[test]
+    fn subscribers_receive_every_window() {
+        let aggregator = Aggregator::new(30);
+        let rx = aggregator.subscribe();
+        aggregator.add_measure(1, 1.0);
+        aggregator.add_measure(1, 3.0);
+
+        let first = rx.recv_timeout(Duration::from_millis(200)).unwrap();
+        assert!(matches!(first.first(), Some(&Average{ sensor_id:1, average_temperature:2.0, .. })));
+        let second = rx.recv_timeout(Duration::from_millis(200)).unwrap();
+        assert!(second.is_empty());
+
+        drop(aggregator);
+        assert!(rx.recv().is_err());
+    }
+
+    #[test]
+    fn late_measures_land_in_their_window() {
+        let aggregator = Aggregator::with_config(AggregatorConfig {
+            sample_time_millis: 50,
+            lateness_millis: 40,
+            ..Default::default()
+        });
+        let rx = aggregator.subscribe();
+        let start = std::time::Instant::now();
+
+        aggregator.add_measure(1, 1.0);
+        std::thread::sleep(Duration::from_millis(70));
+        // appartiene alla prima finestra, che non è ancora stata chiusa
+        assert!(aggregator.add_measure_at(1, 3.0, start + Duration::from_millis(10)));
+        // appartiene alla seconda finestra
+        aggregator.add_measure(1, 10.0);
+
+        let first = rx.recv_timeout(Duration::from_millis(200)).unwrap();
+        assert!(matches!(first.first(), Some(&Average{ sensor_id:1, average_temperature:2.0, .. })));
+        let second = rx.recv_timeout(Duration::from_millis(200)).unwrap();
+        assert!(matches!(second.first(), Some(&Average{ sensor_id:1, average_temperature:10.0, .. })));
+    }
+
+    #[test]
+    fn measures_later_than_the_allowance_are_dropped() {
+        let aggregator = Aggregator::new(20);
+        let start = std::time::Instant::now();
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(!aggregator.add_measure_at(1, 1.0, start));
+        assert_eq!(aggregator.dropped_late_measurements(), 1);
+    }
+
+    #[test]
+    fn the_sample_period_can_be_shortened_at_runtime() {
+        let aggregator = Aggregator::new(10_000);
+        let rx = aggregator.subscribe();
+        aggregator.add_measure(1, 4.0);
+        aggregator.set_sample_period(Duration::from_millis(30));
+
+        // la finestra in corso si chiude con il nuovo periodo, senza perdere la misura
+        let first = rx.recv_timeout(Duration::from_millis(200)).unwrap();
+        assert!(matches!(first.first(), Some(&Average{ sensor_id:1, average_temperature:4.0, .. })));
+        aggregator.add_measure(1, 6.0);
+        let second = rx.recv_timeout(Duration::from_millis(200)).unwrap();
+        assert!(matches!(second.first(), Some(&Average{ sensor_id:1, average_temperature:6.0, .. })));
+    }
+
+    #[test]
+    fn a_shorter_period_does_not_replay_elapsed_windows() {
+        let aggregator = Aggregator::new(10_000);
+        let rx = aggregator.subscribe();
+        aggregator.add_measure(1, 4.0);
+        std::thread::sleep(Duration::from_millis(100));
+        let changed_at = std::time::Instant::now();
+        aggregator.set_sample_period(Duration::from_millis(30));
+
+        // la nuova scadenza è già passata: una sola finestra, riferita al cambio di periodo
+        let first = rx.recv_timeout(Duration::from_millis(200)).unwrap();
+        assert!(matches!(first.first(), Some(&Average{ sensor_id:1, average_temperature:4.0, .. })));
+        assert!(first[0].reference_time >= changed_at);
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(rx.try_iter().count(), 0);
+    }
+
+    #[test]
+    fn a_zero_sample_period_is_clamped() {
+        // con un periodo nullo il thread interno girerebbe a vuoto: le finestre devono restare limitate
+        let count_windows = |aggregator: &Aggregator| {
+            let rx = aggregator.subscribe();
+            std::thread::sleep(Duration::from_millis(100));
+            rx.try_iter().count()
+        };
+        let aggregator = Aggregator::new(0);
+        assert!(count_windows(&aggregator) <= 110);
+
+        let aggregator = Aggregator::new(10_000);
+        aggregator.set_sample_period(Duration::ZERO);
+        assert!(count_windows(&aggregator) <= 110);
+        assert!(aggregator.get_averages_for(Duration::from_millis(1)).is_some());
+    }
+
+    #[test]
+    fn no_averages_are_produced_while_paused() {
+        let aggregator = Aggregator::new(20);
+        let rx = aggregator.subscribe();
+        aggregator.pause();
+        aggregator.add_measure(1, 1.0);
+        assert!(rx.recv_timeout(Duration::from_millis(60)).is_err());
+
+        aggregator.resume();
+        aggregator.add_measure(1, 3.0);
+        let window = rx.recv_timeout(Duration::from_millis(200)).unwrap();
+        // la misura ricevuta durante la pausa è stata conservata
+        assert!(matches!(window.first(), Some(&Average{ sensor_id:1, average_temperature:2.0, .. })));
+    }
+
+    #[test]
+    fn measures_received_while_paused_can_be_dropped() {
+        let aggregator = Aggregator::with_config(AggregatorConfig {
+            sample_time_millis: 20,
+            while_paused: PausedMeasures::Drop,
+            ..Default::default()
+        });
+        let rx = aggregator.subscribe();
+        aggregator.pause();
+        aggregator.add_measure(1, 1.0);
+        aggregator.resume();
+        aggregator.add_measure(1, 3.0);
+        let window = rx.recv_timeout(Duration::from_millis(200)).unwrap();
+        assert!(matches!(window.first(), Some(&Average{ sensor_id:1, average_temperature:3.0, .. })));
+    }
+
+    #[test]
+    fn threshold_callbacks_fire_on_crossings() {
+        use std::sync::mpsc::channel;
+
+        let aggregator = Aggregator::new(30);
+        let rx = aggregator.subscribe();
+        let (tx, alerts) = channel();
+        let tx_global = tx.clone();
+        aggregator.on_threshold(Some(1), 50.0, move |avg, crossing| {
+            tx.send((avg.sensor_id, crossing)).unwrap();
+        });
+        aggregator.on_threshold(None, 100.0, move |avg, crossing| {
+            tx_global.send((avg.sensor_id + 100, crossing)).unwrap();
+        });
+
+        aggregator.add_measure(1, 60.0);
+        aggregator.add_measure(2, 120.0);
+        rx.recv_timeout(Duration::from_millis(200)).unwrap();
+        aggregator.add_measure(1, 70.0); // resta sopra: nessuna nuova notifica
+        rx.recv_timeout(Duration::from_millis(200)).unwrap();
+        aggregator.add_measure(1, 40.0);
+        rx.recv_timeout(Duration::from_millis(200)).unwrap();
+        rx.recv_timeout(Duration::from_millis(200)).unwrap();
+
+        let mut fired: Vec<(usize, Crossing)> = alerts.try_iter().collect();
+        fired.sort_by_key(|(id, _)| *id);
+        assert_eq!(fired, vec![(1, Crossing::Above), (1, Crossing::Below), (102, Crossing::Above)]);
+    }
+
+    #[test]
+    fn stop_flushes_the_pending_measures() {
+        let aggregator = Aggregator::new(10_000);
+        aggregator.add_measure(1, 1.0);
+        aggregator.add_measure(1, 2.0);
+        aggregator.add_measure(2, 7.0);
+        let mut tail = aggregator.stop();
+        tail.sort_by_key(|a| a.sensor_id);
+        assert_eq!(tail.len(), 2);
+        assert!(matches!(tail.first(), Some(&Average{ sensor_id:1, average_temperature:1.5, .. })));
+        assert!(matches!(tail.get(1), Some(&Average{ sensor_id:2, average_temperature:7.0, .. })));
+    }
+
+    #[test]
+    fn stop_without_pending_measures_returns_nothing() {
+        let aggregator = Aggregator::new(10);
+        assert!(aggregator.stop().is_empty());
+    }
+
+    #[test]
+    fn a_full_backlog_drops_the_oldest_measures() {
+        let aggregator = Aggregator::with_config(AggregatorConfig {
+            sample_time_millis: 10_000,
+            max_pending: Some(2),
+            ..Default::default()
+        });
+        aggregator.add_measure(1, 1.0);
+        aggregator.add_measure(1, 2.0);
+        aggregator.add_measure(1, 4.0);
+        assert_eq!(aggregator.dropped_measurements(), 1);
+        assert!(matches!(aggregator.stop().first(), Some(&Average{ average_temperature:3.0, .. })));
+    }
+
+    #[test]
+    fn a_full_backlog_can_reject_new_measures() {
+        let aggregator = Aggregator::with_config(AggregatorConfig {
+            sample_time_millis: 10_000,
+            max_pending: Some(2),
+            overflow: OverflowPolicy::DropNewest,
+            ..Default::default()
+        });
+        aggregator.add_measure(1, 1.0);
+        aggregator.add_measure(1, 2.0);
+        assert!(!aggregator.add_measure_at(1, 4.0, std::time::Instant::now()));
+        assert_eq!(aggregator.dropped_measurements(), 1);
+        assert!(matches!(aggregator.stop().first(), Some(&Average{ average_temperature:1.5, .. })));
+    }
+
+    #[test]
+    fn the_backlog_limit_spans_all_shards() {
+        let aggregator = std::sync::Arc::new(Aggregator::with_config(AggregatorConfig {
+            sample_time_millis: 10_000,
+            max_pending: Some(2),
+            overflow: OverflowPolicy::DropNewest,
+            ingestion_shards: 4,
+            ..Default::default()
+        }));
+        let producers: Vec<_> = (0..8)
+            .map(|i| {
+                let aggregator = aggregator.clone();
+                std::thread::spawn(move || aggregator.add_measure(i, 1.0))
+            })
+            .collect();
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        // il limite è complessivo: su 8 misure ne restano in attesa solo 2, qualunque sia lo shard
+        assert_eq!(aggregator.dropped_measurements(), 6);
+        let aggregator = std::sync::Arc::try_unwrap(aggregator).ok().unwrap();
+        assert_eq!(aggregator.stop().len(), 2);
+    }
+
+    #[test]
+    fn a_full_backlog_can_block_producers() {
+        let aggregator = Aggregator::with_config(AggregatorConfig {
+            sample_time_millis: 50,
+            max_pending: Some(1),
+            overflow: OverflowPolicy::Block,
+            ..Default::default()
+        });
+        aggregator.add_measure(1, 1.0);
+        let start = std::time::Instant::now();
+        aggregator.add_measure(1, 2.0); // attende la chiusura della prima finestra
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        assert_eq!(aggregator.dropped_measurements(), 0);
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(matches!(aggregator.get_averages().first(), Some(&Average{ average_temperature:1.0, .. })));
+    }
+
+    #[test]
+    fn registered_sensors_are_calibrated_and_labeled() {
+        let aggregator = Aggregator::new(20);
+        aggregator.register_sensor(1, "forno", "°C", -0.5);
+        aggregator.add_measure(1, 10.0);
+        aggregator.add_measure(1, 12.0);
+        aggregator.add_measure(2, 3.0);
+        std::thread::sleep(Duration::from_millis(30));
+        let averages = aggregator.get_averages();
+        let forno = SensorInfo { name: "forno".to_string(), unit: "°C".to_string(), calibration_offset: -0.5 };
+        assert!(averages.iter().any(|a| a.sensor_id == 1 && a.average_temperature == 10.5 && a.sensor.as_ref() == Some(&forno)));
+        assert!(averages.iter().any(|a| a.sensor_id == 2 && a.average_temperature == 3.0 && a.sensor.is_none()));
+        let stats = aggregator.get_stats();
+        let s1 = stats.iter().find(|s| s.sensor_id == 1).unwrap();
+        assert_eq!(s1.min_temperature, 9.5);
+        assert_eq!(s1.sensor, Some(SensorInfo { name: "forno".to_string(), unit: "°C".to_string(), calibration_offset: -0.5 }));
+        assert!(stats.iter().find(|s| s.sensor_id == 2).unwrap().sensor.is_none());
+        assert_eq!(aggregator.sensor_info(1).unwrap().unit, "°C");
+    }
+
+    #[test]
+    fn extra_intervals_aggregate_several_base_windows() {
+        let aggregator = Aggregator::with_config(AggregatorConfig {
+            sample_time_millis: 30,
+            extra_intervals_millis: vec![90],
+            ..Default::default()
+        });
+        assert_eq!(aggregator.get_averages_for(Duration::from_millis(90)), None);
+        aggregator.add_measure(1, 1.0);
+        std::thread::sleep(Duration::from_millis(40));
+        aggregator.add_measure(1, 5.0);
+        std::thread::sleep(Duration::from_millis(65));
+        let base = aggregator.get_averages_for(Duration::from_millis(30)).unwrap();
+        assert_eq!(base, aggregator.get_averages());
+        let long = aggregator.get_averages_for(Duration::from_millis(90)).unwrap();
+        assert!(matches!(long.first(), Some(&Average{ sensor_id:1, average_temperature:3.0, .. })));
+        assert_eq!(aggregator.get_averages_for(Duration::from_millis(60)), None);
+    }
+
+    #[test]
+    fn a_zero_extra_interval_is_clamped() {
+        let aggregator = Aggregator::with_config(AggregatorConfig {
+            sample_time_millis: 20,
+            extra_intervals_millis: vec![0],
+            ..Default::default()
+        });
+        // con un intervallo nullo il thread interno resterebbe bloccato alla prima finestra
+        aggregator.add_measure(1, 2.0);
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(aggregator.get_averages_for(Duration::from_millis(1)).is_some());
+        assert!(aggregator.stop().is_empty());
+    }
+
+    #[test]
+    fn measures_from_many_threads_are_all_aggregated() {
+        let aggregator = std::sync::Arc::new(Aggregator::with_config(AggregatorConfig {
+            sample_time_millis: 10_000,
+            ingestion_shards: 4,
+            ..Default::default()
+        }));
+        let producers: Vec<_> = (0..8)
+            .map(|i| {
+                let aggregator = aggregator.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..100 {
+                        aggregator.add_measure(i % 2, i as f64);
+                    }
+                })
+            })
+            .collect();
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        let aggregator = std::sync::Arc::try_unwrap(aggregator).ok().unwrap();
+        let tail = aggregator.stop();
+        assert!(tail.iter().any(|a| a.sensor_id == 0 && a.average_temperature == 3.0));
+        assert!(tail.iter().any(|a| a.sensor_id == 1 && a.average_temperature == 4.0));
+    }
+
+    #[test]
+    fn old_history_is_downsampled_and_expired() {
+        let aggregator = Aggregator::with_config(AggregatorConfig {
+            sample_time_millis: 20,
+            history_windows: 100,
+            retention_millis: Some(400),
+            downsampling: vec![Downsample { after_millis: 100, resolution_millis: 80 }],
+            ..Default::default()
+        });
+        for i in 0..25 {
+            aggregator.add_measure(1, i as f64);
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        let all = aggregator.get_all_history();
+        // 400 ms di storico: al più 5 finestre recenti a piena risoluzione più gli intervalli fusi da 80 ms
+        assert!(all.len() < 12, "{} windows kept", all.len());
+        let history = aggregator.get_history(1);
+        assert!(history.windows(2).all(|w| w[0].reference_time < w[1].reference_time));
+        assert!(history.first().unwrap().average_temperature > 0.0);
+    }
+}
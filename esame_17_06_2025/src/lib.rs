@@ -0,0 +1,279 @@
+// Un applicativo software multithread fa accesso ai servizi di un server remoto, attraverso richieste di tipo HTTP.
+// Tali richieste devono includere un token di sicurezza che identifica l'applicativo stesso e ne autorizza l'accesso.
+// Per motivi di sicurezza, il token ha una validità limitata nel tempo (qualche minuto) e deve essere rinnovato alla sua scadenza.
+// Il token viene ottenuto attraverso una funzione (fornita esternamente e conforme al tipo TokenAcquirer) che restituisce
+// alternativamente un token e la sua data di scadenza o un messaggio di errore se non è possibile fornirlo.
+// Poiché la emissione richiede un tempo apprezzabile (da alcune centinaia di millisecondi ad alcuni secondi), si vuole
+// centralizzare la gestione del token, per evitare che più thread ne facciano richiesta in contemporanea.
+// A tale scopo deve essere implementata la struct TokenManager che si occupa di gestire il rilascio, il rinnovo e la messa a
+// disposizione del token a chi ne abbia bisogno, secondo la logica di seguito indicata.
+
+// La struct TokenManager offre i seguenti metodi:
+
+// type TokenAcquirer = dyn Fn() => Result<(String, Instant), String> + Sync
+
+// pub fn new(acquire_token: Box<TokenAcquirer> ) -> Self
+// pub fn get_token(&self) -> Result<string, string="">
+// pub fn try_get_token(&self) -> Option<string>
+
+// Al proprio interno, la struct TokenManager mantiene 3 possibili stati:
+// Empty - indica che non è ancora stato richiesto alcun token
+// Pending - indica che è in corso una richiesta di acquisizione del token
+// Valid - indica che è disponibile un token in corso di validità
+
+// Il metodo new(...) riceve il puntatore alla funzione in grado di acquisire il token. Essa opera in modalità pigra e si
+// limita a creare un'istanza della struttura con le necessarie informazioni per gestire il suo successivo comportamento.
+
+// Il metodo get_token(...) implementa il seguente comportamento:
+// Se lo stato è Empty, passa allo stato Pending e invoca la funzione per acquisire il token; se questa ritorna un risultato valido,
+// memorizza il token e la sua scadenza, porta lo stato a Valid e restituisce copia del token stesso; se, invece, questa restituisce
+// un errore, pone lo stato a Empty e restituisce l'errore ricevuto.
+// Se lo stato è Pending, attende senza consumare cicli di CPU che questo passi ad un altro valore, dopodiché si comporta di conseguenza.
+// Se lo stato è Valid e il token non risulta ancora scaduto, ne restituisce una copia; altrimenti pone lo stato ad Pending e inizia una
+// richiesta di acquisizione, come indicato sopra.
+
+// Il metodo try_get_token(...) implementa il seguente comportamento:
+// Se lo stato è Valid e il token non è scaduto, restituisce una copia del token opportunamente incapsulata in un oggetto di tipo Option.
+// In tutti gli altri casi restituisce None.
+// Si implementi tale struttura nel linguaggio Rust.
+
+use std::sync::Condvar;
+use std::sync::Mutex;
+#[cfg(test)]
+use std::{sync::Arc, thread};
+use std::time::{Duration, Instant};
+
+#[derive(PartialEq)]
+enum State {
+    Empty,
+    Pending,
+    Valid((String, Instant)),
+}
+
+struct Inner {
+    state: State,
+    last_attempt: Option<Instant>, // inizio dell'ultima invocazione dell'acquirer
+    last_error: Option<String>,    // errore restituito dall'ultima invocazione, se fallita
+}
+
+pub struct TokenManager {
+    fun: Box<TokenAcquirer>,
+    state: Mutex<Inner>,
+    cv: Condvar,
+    min_interval: Duration,
+}
+
+pub type TokenAcquirer = dyn Fn() -> Result<(String, Instant), String> + Send + Sync;
+
+impl TokenManager {
+    pub fn new(fun: Box<TokenAcquirer>) -> Self {
+        Self::with_min_interval(fun, Duration::ZERO)
+    }
+
+    // come new(), ma tra l'inizio di due invocazioni consecutive dell'acquirer (anche fallite)
+    // devono trascorrere almeno `min_interval`: durante l'intervallo chi richiede il token riceve
+    // l'ultimo errore, se l'ultimo tentativo è fallito, altrimenti attende il prossimo tentativo consentito
+    pub fn with_min_interval(fun: Box<TokenAcquirer>, min_interval: Duration) -> Self {
+        TokenManager {
+            fun: fun,
+            state: Mutex::new(Inner {
+                state: State::Empty,
+                last_attempt: None,
+                last_error: None,
+            }),
+            cv: Condvar::new(),
+            min_interval,
+        }
+    }
+
+    pub fn get_token(&self) -> Result<String, String> {
+        let mut inner = self.state.lock().unwrap();
+        loop {
+            match &inner.state {
+                State::Empty => {
+                    if let Some(last) = inner.last_attempt {
+                        let next_attempt = last + self.min_interval;
+                        let now = Instant::now();
+                        if now < next_attempt {
+                            if let Some(e) = &inner.last_error {
+                                return Err(e.clone());
+                            }
+                            inner = self.cv.wait_timeout(inner, next_attempt - now).unwrap().0;
+                            continue;
+                        }
+                    }
+                    inner.state = State::Pending;
+                    inner.last_attempt = Some(Instant::now());
+                    drop(inner);
+                    let res = (self.fun)();
+                    inner = self.state.lock().unwrap();
+                    return match res {
+                        Ok((s, i)) => {
+                            let r = s.clone();
+                            inner.state = State::Valid((s, i));
+                            inner.last_error = None;
+                            drop(inner);
+                            self.cv.notify_all();
+                            Ok(r)
+                        }
+                        Err(s) => {
+                            inner.state = State::Empty;
+                            inner.last_error = Some(s.clone());
+                            drop(inner);
+                            self.cv.notify_all();
+                            Err(s)
+                        }
+                    };
+                }
+                State::Valid((s, i)) => {
+                    let now = Instant::now();
+                    if now >= *i {
+                        inner.state = State::Empty;
+                        continue;
+                    }
+                    // token ancora valido: se ne restituisce una copia senza invocare l'acquirer
+                    return Ok(s.clone());
+                }
+                State::Pending => {
+                    inner = self.cv.wait_while(inner, |s| s.state == State::Pending).unwrap();
+                    continue;
+                }
+            }
+        }
+    }
+
+    // scarta `rejected`, rifiutato dal server prima della scadenza, e ne ottiene uno nuovo;
+    // se un altro thread l'ha già rinnovato si usa quello, così più rifiuti contemporanei provocano un solo rinnovo
+    pub fn refresh_token(&self, rejected: &str) -> Result<String, String> {
+        {
+            let mut inner = self.state.lock().unwrap();
+            if matches!(&inner.state, State::Valid((token, _)) if token == rejected) {
+                inner.state = State::Empty;
+            }
+        }
+        self.get_token()
+    }
+
+    pub fn try_get_token(&self) -> Option<String> {
+        // Se lo stato è Valid e il token non è scaduto, restituisce una copia del token opportunamente incapsulata in un oggetto di tipo Option.
+        // In tutti gli altri casi restituisce None.
+        // Si implementi tale struttura nel linguaggio Rust.
+        let inner = self.state.lock().unwrap();
+        match &inner.state {
+            State::Valid((s, i)) if *i > Instant::now() => Some(s.clone()),
+            _ => None,
+        }
+    }
+}
+
+// A supporto della validazione del codice realizzato si considerino i seguenti test (due dei quali sono forniti con la relativa
+// implementazione, i restanti sono solo indicati e devono essere opportunamente completati):
+
+#[test]
+fn a_new_manager_contains_no_token() {
+    let a: Box<TokenAcquirer> = Box::new(|| Err("failure".to_string()));
+    let manager = TokenManager::new(a);
+    assert!(manager.try_get_token().is_none());
+}
+#[test]
+fn a_failing_acquirer_always_returns_an_error() {
+    let a: Box<TokenAcquirer> = Box::new(|| Err("failure".to_string()));
+    let manager = TokenManager::new(a);
+    assert_eq!(manager.get_token(), Err("failure".to_string()));
+    assert_eq!(manager.get_token(), Err("failure".to_string()));
+}
+#[test]
+fn a_successful_acquirer_always_returns_success() {
+    let a: Box<TokenAcquirer> = Box::new(|| Ok(("abc".to_string(), Instant::now())));
+    let manager = TokenManager::new(a);
+    assert_eq!(manager.get_token(), Ok("abc".to_string()));
+}
+#[test]
+fn a_slow_acquirer_causes_other_threads_to_wait() {
+    use std::time::{Duration, Instant};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let call_count = Arc::new(AtomicUsize::new(0));
+    let call_count_clone = Arc::clone(&call_count);
+
+    // Token acquirer that simulates a delay
+    let a: Box<TokenAcquirer> = Box::new(move || {
+        call_count_clone.fetch_add(1, Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(500)); // Simulate long acquisition
+        Ok(("abc".to_string(), Instant::now() + Duration::from_secs(10)))
+    });
+
+    let manager = Arc::new(TokenManager::new(a));
+
+    let manager1 = Arc::clone(&manager);
+    let thread1 = thread::spawn(move || {
+        assert_eq!(manager1.get_token(), Ok("abc".to_string()));
+    });
+
+    // Give thread1 a bit of time to enter `Pending` state
+    thread::sleep(Duration::from_millis(100));
+
+    let manager2 = Arc::clone(&manager);
+    let thread2 = thread::spawn(move || {
+        // This call should wait for thread1 to finish acquiring
+        assert_eq!(manager2.get_token(), Ok("abc".to_string()));
+    });
+
+    thread1.join().unwrap();
+    thread2.join().unwrap();
+
+    // Only one call to the token acquirer should have happened
+    assert_eq!(call_count.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn a_rate_limited_failing_acquirer_is_not_called_again_within_the_interval() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let call_count = Arc::new(AtomicUsize::new(0));
+    let call_count_clone = Arc::clone(&call_count);
+    let a: Box<TokenAcquirer> = Box::new(move || {
+        call_count_clone.fetch_add(1, Ordering::SeqCst);
+        Err("failure".to_string())
+    });
+
+    let manager = TokenManager::with_min_interval(a, Duration::from_millis(200));
+    assert_eq!(manager.get_token(), Err("failure".to_string()));
+    // Within the interval the last error is returned without calling the acquirer
+    assert_eq!(manager.get_token(), Err("failure".to_string()));
+    assert_eq!(call_count.load(Ordering::SeqCst), 1);
+
+    thread::sleep(Duration::from_millis(210));
+    assert_eq!(manager.get_token(), Err("failure".to_string()));
+    assert_eq!(call_count.load(Ordering::SeqCst), 2);
+}
+#[test]
+fn refresh_token_replaces_only_the_rejected_token() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let call_count = Arc::new(AtomicUsize::new(0));
+    let call_count_clone = Arc::clone(&call_count);
+    let a: Box<TokenAcquirer> = Box::new(move || {
+        let n = call_count_clone.fetch_add(1, Ordering::SeqCst);
+        Ok((format!("token{}", n), Instant::now() + Duration::from_secs(10)))
+    });
+
+    let manager = TokenManager::new(a);
+    assert_eq!(manager.get_token(), Ok("token0".to_string()));
+    assert_eq!(manager.get_token(), Ok("token0".to_string()));
+    assert_eq!(manager.refresh_token("token0"), Ok("token1".to_string()));
+    // A stale rejection does not discard the token that replaced it
+    assert_eq!(manager.refresh_token("token0"), Ok("token1".to_string()));
+    assert_eq!(call_count.load(Ordering::SeqCst), 2);
+}
+#[test]
+fn a_rate_limited_manager_waits_for_the_next_allowed_attempt() {
+    // The token expires immediately, so the second call needs a new attempt
+    let a: Box<TokenAcquirer> = Box::new(|| Ok(("abc".to_string(), Instant::now())));
+    let manager = TokenManager::with_min_interval(a, Duration::from_millis(100));
+
+    let start = Instant::now();
+    assert_eq!(manager.get_token(), Ok("abc".to_string()));
+    assert_eq!(manager.get_token(), Ok("abc".to_string()));
+    assert!(start.elapsed() >= Duration::from_millis(100));
+}
@@ -1,33 +1,268 @@
+use std::collections::VecDeque;
+use std::ops::{Bound, Index, IndexMut, RangeBounds};
+use std::sync::mpsc::{channel, Receiver, Sender};
+
 #[derive(Debug, PartialEq)]
 pub enum Err {
     Full,
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BufferEvent {
+    AboveHigh, // l'occupazione ha raggiunto la soglia alta
+    BelowLow,  // l'occupazione è tornata alla soglia bassa
+}
+
+struct Watermarks {
+    high: usize,
+    low: usize,
+    above: bool, // true tra un AboveHigh e il BelowLow successivo
+    events: Sender<BufferEvent>,
+}
+
+// gli elementi stanno in una VecDeque (anch'essa un buffer circolare) riservata una volta sola con la
+// capacità richiesta: non viene mai riallocata e, a differenza di un Vec<Option<T>>, as_slices può
+// restituire direttamente gli elementi
 pub struct CircularBuffer<T> { 
-    buffer: Vec<Option<T>>,
-    head: usize,
-    tail: usize,
-    size: usize,
+    buffer: VecDeque<T>,
     capacity: usize,
+    watermarks: Option<Watermarks>,
 }
 
 impl<T> CircularBuffer<T> {
     pub fn new(capacity: usize) -> Self {
         CircularBuffer {
-            buffer: (0..capacity).map(|_| None).collect(),
+            buffer: VecDeque::with_capacity(capacity),
+            capacity,
+            watermarks: None,
+        }
+    }
+
+    pub fn set_watermarks(&mut self, high: usize, low: usize) -> Receiver<BufferEvent> {
+        // notifica AboveHigh quando l'occupazione raggiunge `high` e BelowLow quando scende a `low`
+        // (con isteresi: ogni evento è inviato una volta per attraversamento). Sostituisce le soglie precedenti
+        assert!(low < high, "low watermark must be below the high one");
+        let (tx, rx) = channel();
+        self.watermarks = Some(Watermarks { high, low, above: false, events: tx });
+        self.check_watermarks();
+        rx
+    }
+
+    fn check_watermarks(&mut self) {
+        let size = self.buffer.len();
+        let Some(w) = self.watermarks.as_mut() else {
+            return;
+        };
+        let event = if !w.above && size >= w.high {
+            w.above = true;
+            BufferEvent::AboveHigh
+        } else if w.above && size <= w.low {
+            w.above = false;
+            BufferEvent::BelowLow
+        } else {
+            return;
+        };
+        if w.events.send(event).is_err() {
+            self.watermarks = None; // nessuno ascolta più
+        }
+    }
+
+    pub fn write(&mut self, item: T) -> Result<(), Err> {
+        if self.buffer.len() == self.capacity {
+            return Err(Err::Full)
+        }
+        self.buffer.push_back(item);
+        self.check_watermarks();
+        Ok(())
+    }
+
+    pub fn read(&mut self) -> Option<T> {
+        let value = self.buffer.pop_front()?;
+        self.check_watermarks();
+        Some(value)
+    }
+
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+        self.check_watermarks();
+    }
+
+    pub fn size(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        // elemento che verrebbe restituito dalla prossima read, senza consumarlo
+        self.buffer.front()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        // ordine logico: dal più vecchio al più recente, senza consumare gli elementi
+        self.buffer.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.buffer.iter_mut()
+    }
+
+    pub fn overwrite(&mut self, item: T) -> Option<T> {
+        // restituisce l'elemento più vecchio se è stato sovrascritto
+        if self.capacity == 0 {
+            return Some(item); // non c'è posto nemmeno sovrascrivendo
+        }
+        if self.buffer.len() == self.capacity {
+            // buffer pieno
+            let evicted = self.buffer.pop_front();
+            self.buffer.push_back(item);
+            evicted
+        } else {
+            self.buffer.push_back(item);
+            self.check_watermarks();
+            None
+        }
+    }
+
+    pub fn write_slice(&mut self, items: &[T]) -> usize
+    where
+        T: Clone,
+    {
+        // scrive quanti più elementi possibile (fino allo spazio libero) e restituisce quanti ne ha scritti
+        let n = items.len().min(self.capacity - self.buffer.len());
+        self.buffer.extend(items[..n].iter().cloned());
+        if n > 0 {
+            self.check_watermarks();
+        }
+        n
+    }
+
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        // come VecDeque::as_slices: le due metà contigue in ordine logico (la seconda è vuota se il
+        // buffer è contiguo, es. dopo make_contiguous)
+        self.buffer.as_slices()
+    }
+
+    pub fn as_mut_slices(&mut self) -> (&mut [T], &mut [T]) {
+        self.buffer.as_mut_slices()
+    }
+
+    fn check_index(&self, index: usize) {
+        // posizione logica (0 = il più vecchio)
+        assert!(index < self.buffer.len(), "index {} out of bounds (size {})", index, self.buffer.len());
+    }
+
+    pub fn read_many(&mut self, n: usize) -> Vec<T> {
+        // legge fino a n elementi in ordine logico
+        let n = n.min(self.buffer.len());
+        let items = self.buffer.drain(..n).collect();
+        if n > 0 {
+            self.check_watermarks();
+        }
+        items
+    }
+
+    pub fn drain<R: RangeBounds<usize>>(&mut self, range: R) -> Drain<T> {
+        // come Vec::drain: rimuove gli elementi nell'intervallo di posizioni logiche (0 = il più vecchio) e li
+        // restituisce in ordine; quelli successivi scorrono indietro. A differenza di Vec::drain gli elementi
+        // vengono raccolti subito in un Vec (l'iteratore restituito non prende in prestito il buffer), quindi
+        // la rimozione avviene anche se l'iteratore non viene consumato. `drain(..)` svuota il buffer
+        let size = self.buffer.len();
+        // un estremo pari a usize::MAX non ha successore: l'intervallo è comunque fuori dai limiti
+        let start = match range.start_bound() {
+            Bound::Included(&s) => s,
+            Bound::Excluded(&s) => s
+                .checked_add(1)
+                .unwrap_or_else(|| panic!("drain range starting after {} out of bounds (size {})", s, size)),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&e) => e
+                .checked_add(1)
+                .unwrap_or_else(|| panic!("drain range {}..={} out of bounds (size {})", start, e, size)),
+            Bound::Excluded(&e) => e,
+            Bound::Unbounded => size,
+        };
+        assert!(start <= end && end <= size, "drain range {}..{} out of bounds (size {})", start, end, size);
+
+        let items: Vec<T> = self.buffer.drain(start..end).collect();
+        if !items.is_empty() {
+            self.check_watermarks();
+        }
+        Drain { items: items.into_iter() }
+    }
+
+    pub fn make_contiguous(&mut self) {
+        // rotazione sul posto (VecDeque::make_contiguous non alloca): dopo la chiamata
+        // as_slices restituisce tutti gli elementi nella prima metà
+        self.buffer.make_contiguous();
+    }
+}
+
+impl<T> Index<usize> for CircularBuffer<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.check_index(index);
+        &self.buffer[index]
+    }
+}
+
+impl<T> IndexMut<usize> for CircularBuffer<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        self.check_index(index);
+        &mut self.buffer[index]
+    }
+}
+
+// iteratore restituito da CircularBuffer::drain
+pub struct Drain<T> {
+    items: std::vec::IntoIter<T>,
+}
+
+impl<T> Iterator for Drain<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.items.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.items.size_hint()
+    }
+}
+
+impl<T> DoubleEndedIterator for Drain<T> {
+    fn next_back(&mut self) -> Option<T> {
+        self.items.next_back()
+    }
+}
+
+impl<T> ExactSizeIterator for Drain<T> {}
+
+// stessa API di CircularBuffer con capacità fissata a compile time: nessuna allocazione
+// sullo heap, usa solo `core` (utilizzabile anche in contesti no_std)
+pub struct ArrayCircularBuffer<T, const N: usize> {
+    buffer: [Option<T>; N],
+    head: usize,
+    tail: usize,
+    size: usize,
+}
+
+impl<T, const N: usize> ArrayCircularBuffer<T, N> {
+    pub fn new() -> Self {
+        ArrayCircularBuffer {
+            buffer: core::array::from_fn(|_| None),
             head: 0,
             tail: 0,
             size: 0,
-            capacity,
         }
     }
 
     pub fn write(&mut self, item: T) -> Result<(), Err> {
-        if self.size == self.capacity {
+        if self.size == N {
             return Err(Err::Full)
         }
         self.buffer[self.tail] = Some(item);
-        self.tail = (self.tail + 1) % self.capacity; 
+        self.tail = (self.tail + 1) % N;
         self.size += 1;
         Ok(())
     }
@@ -37,7 +272,7 @@ impl<T> CircularBuffer<T> {
             return None
         }
         let value = self.buffer[self.head].take();
-        self.head = (self.head + 1) % self.capacity;
+        self.head = (self.head + 1) % N;
         self.size -= 1;
         value
     }
@@ -55,37 +290,28 @@ impl<T> CircularBuffer<T> {
         self.size
     }
 
-    pub fn overwrite(&mut self, item: T) {
-        if self.size == self.capacity {
-            // buffer pieno
-            self.buffer[self.head] = Some(item);
-            self.head = (self.head + 1) % self.capacity;
-            self.tail = (self.tail + 1) % self.capacity;
+    pub fn overwrite(&mut self, item: T) -> Option<T> {
+        // restituisce l'elemento più vecchio se è stato sovrascritto
+        if N == 0 {
+            return Some(item); // non c'è posto nemmeno sovrascrivendo (e `% N` andrebbe in panic)
+        }
+        if self.size == N {
+            let evicted = self.buffer[self.head].replace(item);
+            self.head = (self.head + 1) % N;
+            self.tail = (self.tail + 1) % N;
+            evicted
         } else {
             self.buffer[self.tail] = Some(item);
-            self.tail = (self.tail + 1) % self.capacity;
+            self.tail = (self.tail + 1) % N;
             self.size += 1;
+            None
         }
     }
+}
 
-    pub fn make_contiguous(&mut self) {
-        if self.head == 0 || self.size == 0 {
-            return;
-        }
-
-        let mut new_buffer: Vec<Option<T>> = (0..self.capacity).map(|_| None).collect();
-        let mut new_index = 0;
-
-        let mut current = self.head;
-        for _ in 0..self.size {
-            new_buffer[new_index] = self.buffer[current].take();
-            current = (current + 1) % self.capacity;
-            new_index += 1;
-        }
-
-        self.buffer = new_buffer;
-        self.head = 0;
-        self.tail = self.size % self.capacity;
+impl<T, const N: usize> Default for ArrayCircularBuffer<T, N> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -180,4 +406,214 @@ mod tests {
         assert_eq!(buf.read(), Some(4));
         assert_eq!(buf.read(), Some(5));
     }
+
+    #[test]
+    fn peek_does_not_consume() {
+        let mut buf = CircularBuffer::new(2);
+        assert_eq!(buf.peek(), None);
+        buf.write(1).unwrap();
+        buf.write(2).unwrap();
+        assert_eq!(buf.peek(), Some(&1));
+        assert_eq!(buf.size(), 2);
+        assert_eq!(buf.read(), Some(1));
+        assert_eq!(buf.peek(), Some(&2));
+    }
+
+    #[test]
+    fn iter_follows_logical_order() {
+        let mut buf = CircularBuffer::new(3);
+        buf.write(1).unwrap();
+        buf.write(2).unwrap();
+        buf.write(3).unwrap();
+        buf.read();
+        buf.write(4).unwrap(); // tail wrap-around
+        assert_eq!(buf.iter().copied().collect::<Vec<_>>(), vec![2, 3, 4]);
+        assert_eq!(buf.size(), 3);
+
+        for item in buf.iter_mut() {
+            *item *= 10;
+        }
+        assert_eq!(buf.read(), Some(20));
+        assert_eq!(buf.read(), Some(30));
+        assert_eq!(buf.read(), Some(40));
+        assert_eq!(buf.iter().count(), 0);
+    }
+
+    #[test]
+    fn write_slice_fills_free_space() {
+        let mut buf = CircularBuffer::new(4);
+        buf.write(0).unwrap();
+        buf.read();
+        assert_eq!(buf.write_slice(&[1, 2, 3, 4, 5]), 4); // wrap-around, l'ultimo non entra
+        assert_eq!(buf.size(), 4);
+        assert_eq!(buf.write_slice(&[6]), 0);
+        assert_eq!(buf.iter().copied().collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn read_many_and_drain() {
+        let mut buf = CircularBuffer::new(3);
+        buf.write_slice(&[1, 2, 3]);
+        assert_eq!(buf.read_many(2), vec![1, 2]);
+        buf.write_slice(&[4, 5]);
+        assert_eq!(buf.read_many(10), vec![3, 4, 5]);
+        assert!(buf.read_many(1).is_empty());
+        buf.write_slice(&[6, 7]);
+        assert_eq!(buf.drain(..).collect::<Vec<_>>(), vec![6, 7]);
+        assert_eq!(buf.size(), 0);
+        assert_eq!(buf.read(), None);
+    }
+
+    #[test]
+    fn index_uses_logical_offsets() {
+        let mut buf = CircularBuffer::new(3);
+        buf.write_slice(&[1, 2, 3]);
+        buf.read();
+        buf.write(4).unwrap();
+        assert_eq!((buf[0], buf[1], buf[2]), (2, 3, 4));
+        buf[2] = 40;
+        assert_eq!(buf.drain(..).collect::<Vec<_>>(), vec![2, 3, 40]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn index_out_of_bounds_panics() {
+        let mut buf = CircularBuffer::new(3);
+        buf.write(1).unwrap();
+        let _ = buf[1];
+    }
+
+    #[test]
+    fn as_slices_return_the_elements_in_order() {
+        let mut buf = CircularBuffer::new(4);
+        buf.write_slice(&[1, 2, 3]);
+        assert_eq!(buf.as_slices(), (&[1, 2, 3][..], &[][..]));
+        buf.read_many(2);
+        buf.write_slice(&[4, 5]);
+        // il punto di separazione dipende da dove si trova il wrap-around nella memoria
+        let (front, back) = buf.as_slices();
+        assert_eq!([front, back].concat(), vec![3, 4, 5]);
+        let (front, back) = buf.as_mut_slices();
+        for item in front.iter_mut().chain(back.iter_mut()) {
+            *item *= 10;
+        }
+        buf.make_contiguous();
+        assert_eq!(buf.as_slices(), (&[30, 40, 50][..], &[][..]));
+
+        let empty: CircularBuffer<i32> = CircularBuffer::new(2);
+        assert_eq!(empty.as_slices(), (&[][..], &[][..]));
+    }
+
+    #[test]
+    fn make_contiguous_keeps_capacity_and_order() {
+        let mut buf = CircularBuffer::new(5);
+        buf.write_slice(&[1, 2, 3, 4]);
+        buf.read_many(3);
+        buf.write_slice(&[5, 6, 7]); // wrap-around
+        buf.make_contiguous();
+        assert_eq!(buf.as_slices().1.len(), 0);
+        assert_eq!(buf.iter().copied().collect::<Vec<_>>(), vec![4, 5, 6, 7]);
+        buf.write(8).unwrap(); // tail corretto dopo la rotazione
+        assert!(buf.write(9).is_err());
+        assert_eq!(buf.drain(..).collect::<Vec<_>>(), vec![4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn overwrite_returns_the_evicted_element() {
+        let mut buf = CircularBuffer::new(2);
+        assert_eq!(buf.overwrite(1), None);
+        assert_eq!(buf.overwrite(2), None);
+        assert_eq!(buf.overwrite(3), Some(1));
+        assert_eq!(buf.overwrite(4), Some(2));
+        assert_eq!(buf.drain(..).collect::<Vec<_>>(), vec![3, 4]);
+    }
+
+    #[test]
+    fn array_buffer_mirrors_the_vec_api() {
+        let mut buf: ArrayCircularBuffer<i32, 2> = ArrayCircularBuffer::new();
+        assert_eq!(buf.read(), None);
+        buf.write(1).unwrap();
+        buf.write(2).unwrap();
+        assert_eq!(buf.write(3), Err(Err::Full));
+        assert_eq!(buf.read(), Some(1));
+        buf.write(3).unwrap(); // wrap-around
+        assert_eq!(buf.overwrite(4), Some(2));
+        assert_eq!(buf.size(), 2);
+        assert_eq!(buf.read(), Some(3));
+        assert_eq!(buf.read(), Some(4));
+        buf.write(5).unwrap();
+        buf.clear();
+        assert_eq!(buf.size(), 0);
+        assert_eq!(buf.read(), None);
+    }
+
+    #[test]
+    fn zero_capacity_array_buffer_rejects_every_item() {
+        let mut buf: ArrayCircularBuffer<i32, 0> = ArrayCircularBuffer::new();
+        assert_eq!(buf.write(1), Err(Err::Full));
+        assert_eq!(buf.overwrite(2), Some(2));
+        assert_eq!(buf.size(), 0);
+        assert_eq!(buf.read(), None);
+    }
+
+    #[test]
+    fn watermarks_notify_with_hysteresis() {
+        let mut buf = CircularBuffer::new(10);
+        let events = buf.set_watermarks(8, 2);
+        buf.write_slice(&[0; 7]);
+        assert!(events.try_recv().is_err());
+        buf.write(7).unwrap();
+        buf.write(8).unwrap();
+        buf.read_many(5);
+        assert_eq!(events.try_iter().collect::<Vec<_>>(), vec![BufferEvent::AboveHigh]);
+        buf.read();
+        buf.read();
+        buf.overwrite(1);
+        buf.clear();
+        assert_eq!(events.try_iter().collect::<Vec<_>>(), vec![BufferEvent::BelowLow]);
+        buf.write_slice(&[0; 8]);
+        assert_eq!(events.try_recv(), Ok(BufferEvent::AboveHigh));
+    }
+
+    #[test]
+    fn drain_range_removes_the_middle() {
+        let mut buf = CircularBuffer::new(6);
+        buf.write_slice(&[0, 0, 0]);
+        buf.read_many(3);
+        buf.write_slice(&[1, 2, 3, 4, 5, 6]); // wrap-around
+        let drained: Vec<i32> = buf.drain(1..4).collect();
+        assert_eq!(drained, vec![2, 3, 4]);
+        assert_eq!(buf.iter().copied().collect::<Vec<_>>(), vec![1, 5, 6]);
+        buf.write_slice(&[7, 8, 9]); // tail corretto dopo lo scorrimento
+        assert!(buf.write(10).is_err());
+        assert_eq!(buf.drain(4..=5).rev().collect::<Vec<_>>(), vec![9, 8]);
+        assert_eq!(buf.drain(..2).len(), 2);
+        assert_eq!(buf.drain(..).map(|x| x * 10).collect::<Vec<_>>(), vec![60, 70]);
+        assert_eq!(buf.size(), 0);
+    }
+
+    #[test]
+    fn dropped_drain_still_removes_the_range() {
+        let mut buf = CircularBuffer::new(3);
+        buf.write_slice(&[1, 2, 3]);
+        drop(buf.drain(1..));
+        assert_eq!(buf.read(), Some(1));
+        assert_eq!(buf.read(), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn drain_out_of_bounds_panics() {
+        let mut buf = CircularBuffer::new(3);
+        buf.write(1).unwrap();
+        buf.drain(0..2);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn drain_up_to_usize_max_panics_instead_of_overflowing() {
+        let mut buf = CircularBuffer::new(3);
+        buf.write(1).unwrap();
+        buf.drain(..=usize::MAX);
+    }
 }
@@ -1,27 +1,82 @@
 
-use std::time::SystemTime;
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::fs;
 use std::io;
-use std::path::Path;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct File {
     name: String,
     modified: SystemTime,
     content: Vec<u8>,
+    truncated: bool, // content holds only the first bytes of the file on disk (see Filesystem::from)
+    dirty: bool,     // content changed (or new) since it was loaded or last synced, see Filesystem::sync_to
+    metadata: Metadata,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct Dir {
     name: String,
     modified: SystemTime,
     children: Vec<Node>,
+    metadata: Metadata,
 }
 
-#[derive(Debug)]
+// permissions of a node: readonly nodes can't be written or deleted
+// (mode uses the unix bits, e.g. 0o644, readonly is kept in sync with its write bits by chmod)
+#[derive(Debug, Clone, PartialEq)]
+struct Metadata {
+    readonly: bool,
+    owner: String,
+    mode: u32,
+}
+
+impl Metadata {
+    fn file() -> Self {
+        Metadata { readonly: false, owner: String::new(), mode: 0o644 }
+    }
+
+    fn dir() -> Self {
+        Metadata { readonly: false, owner: String::new(), mode: 0o755 }
+    }
+
+    fn symlink() -> Self {
+        Metadata { readonly: false, owner: String::new(), mode: 0o777 }
+    }
+
+    // permissions read from disk, the owner is the numeric uid on unix
+    fn from_disk(metadata: &fs::Metadata) -> Self {
+        let readonly = metadata.permissions().readonly();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            Metadata { readonly, owner: metadata.uid().to_string(), mode: metadata.mode() & 0o7777 }
+        }
+        #[cfg(not(unix))]
+        {
+            let default = if metadata.is_dir() { Metadata::dir() } else { Metadata::file() };
+            Metadata { readonly, ..default }
+        }
+    }
+}
+
+// symbolic link: target is a path in the filesystem, absolute or relative to the dir of the link,
+// and it's not required to exist
+#[derive(Debug, Clone)]
+struct Symlink {
+    name: String,
+    modified: SystemTime,
+    target: String,
+    metadata: Metadata,
+}
+
+#[derive(Debug, Clone)]
 enum Node {
     File(File),
     Dir(Dir),
+    Symlink(Symlink),
 }
 
 // RISPOSTA DI TEORIA
@@ -36,9 +91,234 @@ enum FSError {
     Duplicate,
     DirNotEmpty,
     PermissionDenied,
+    TooManyLinks, // more than MAX_LINK_HOPS symlinks followed in a path, usually a cycle
     GenericError(String),
 }
 
+// names of the nodes along a path, after resolving ".", ".." and empty parts
+fn path_components(path: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            name => parts.push(name),
+        }
+    }
+    parts
+}
+
+// set the modification time of a node and of all its subtree
+fn set_modified(node: &mut Node, time: SystemTime) {
+    match node {
+        Node::File(file) => file.modified = time,
+        Node::Dir(dir) => {
+            dir.modified = time;
+            for child in dir.children.iter_mut() {
+                set_modified(child, time);
+            }
+        }
+        Node::Symlink(link) => link.modified = time,
+    }
+}
+
+// set the dirty flag of a file, or of all the files in the subtree of a dir
+fn set_dirty(node: &mut Node, dirty: bool) {
+    match node {
+        Node::File(file) => file.dirty = dirty,
+        Node::Dir(dir) => {
+            for child in dir.children.iter_mut() {
+                set_dirty(child, dirty);
+            }
+        }
+        Node::Symlink(_) => {}
+    }
+}
+
+fn metadata(node: &Node) -> &Metadata {
+    match node {
+        Node::File(file) => &file.metadata,
+        Node::Dir(dir) => &dir.metadata,
+        Node::Symlink(link) => &link.metadata,
+    }
+}
+
+fn metadata_mut(node: &mut Node) -> &mut Metadata {
+    match node {
+        Node::File(file) => &mut file.metadata,
+        Node::Dir(dir) => &mut dir.metadata,
+        Node::Symlink(link) => &mut link.metadata,
+    }
+}
+
+// true if the node or any node of its subtree is readonly
+fn has_readonly(node: &Node) -> bool {
+    metadata(node).readonly
+        || match node {
+            Node::Dir(dir) => dir.children.iter().any(has_readonly),
+            _ => false,
+        }
+}
+
+fn node_name(node: &Node) -> &str {
+    match node {
+        Node::File(file) => &file.name,
+        Node::Dir(dir) => &dir.name,
+        Node::Symlink(link) => &link.name,
+    }
+}
+
+fn set_name(node: &mut Node, name: &str) {
+    match node {
+        Node::File(file) => file.name = name.to_string(),
+        Node::Dir(dir) => dir.name = name.to_string(),
+        Node::Symlink(link) => link.name = name.to_string(),
+    }
+}
+
+// path of the node reached following the given names from the root
+fn join_path(parts: &[String]) -> String {
+    format!("/{}", parts.join("/"))
+}
+
+// check a single "type:pattern" constraint, unknown constraints never match
+fn matches_query(node: &Node, path: &str, q: &str) -> bool {
+    match q.split_once(':') {
+        Some(("type", "dir")) => matches!(node, Node::Dir(_)),
+        Some(("type", "file")) => matches!(node, Node::File(_)),
+        Some(("type", "link")) => matches!(node, Node::Symlink(_)),
+        Some(("name", value)) => node_name(node) == value,
+        Some(("partname", value)) => node_name(node).contains(value),
+        // without any '/' the pattern is matched against the name, at any depth
+        Some(("glob", pattern)) if !pattern.contains('/') => glob_match_name(pattern.as_bytes(), node_name(node).as_bytes()),
+        Some(("glob", pattern)) => {
+            let pattern: Vec<&str> = pattern.split('/').filter(|s| !s.is_empty()).collect();
+            glob_match_path(&pattern, &path_components(path))
+        }
+        _ => false,
+    }
+}
+
+// glob on a single name: '*' matches any sequence of characters, '?' exactly one
+fn glob_match_name(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|skip| glob_match_name(rest, &name[skip..])),
+        Some((b'?', rest)) => !name.is_empty() && glob_match_name(rest, char_tail(name)),
+        Some((c, rest)) => name.first() == Some(c) && glob_match_name(rest, &name[1..]),
+    }
+}
+
+// the name without its first (utf-8) character, so that '?' matches a whole character
+fn char_tail(name: &[u8]) -> &[u8] {
+    let len = name.iter().skip(1).take_while(|b| (**b & 0xC0) == 0x80).count() + 1;
+    &name[len..]
+}
+
+// glob on a whole path, one component at a time: '**' matches any number of components (also none)
+fn glob_match_path(pattern: &[&str], parts: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => parts.is_empty(),
+        Some((&"**", rest)) => (0..=parts.len()).any(|skip| glob_match_path(rest, &parts[skip..])),
+        Some((first, rest)) => match parts.split_first() {
+            Some((part, others)) => glob_match_name(first.as_bytes(), part.as_bytes()) && glob_match_path(rest, others),
+            None => false,
+        },
+    }
+}
+
+// child of a dir with the given name
+fn parent_child<'a>(node: &'a Node, name: &str) -> Result<&'a Node, FSError> {
+    match node {
+        Node::Dir(dir) => dir.children.iter().find(|child| node_name(child) == name).ok_or(FSError::NotFound),
+        _ => Err(FSError::NotADir),
+    }
+}
+
+fn content_size(node: &Node) -> usize {
+    match node {
+        Node::File(file) => file.content.len(),
+        Node::Dir(dir) => dir.children.iter().map(content_size).sum(),
+        Node::Symlink(_) => 0,
+    }
+}
+
+fn node_modified(node: &Node) -> SystemTime {
+    match node {
+        Node::File(file) => file.modified,
+        Node::Dir(dir) => dir.modified,
+        Node::Symlink(link) => link.modified,
+    }
+}
+
+// "YYYY-MM-DD HH:MM" in UTC (no date crate in this project: days to civil date as in
+// http://howardhinnant.github.io/date_algorithms.html), times before 1970 are shown as the epoch
+fn format_time(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rem) = (secs / 86400, secs % 86400);
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, rem / 3600, rem % 3600 / 60)
+}
+
+// extra columns shown by render_tree_with, before the name of each node like tree -s -D
+#[derive(Debug, Clone, Copy, Default)]
+struct TreeOptions {
+    sizes: bool,      // content size, the total of the subtree for dirs (as du)
+    timestamps: bool, // modification time
+}
+
+#[derive(Debug, PartialEq)]
+struct FsStats {
+    files: usize,
+    dirs: usize,
+    links: usize,
+    max_depth: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Traversal {
+    DepthFirst,
+    BreadthFirst,
+}
+
+// the nodes still to visit are kept in a deque: depth-first takes them from the back (a stack),
+// breadth-first from the front (a queue)
+struct FsIter<'a> {
+    pending: VecDeque<(String, &'a Node)>,
+    order: Traversal,
+}
+
+impl<'a> Iterator for FsIter<'a> {
+    type Item = (String, &'a Node);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, node) = match self.order {
+            Traversal::DepthFirst => self.pending.pop_back()?,
+            Traversal::BreadthFirst => self.pending.pop_front()?,
+        };
+        if let Node::Dir(dir) = node {
+            let prefix = path.trim_end_matches('/');
+            let children = dir.children.iter().map(|child| (format!("{}/{}", prefix, node_name(child)), child));
+            match self.order {
+                // reversed, so that the first child is the next one popped
+                Traversal::DepthFirst => self.pending.extend(children.rev()),
+                Traversal::BreadthFirst => self.pending.extend(children),
+            }
+        }
+        Some((path, node))
+    }
+}
+
 // define lifetimes
 struct MatchResult<'a> {
     q: &'a str, // matched query string
@@ -46,8 +326,43 @@ struct MatchResult<'a> {
     node: &'a Node, // matched node
 }
 
+// where the tree comes from: only a filesystem loaded from disk can be written back with sync
+#[derive(Debug)]
+enum Mode {
+    InMemory,
+    FromDisk(PathBuf),
+}
+
+// change notified to the subscribers of a filesystem, with the real paths of the nodes (symlinks resolved)
+#[derive(Debug, Clone, PartialEq)]
+enum FsEvent {
+    Created(String),
+    Modified(String),
+    Deleted(String),
+    Moved { from: String, to: String },
+}
+
 struct Filesystem {
     root: Node,
+    mode: Mode,
+    subscribers: Vec<Box<dyn Fn(FsEvent)>>,
+}
+
+// only the first bytes of each file are loaded by Filesystem::from
+const CONTENT_PREVIEW: u64 = 1024;
+// directories deeper than this are loaded without their children
+const MAX_DEPTH: usize = 32;
+// symlinks followed while resolving a single path (the same limit as Linux)
+const MAX_LINK_HOPS: usize = 40;
+
+// same mapping used by mkdir for the errors returned by std::fs
+fn io_error(e: io::Error) -> FSError {
+    match e.kind() {
+        io::ErrorKind::AlreadyExists => FSError::Duplicate,
+        io::ErrorKind::PermissionDenied => FSError::PermissionDenied,
+        io::ErrorKind::NotFound => FSError::NotFound,
+        _ => FSError::GenericError(format!("IO Error: {}", e)),
+    }
 }
 
 impl Filesystem {
@@ -58,215 +373,696 @@ impl Filesystem {
             name: "".to_string(),
             modified: SystemTime::now(),
             children: Vec::new(),
+            metadata: Metadata::dir(),
         };
         let root = Node::Dir(dir);
-        Filesystem { root }
+        Filesystem { root, mode: Mode::InMemory, subscribers: Vec::new() }
     }
 
     // create a new filesystem reading from disk all the structure under the given path
+    // the tree is kept in memory: changes are written to disk only by sync
     // in the file content just write the firt 1k bytes of the file
     // return the root node of the filesystem
     // (implement this function at the end, after all the other methods, the only purpose is to take a look std::fs functions, use std::fs:read_dir)
-    // pub fn from(path: &str) -> Self {
-    //     unimplemented!()
-    // }
+    // possible errors: NotFound, NotADir, PermissionDenied (only for the root path, unreadable entries below it are skipped)
+    pub fn from(path: &str) -> Result<Self, FSError> {
+        Self::from_with_depth(path, MAX_DEPTH)
+    }
 
+    // same as from, but directories deeper than max_depth are loaded empty
+    // (max_depth = 1 loads only the entries of the given path)
+    pub fn from_with_depth(path: &str, max_depth: usize) -> Result<Self, FSError> {
+        let root_path = Path::new(path);
+        let metadata = fs::metadata(root_path).map_err(io_error)?;
+        if !metadata.is_dir() {
+            return Err(FSError::NotADir);
+        }
 
-    pub fn navigate_filesystem_mut(&mut self, path: &str) -> Result<&mut Node, FSError> {
-        // Navigate through the filesystem structure
-        let mut current_node = &mut self.root;
-        
-        for part in path.split('/').filter(|s| !s.is_empty()) {
-            // Check if current node is a directory
-            match current_node {
-                Node::Dir(ref mut dir) => {
-                    // Find the child with the matching name
-                    let found = dir.children.iter_mut().find(|child| {
-                        match child {
-                            Node::Dir(child_dir) => child_dir.name == part,
-                            Node::File(child_file) => child_file.name == part,
+        let canonical = fs::canonicalize(root_path).map_err(io_error)?;
+        let dir = Dir {
+            name: "".to_string(),
+            modified: metadata.modified().unwrap_or_else(|_| SystemTime::now()),
+            children: Self::read_children(root_path, 1, max_depth),
+            metadata: Metadata::from_disk(&metadata),
+        };
+        Ok(Filesystem { root: Node::Dir(dir), mode: Mode::FromDisk(canonical), subscribers: Vec::new() })
+    }
+
+    fn read_children(path: &Path, depth: usize, max_depth: usize) -> Vec<Node> {
+        if depth > max_depth {
+            return Vec::new();
+        }
+        let Ok(entries) = fs::read_dir(path) else {
+            return Vec::new();
+        };
+
+        let mut children = Vec::new();
+        for entry in entries.flatten() {
+            let entry_path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // symlink_metadata doesn't follow symlinks: a link is loaded as a link, even if broken,
+            // so the tree can't loop and sync writes it back as a link
+            let Ok(metadata) = fs::symlink_metadata(&entry_path) else {
+                continue; // entry removed while reading
+            };
+            let modified = metadata.modified().unwrap_or_else(|_| SystemTime::now());
+            let permissions = Metadata::from_disk(&metadata);
+
+            if metadata.file_type().is_symlink() {
+                let Ok(target) = fs::read_link(&entry_path) else {
+                    continue;
+                };
+                let target = target.to_string_lossy().into_owned();
+                children.push(Node::Symlink(Symlink { name, modified, target, metadata: permissions }));
+            } else if metadata.is_dir() {
+                let dir_children = Self::read_children(&entry_path, depth + 1, max_depth);
+                children.push(Node::Dir(Dir { name, modified, children: dir_children, metadata: permissions }));
+            } else {
+                let mut content = Vec::new();
+                if let Ok(file) = fs::File::open(&entry_path) {
+                    let _ = file.take(CONTENT_PREVIEW).read_to_end(&mut content);
+                }
+                let truncated = metadata.len() > CONTENT_PREVIEW;
+                let file = File { name, modified, content, truncated, dirty: false, metadata: permissions };
+                children.push(Node::File(file));
+            }
+        }
+        // read_dir doesn't guarantee any order
+        children.sort_by(|a, b| node_name(a).cmp(node_name(b)));
+        children
+    }
+
+
+    // index of the child with the given name, shared by the two navigations
+    fn child_index(node: &Node, part: &str) -> Result<usize, FSError> {
+        match node {
+            Node::Dir(dir) => dir.children.iter().position(|child| node_name(child) == part).ok_or(FSError::NotFound),
+            _ => Err(FSError::NotADir),
+        }
+    }
+
+    // names of the real nodes along a path, from the root: ".", ".." and the symlinks met along the way
+    // are resolved (the last component only if follow_last, to work on the link itself otherwise)
+    // possible errors: NotFound, NotADir, TooManyLinks
+    fn resolve(&self, path: &str, follow_last: bool) -> Result<Vec<String>, FSError> {
+        let mut resolved: Vec<String> = Vec::new();
+        let mut pending: VecDeque<String> = path.split('/').map(str::to_string).collect();
+        let mut hops = 0;
+
+        while let Some(part) = pending.pop_front() {
+            match part.as_str() {
+                "" | "." => {}
+                ".." => {
+                    resolved.pop();
+                }
+                name => {
+                    let parent = self.physical(&resolved)?;
+                    let is_last = pending.iter().all(|p| p.is_empty() || p == ".");
+                    match &parent_child(parent, name)? {
+                        Node::Symlink(link) if follow_last || !is_last => {
+                            hops += 1;
+                            if hops > MAX_LINK_HOPS {
+                                return Err(FSError::TooManyLinks);
+                            }
+                            // the target replaces the link in the rest of the path
+                            if link.target.starts_with('/') {
+                                resolved.clear();
+                            }
+                            for target_part in link.target.split('/').rev() {
+                                pending.push_front(target_part.to_string());
+                            }
                         }
-                    });
-                    
-                    match found {
-                        Some(node) => current_node = node,
-                        None => return Err(FSError::NotFound),
+                        _ => resolved.push(name.to_string()),
                     }
-                },
-                Node::File(_) => return Err(FSError::NotADir),
+                }
             }
         }
+        Ok(resolved)
+    }
 
-        return Ok(current_node)
+    // node reached from the root following exactly the given names (no symlink is resolved)
+    fn physical(&self, parts: &[String]) -> Result<&Node, FSError> {
+        let mut current_node = &self.root;
+        for part in parts {
+            current_node = parent_child(current_node, part)?;
+        }
+        Ok(current_node)
+    }
+
+    fn physical_mut(&mut self, parts: &[String]) -> Result<&mut Node, FSError> {
+        let mut current_node = &mut self.root;
+        for part in parts {
+            // Find the child with the matching name (the current node must be a directory)
+            let index = Self::child_index(current_node, part)?;
+            if let Node::Dir(dir) = current_node {
+                current_node = &mut dir.children[index];
+            }
+        }
+        Ok(current_node)
+    }
+
+    // the symlinks in the path are followed, the last one too
+    pub fn navigate_filesystem(&self, path: &str) -> Result<&Node, FSError> {
+        self.physical(&self.resolve(path, true)?)
+    }
+
+    pub fn navigate_filesystem_mut(&mut self, path: &str) -> Result<&mut Node, FSError> {
+        // Navigate through the filesystem structure
+        let parts = self.resolve(path, true)?;
+        self.physical_mut(&parts)
     }
 
     // create a new directory in the filesystem under the given path
     // return a reference the created dir
+    // only the in-memory tree is changed, use sync to write it to disk
     // possible errors: NotFound, path NotADir, Duplicate
     pub fn mkdir(&mut self, path: &str, name: &str) -> Result<&mut Dir, FSError> {
-        let new_path = format!("{}/{}", path, name);
-        match fs::create_dir_all(&new_path) {
-            Ok(_) => {
-                // Navigate through the filesystem structure
-                let mut current_node = self.navigate_filesystem_mut(path)?;
-                
-                // Now current_node should point to the parent directory
-                // Check if it's actually a directory and add the new directory
-                match current_node {
-                    Node::Dir(ref mut parent_dir) => {
-                        // Check if directory already exists
-                        let already_exists = parent_dir.children.iter().any(|child| {
-                            match child {
-                                Node::Dir(child_dir) => child_dir.name == name,
-                                _ => false,
-                            }
-                        });
-                        
-                        if already_exists {
-                            return Err(FSError::Duplicate);
-                        }
-                        
-                        // Create new directory
-                        let new_dir = Dir {
-                            name: name.to_string(),
-                            modified: SystemTime::now(),
-                            children: Vec::new(),
-                        };
-                        
-                        parent_dir.children.push(Node::Dir(new_dir));
-                        
-                        // Return reference to the newly created directory
-                        if let Some(Node::Dir(ref mut created_dir)) = parent_dir.children.last_mut() {
-                            println!("Directory created successfully!");
-                            Ok(created_dir)
-                        } else {
-                            Err(FSError::GenericError("Failed to create directory".to_string()))
-                        }
-                    },
-                    Node::File(_) => Err(FSError::NotADir),
-                }
-            },
-            Err(e) => match e.kind() {
-                std::io::ErrorKind::AlreadyExists => {
-                    println!("Directory already exists.");
-                    Err(FSError::Duplicate)
-                },
-                std::io::ErrorKind::PermissionDenied => {
-                    println!("Permission denied.");
-                    Err(FSError::PermissionDenied)
-                },
-                std::io::ErrorKind::NotFound => {
-                    println!("Path not found.");
-                    Err(FSError::NotFound)
-                },
-                _ => {
-                    println!("An error occurred: {:?}", e);
-                    Err(FSError::GenericError(format!("IO Error: {}", e)))
-                }
-            }
+        // Navigate to the parent directory
+        let mut parts = self.resolve(path, true)?;
+        let parent_dir = Self::as_dir_mut(self.physical_mut(&parts)?)?;
+
+        // A file or a directory with the same name already exists
+        if parent_dir.children.iter().any(|child| node_name(child) == name) {
+            return Err(FSError::Duplicate);
+        }
+
+        let new_dir = Dir {
+            name: name.to_string(),
+            modified: SystemTime::now(),
+            children: Vec::new(),
+            metadata: Metadata::dir(),
+        };
+        parent_dir.children.push(Node::Dir(new_dir));
+        parts.push(name.to_string());
+        self.emit(FsEvent::Created(join_path(&parts)));
+
+        // Return reference to the newly created directory
+        match self.physical_mut(&parts) {
+            Ok(Node::Dir(created_dir)) => Ok(created_dir),
+            _ => Err(FSError::GenericError("Failed to create directory".to_string())),
         }
     }
 
     // possible errors: NotFound, path is NotADir, Duplicate
     pub fn create_file(&mut self, path: &str, name: &str) -> Result<&mut File, FSError> {
-        let file_path = format!("{}/{}", path, name);
-        let path_obj = Path::new(path);
-        
-        if path_obj.is_dir() {
-            match fs::metadata(&file_path) {
-                Ok(_) => {
-                    println!("File already exists.");
-                    Err(FSError::Duplicate)
-                },
-                Err(_) => {
-                    // Navigate to the parent directory 
-                    let parent_node = self.navigate_filesystem_mut(path)?;
-
-                    // Create new file
-                    let newfile = File {
-                        name: name.to_string(),
-                        modified: SystemTime::now(),
-                        content: Vec::new(),
-                    };
-
-                    // parent_node should be a directory, so we need to match on it
-                    match parent_node {
-                        Node::Dir(ref mut parent_dir) => {
-                            parent_dir.children.push(Node::File(newfile));
-                            
-                            // Return reference to the newly created file
-                            if let Some(Node::File(ref mut created_file)) = parent_dir.children.last_mut() {
-                                println!("File created successfully!");
-                                Ok(created_file)
-                            } else {
-                                Err(FSError::GenericError("Failed to create file".to_string()))
-                            }
-                        },
-                        Node::File(_) => {
-                            Err(FSError::NotADir)
-                        }
-                    }
-                }
+        // Navigate to the parent directory
+        let mut parts = self.resolve(path, true)?;
+        let parent_dir = Self::as_dir_mut(self.physical_mut(&parts)?)?;
+
+        if parent_dir.children.iter().any(|child| node_name(child) == name) {
+            return Err(FSError::Duplicate);
+        }
+
+        let newfile = File {
+            name: name.to_string(),
+            modified: SystemTime::now(),
+            content: Vec::new(),
+            truncated: false,
+            dirty: true,
+            metadata: Metadata::file(),
+        };
+        parent_dir.children.push(Node::File(newfile));
+        parts.push(name.to_string());
+        self.emit(FsEvent::Created(join_path(&parts)));
+
+        // Return reference to the newly created file
+        match self.physical_mut(&parts) {
+            Ok(Node::File(created_file)) => Ok(created_file),
+            _ => Err(FSError::GenericError("Failed to create file".to_string())),
+        }
+    }
+
+    // register a callback called after every successful change of the tree:
+    // Created by mkdir, create_file, ln and cp, Modified by touch, write_file and append_file,
+    // Deleted by delete and delete_recursive, Moved by mv and rename
+    // (changes of the metadata and sync don't send events)
+    pub fn subscribe(&mut self, f: impl Fn(FsEvent) + 'static) {
+        self.subscribers.push(Box::new(f));
+    }
+
+    fn emit(&self, event: FsEvent) {
+        for subscriber in &self.subscribers {
+            subscriber(event.clone());
+        }
+    }
+
+    // real path of a node for the events (lexical if it can't be resolved)
+    fn real_path(&self, path: &str, follow_last: bool) -> String {
+        match self.resolve(path, follow_last) {
+            Ok(parts) => join_path(&parts),
+            Err(_) => self.canonicalize(path),
+        }
+    }
+
+    fn as_dir_mut(node: &mut Node) -> Result<&mut Dir, FSError> {
+        match node {
+            Node::Dir(dir) => Ok(dir),
+            _ => Err(FSError::NotADir),
+        }
+    }
+
+    // create a symlink at the given path (parent dir and name of the link) pointing to target,
+    // like ln -s the target is not required to exist
+    // possible errors: NotFound, NotADir, Duplicate, GenericError (empty target)
+    pub fn ln(&mut self, path: &str, target: &str) -> Result<(), FSError> {
+        if target.is_empty() {
+            return Err(FSError::GenericError("Empty symlink target".to_string()));
+        }
+        let (parent, name) = self.split_parent(path)?;
+        let parent_dir = Self::as_dir_mut(self.physical_mut(&parent)?)?;
+        if parent_dir.children.iter().any(|child| node_name(child) == name) {
+            return Err(FSError::Duplicate);
+        }
+        parent_dir.children.push(Node::Symlink(Symlink {
+            name,
+            modified: SystemTime::now(),
+            target: target.to_string(),
+            metadata: Metadata::symlink(),
+        }));
+        self.emit(FsEvent::Created(self.real_path(path, false)));
+        Ok(())
+    }
+
+    // write the in-memory tree back to the directory it was loaded from (see from)
+    // possible errors: GenericError for an in-memory filesystem, the ones of sync_to
+    pub fn sync(&mut self) -> Result<(), FSError> {
+        match &self.mode {
+            Mode::FromDisk(path) => {
+                let path = path.to_string_lossy().into_owned();
+                self.sync_to(&path)
             }
-        } else {
-            println!("Path is not a directory.");
-            Err(FSError::NotADir)
+            Mode::InMemory => Err(FSError::GenericError("In-memory filesystem: use sync_to".to_string())),
         }
     }
 
-    // updated modification time of the file or the dir
+    // create on disk, under the given path, all the dirs and files of the tree, writing the file contents
+    // only the files changed since they were loaded or last synced (or missing on disk) are written,
+    // so unchanged files (maybe readonly, like the objects of a git repo) are left as they are;
+    // files loaded by from with only the first bytes of their content are never overwritten,
+    // entries on disk which are not in the tree are left untouched
+    // possible errors: NotFound, PermissionDenied, GenericError (from std::fs)
+    pub fn sync_to(&mut self, path: &str) -> Result<(), FSError> {
+        let base = Path::new(path);
+        let mut result = Ok(());
+        self.walk(|node_path, node| {
+            if result.is_err() {
+                return;
+            }
+            let target = base.join(node_path.trim_start_matches('/'));
+            result = match node {
+                Node::Dir(_) => fs::create_dir_all(&target),
+                Node::File(file) if (file.truncated || !file.dirty) && target.exists() => Ok(()),
+                Node::File(file) => fs::write(&target, &file.content),
+                Node::Symlink(link) => Self::sync_link(base, &target, &link.target),
+            }
+            .map_err(io_error);
+        });
+        // after an error the files not written yet stay dirty for the next sync
+        if result.is_ok() {
+            set_dirty(&mut self.root, false);
+        }
+        result
+    }
+
+    // absolute targets are inside the synced tree, so they are made relative to base
+    #[cfg(unix)]
+    fn sync_link(base: &Path, link: &Path, target: &str) -> io::Result<()> {
+        if fs::symlink_metadata(link).is_ok() {
+            return Ok(());
+        }
+        let target = match target.strip_prefix('/') {
+            Some(inside) => base.join(inside),
+            None => PathBuf::from(target),
+        };
+        std::os::unix::fs::symlink(target, link)
+    }
+
+    #[cfg(not(unix))]
+    fn sync_link(_base: &Path, _link: &Path, _target: &str) -> io::Result<()> {
+        Ok(()) // symlinks need special privileges on windows, they are not synced
+    }
+
+    // updated modification time of the file or the dir (a symlink is followed)
     // possible errors: NotFound
     pub fn touch(&mut self, path: &str) -> Result<(), FSError> {
         // Navigate to the node 
         let node = self.navigate_filesystem_mut(path)?;
 
         match node {
-            Node::File(ref mut file) => {
-                file.modified = SystemTime::now();
-                Ok(())
-            },
-            Node::Dir(ref mut dir) => {
-                dir.modified = SystemTime::now();
-                Ok(())
-            }
+            Node::File(ref mut file) => file.modified = SystemTime::now(),
+            Node::Dir(ref mut dir) => dir.modified = SystemTime::now(),
+            Node::Symlink(ref mut link) => link.modified = SystemTime::now(),
+        }
+        self.emit(FsEvent::Modified(self.real_path(path, true)));
+        Ok(())
+    }
+
+    // absolute path without ".", "..", repeated or trailing slashes, e.g. "//a/./b/../c/" -> "/a/c"
+    // paths are always resolved from the root, ".." at the root stays at the root (like in a shell)
+    // the result is purely lexical: symlinks are not resolved and the path may not exist
+    pub fn canonicalize(&self, path: &str) -> String {
+        format!("/{}", path_components(path).join("/"))
+    }
+
+    // split a path in the real parent dir (see resolve) and the name of the node, which is not followed
+    // if it's a symlink (the node itself doesn't need to exist)
+    // possible errors: NotFound, NotADir, TooManyLinks (for the parent), GenericError for the root
+    fn split_parent(&self, path: &str) -> Result<(Vec<String>, String), FSError> {
+        let trimmed = path.trim_end_matches('/');
+        let (parent, name) = trimmed.rsplit_once('/').unwrap_or(("", trimmed));
+        if name.is_empty() || name == "." || name == ".." {
+            // the name is known only after resolving the whole path
+            let mut parts = self.resolve(path, true)?;
+            let name = parts.pop().ok_or_else(|| FSError::GenericError("The root dir has no parent".to_string()))?;
+            return Ok((parts, name));
         }
+        Ok((self.resolve(parent, true)?, name.to_string()))
     }
 
     // remove a node from the filesystem and return it
     // if it's a dir, it must be empty
-    // possible errors: NotFound, DirNotEmpty
+    // possible errors: NotFound, DirNotEmpty, PermissionDenied (readonly node)
+    // a symlink is removed, not its target
     pub fn delete(&mut self, path: &str) -> Result<Node, FSError> {
-        // Navigate to the node 
-        let node = self.navigate_filesystem_mut(path)?;
-        let path_obj = Path::new(path);
+        if metadata(self.physical(&self.resolve(path, false)?)?).readonly {
+            return Err(FSError::PermissionDenied);
+        }
+        self.remove_node(path, false)
+    }
 
-        match node {
-            Node::File(ref mut file) => {
-                let parent_node = self.navigate_filesystem_mut((path_obj).parent())?;
-                parent_node.child.pop(node);
-                return node
-            },
-            Node::Dir(ref mut dir) => {
-                if dir.child.empty() {
-                    let parent_node = self.navigate_filesystem_mut((path_obj).parent())?;
-                    parent_node.child.pop(node);
-                    return node
-                } else {
-                    return Err(FSError::DirNotEmpty)
-                }
+    // remove a node from the filesystem with all its content and return it
+    // a single Deleted event is sent for the whole subtree
+    // possible errors: NotFound, PermissionDenied (a readonly node in the subtree, nothing is removed)
+    pub fn delete_recursive(&mut self, path: &str) -> Result<Node, FSError> {
+        if has_readonly(self.physical(&self.resolve(path, false)?)?) {
+            return Err(FSError::PermissionDenied);
+        }
+        self.remove_node(path, true)
+    }
+
+    // set the permission bits of a node, readonly when no write bit is set
+    // possible errors: NotFound
+    pub fn chmod(&mut self, path: &str, mode: u32) -> Result<(), FSError> {
+        let metadata = metadata_mut(self.navigate_filesystem_mut(path)?);
+        metadata.mode = mode & 0o7777;
+        metadata.readonly = mode & 0o222 == 0;
+        Ok(())
+    }
+
+    // like std::fs::Permissions::set_readonly: clears or restores the write bit of the owner
+    // possible errors: NotFound
+    pub fn set_readonly(&mut self, path: &str, readonly: bool) -> Result<(), FSError> {
+        let metadata = metadata_mut(self.navigate_filesystem_mut(path)?);
+        metadata.readonly = readonly;
+        metadata.mode = if readonly { metadata.mode & !0o222 } else { metadata.mode | 0o200 };
+        Ok(())
+    }
+
+    // possible errors: NotFound
+    pub fn chown(&mut self, path: &str, owner: &str) -> Result<(), FSError> {
+        metadata_mut(self.navigate_filesystem_mut(path)?).owner = owner.to_string();
+        Ok(())
+    }
+
+    fn remove_node(&mut self, path: &str, recursive: bool) -> Result<Node, FSError> {
+        let deleted = self.real_path(path, false);
+        let node = self.detach(path, recursive)?;
+        self.emit(FsEvent::Deleted(deleted));
+        Ok(node)
+    }
+
+    // remove a node from its parent without notifying it (mv sends its own event)
+    fn detach(&mut self, path: &str, recursive: bool) -> Result<Node, FSError> {
+        let (parent, name) = self.split_parent(path)?;
+        // Navigate to the parent dir, the node is one of its children
+        let parent_node = self.physical_mut(&parent)?;
+        let index = Self::child_index(parent_node, &name)?;
+        let parent_dir = Self::as_dir_mut(parent_node)?;
+
+        match &parent_dir.children[index] {
+            Node::Dir(dir) if !recursive && !dir.children.is_empty() => Err(FSError::DirNotEmpty),
+            _ => Ok(parent_dir.children.remove(index)),
+        }
+    }
+
+    // change the name of a node, leaving it in the same dir
+    // possible errors: NotFound, Duplicate, GenericError (invalid name)
+    pub fn rename(&mut self, path: &str, new_name: &str) -> Result<(), FSError> {
+        if new_name.is_empty() || new_name.contains('/') {
+            return Err(FSError::GenericError(format!("Invalid name: {}", new_name)));
+        }
+        let (mut parent, name) = self.split_parent(path)?;
+        let parent_node = self.physical_mut(&parent)?;
+        let index = Self::child_index(parent_node, &name)?;
+        if name == new_name {
+            return Ok(());
+        }
+        if Self::child_index(parent_node, new_name).is_ok() {
+            return Err(FSError::Duplicate);
+        }
+
+        let node = &mut Self::as_dir_mut(parent_node)?.children[index];
+        set_name(node, new_name);
+        // the files are at a new path on disk, sync has to write them there
+        set_dirty(node, true);
+        parent.push(name);
+        let from = join_path(&parent);
+        parent.pop();
+        parent.push(new_name.to_string());
+        self.emit(FsEvent::Moved { from, to: join_path(&parent) });
+        Ok(())
+    }
+
+    // real destination dir and name of the node for mv and cp:
+    // if dst is a dir the node goes inside it, otherwise dst is the new path of the node
+    // (symlinks are compared by their real paths, so a dir can't be moved into itself through a link)
+    // possible errors: NotFound, NotADir, TooManyLinks, GenericError (a dir into itself)
+    fn destination(&self, src: &str, dst: &str) -> Result<(Vec<String>, String), FSError> {
+        let src_parts = self.resolve(src, false)?;
+        let src_name = src_parts.last().cloned().ok_or_else(|| FSError::GenericError("The root dir has no parent".to_string()))?;
+
+        let (dst_dir, dst_name) = match self.navigate_filesystem(dst) {
+            Ok(Node::Dir(_)) => (self.resolve(dst, true)?, src_name),
+            _ => self.split_parent(dst)?,
+        };
+        if dst_dir.starts_with(&src_parts) {
+            return Err(FSError::GenericError("Cannot move or copy a dir into itself".to_string()));
+        }
+        if !matches!(self.physical(&dst_dir)?, Node::Dir(_)) {
+            return Err(FSError::NotADir);
+        }
+        Ok((dst_dir, dst_name))
+    }
+
+    // move a node (with its subtree) like the shell mv:
+    // if dst is a dir the node is moved inside it, otherwise dst is the new path of the node
+    // return the new path of the node
+    // the tree is unchanged if an error is returned
+    // possible errors: NotFound, NotADir, Duplicate, GenericError (moving a dir into itself)
+    pub fn mv(&mut self, src: &str, dst: &str) -> Result<String, FSError> {
+        let (mut dst_dir, dst_name) = self.destination(src, dst)?;
+        let (src_parent, src_name) = self.split_parent(src)?;
+        if src_parent == dst_dir && src_name == dst_name {
+            dst_dir.push(dst_name);
+            return Ok(join_path(&dst_dir));
+        }
+        if Self::child_index(self.physical(&dst_dir)?, &dst_name).is_ok() {
+            return Err(FSError::Duplicate);
+        }
+
+        let from = self.real_path(src, false);
+        let mut node = self.detach(src, true)?;
+        set_name(&mut node, &dst_name);
+        set_dirty(&mut node, true);
+        Self::as_dir_mut(self.physical_mut(&dst_dir)?)?.children.push(node);
+        dst_dir.push(dst_name);
+        let to = join_path(&dst_dir);
+        self.emit(FsEvent::Moved { from, to: to.clone() });
+        Ok(to)
+    }
+
+    // copy a file, or a dir with all its subtree if recursive is true, like the shell cp:
+    // the copies get a new modification time, dst is handled as in mv
+    // symlinks are copied as links (like cp -d), not as the nodes they point to
+    // return the path of the copy
+    // possible errors: NotFound, NotADir, Duplicate, GenericError (dir without recursive, copying a dir into itself)
+    pub fn cp(&mut self, src: &str, dst: &str, recursive: bool) -> Result<String, FSError> {
+        self.copy_node(src, dst, recursive, false)
+    }
+
+    // same as cp, but the copies keep the modification times of the originals (like cp -p)
+    pub fn cp_preserve(&mut self, src: &str, dst: &str, recursive: bool) -> Result<String, FSError> {
+        self.copy_node(src, dst, recursive, true)
+    }
+
+    fn copy_node(&mut self, src: &str, dst: &str, recursive: bool, preserve: bool) -> Result<String, FSError> {
+        let (mut dst_dir, dst_name) = self.destination(src, dst)?;
+        let original = self.physical(&self.resolve(src, false)?)?;
+        if matches!(original, Node::Dir(_)) && !recursive {
+            return Err(FSError::GenericError(format!("{} is a dir (not copied)", src)));
+        }
+        if Self::child_index(self.physical(&dst_dir)?, &dst_name).is_ok() {
+            return Err(FSError::Duplicate);
+        }
+
+        let mut copy = original.clone();
+        set_name(&mut copy, &dst_name);
+        set_dirty(&mut copy, true);
+        if !preserve {
+            set_modified(&mut copy, SystemTime::now());
+        }
+        Self::as_dir_mut(self.physical_mut(&dst_dir)?)?.children.push(copy);
+        dst_dir.push(dst_name);
+        let path = join_path(&dst_dir);
+        self.emit(FsEvent::Created(path.clone()));
+        Ok(path)
+    }
+
+    fn file_mut(&mut self, path: &str) -> Result<&mut File, FSError> {
+        match self.navigate_filesystem_mut(path)? {
+            Node::File(file) => Ok(file),
+            _ => Err(FSError::GenericError(format!("{} is a dir", path))),
+        }
+    }
+
+    // replace the content of a file and update its modification time
+    // possible errors: NotFound, PermissionDenied (readonly file), GenericError (path is a dir)
+    pub fn write_file(&mut self, path: &str, content: &[u8]) -> Result<(), FSError> {
+        let file = self.file_mut(path)?;
+        if file.metadata.readonly {
+            return Err(FSError::PermissionDenied);
+        }
+        file.content = content.to_vec();
+        // the whole content is known now, sync can overwrite the file on disk
+        file.truncated = false;
+        file.dirty = true;
+        file.modified = SystemTime::now();
+        self.emit(FsEvent::Modified(self.real_path(path, true)));
+        Ok(())
+    }
+
+    // add data at the end of a file and update its modification time
+    // possible errors: NotFound, PermissionDenied (readonly file), GenericError (path is a dir, or only a preview of the file was loaded from disk)
+    pub fn append_file(&mut self, path: &str, content: &[u8]) -> Result<(), FSError> {
+        let file = self.file_mut(path)?;
+        if file.metadata.readonly {
+            return Err(FSError::PermissionDenied);
+        }
+        if file.truncated {
+            return Err(FSError::GenericError(format!("{} was not fully loaded", path)));
+        }
+        file.content.extend_from_slice(content);
+        file.dirty = true;
+        file.modified = SystemTime::now();
+        self.emit(FsEvent::Modified(self.real_path(path, true)));
+        Ok(())
+    }
+
+    // content of a file (for files loaded by from, at most the first 1k bytes)
+    // possible errors: NotFound, GenericError (path is a dir)
+    pub fn read_file(&self, path: &str) -> Result<&[u8], FSError> {
+        match self.navigate_filesystem(path)? {
+            Node::File(file) => Ok(&file.content),
+            _ => Err(FSError::GenericError(format!("{} is a dir", path))),
+        }
+    }
+
+    // total size of the file contents under the given path (or of the file itself)
+    // for files loaded by from only the loaded bytes are counted
+    // possible errors: NotFound, NotADir (a file in the middle of the path)
+    pub fn du(&self, path: &str) -> Result<usize, FSError> {
+        Ok(content_size(self.navigate_filesystem(path)?))
+    }
+
+    // the subtree at path drawn like the tree command, e.g. for "/src":
+    // /src
+    // ├── main.rs
+    // └── fs
+    //     └── mod.rs
+    // children are listed in the order of the tree, symlinks are shown as "name -> target" and not followed
+    // possible errors: NotFound, NotADir, TooManyLinks
+    pub fn render_tree(&self, path: &str) -> Result<String, FSError> {
+        self.render_tree_with(path, TreeOptions::default())
+    }
+
+    // same as render_tree, with sizes and/or timestamps in brackets before the names
+    pub fn render_tree_with(&self, path: &str, options: TreeOptions) -> Result<String, FSError> {
+        let node = self.navigate_filesystem(path)?;
+        let mut out = Self::tree_entry(node, &self.canonicalize(path), options);
+        out.push('\n');
+        if let Node::Dir(dir) = node {
+            Self::render_children(dir, &mut String::new(), options, &mut out);
+        }
+        Ok(out)
+    }
+
+    // prefix holds the vertical lines of the ancestors, extended and truncated like the path in walk
+    fn render_children(dir: &Dir, prefix: &mut String, options: TreeOptions, out: &mut String) {
+        for (i, child) in dir.children.iter().enumerate() {
+            let last = i + 1 == dir.children.len();
+            out.push_str(prefix);
+            out.push_str(if last { "└── " } else { "├── " });
+            out.push_str(&Self::tree_entry(child, node_name(child), options));
+            out.push('\n');
+            if let Node::Dir(sub) = child {
+                let len = prefix.len();
+                prefix.push_str(if last { "    " } else { "│   " });
+                Self::render_children(sub, prefix, options, out);
+                prefix.truncate(len);
             }
         }
     }
 
-    // get a reference to a node in the filesystem, given the path
-    // pub fn get(&mut self, path: &str) -> Result<&Node, FSError> {
-    //     unimplemented!()
-    // }
+    fn tree_entry(node: &Node, name: &str, options: TreeOptions) -> String {
+        let mut columns = Vec::new();
+        if options.sizes {
+            columns.push(format!("{:>8}", content_size(node)));
+        }
+        if options.timestamps {
+            columns.push(format_time(node_modified(node)));
+        }
+        let mut entry = if columns.is_empty() { String::new() } else { format!("[{}]  ", columns.join("  ")) };
+        entry.push_str(name);
+        if let Node::Symlink(link) = node {
+            entry.push_str(" -> ");
+            entry.push_str(&link.target);
+        }
+        entry
+    }
 
-    // get a mutable reference to a node in the filesystem, given the path
-    // pub fn get_mut(&mut self, path: &str) -> Result<&mut Node, FSError> {
-    //     unimplemented!()
-    // }
+    // number of files, dirs (the root dir is not counted) and symlinks, and depth of the deepest node
+    // (the children of the root are at depth 1, an empty filesystem has depth 0; links are not followed)
+    pub fn stats(&self) -> FsStats {
+        let mut stats = FsStats { files: 0, dirs: 0, links: 0, max_depth: 0 };
+        self.walk(|path, node| {
+            let depth = path_components(path).len();
+            if depth == 0 {
+                return;
+            }
+            match node {
+                Node::File(_) => stats.files += 1,
+                Node::Dir(_) => stats.dirs += 1,
+                Node::Symlink(_) => stats.links += 1,
+            }
+            stats.max_depth = stats.max_depth.max(depth);
+        });
+        stats
+    }
+
+    // get a reference to a node in the filesystem, given the path (symlinks are followed)
+    // possible errors: NotFound, NotADir (a file in the middle of the path), TooManyLinks
+    pub fn get(&self, path: &str) -> Result<&Node, FSError> {
+        self.navigate_filesystem(path)
+    }
+
+    // get a mutable reference to a node in the filesystem, given the path (symlinks are followed)
+    // the files of the node may be changed through the reference, so sync writes all of them
+    // possible errors: NotFound, NotADir (a file in the middle of the path), TooManyLinks
+    pub fn get_mut(&mut self, path: &str) -> Result<&mut Node, FSError> {
+        let node = self.navigate_filesystem_mut(path)?;
+        set_dirty(node, true);
+        Ok(node)
+    }
 
     // search for a list of paths in the filesystem
     // qs is a list query strings with constraints
@@ -275,19 +1071,99 @@ impl Filesystem {
     // constraints:
     // - "type:dir" -> match only directories
     // - "type:file" -> match only files
+    // - "type:link" -> match only symlinks (walk, iter and find never follow them)
     // - "name:value" -> match only nodes with the given name
     // - "partname:value" -> match only nodes with the given string in the name
+    // - "glob:pattern" -> match the full path against the pattern, with '*' and '?' inside a name
+    //   and '**' for any number of dirs (e.g. "glob:/src/**/*.rs"); a pattern without '/' is
+    //   matched against the name only (e.g. "glob:*.txt" matches at any depth)
+    // the root dir is never returned; nodes are listed depth-first, each one at most once,
+    // with the first query it matched
+    pub fn find<'a>(&'a self, qs: &[&'a str]) -> Vec<MatchResult<'a>> {
+        let mut results = Vec::new();
+        if let Node::Dir(root) = &self.root {
+            for child in &root.children {
+                Self::find_in(child, "", qs, &mut results);
+            }
+        }
+        results
+    }
 
-    // pub fn find<'a>(&'a self, qs: &[&'a str]) -> Vec<MatchResult> {
-    //     unimplemented!()
-    // }
+    fn find_in<'a>(node: &'a Node, parent: &str, qs: &[&'a str], results: &mut Vec<MatchResult<'a>>) {
+        let path = format!("{}/{}", parent, node_name(node));
+        if let Some(q) = qs.iter().find(|q| matches_query(node, &path, q)) {
+            results.push(MatchResult { q, path: path.clone(), node });
+        }
+        if let Node::Dir(dir) = node {
+            for child in &dir.children {
+                Self::find_in(child, &path, qs, results);
+            }
+        }
+    }
 
 
     // walk the filesystem, starting from the root, and call the closure for each node with its path
     // the first parameter of the closure is the path of the node, second is the node itself
-    // pub fn walk(&self, f: impl Fn(&str, &Node)) {
-    //     unimplemented!()
-    // }
+    // the traversal is depth-first, the root dir is visited first with path "/"
+    pub fn walk(&self, mut f: impl FnMut(&str, &Node)) {
+        f("/", &self.root);
+        if let Node::Dir(root) = &self.root {
+            // the same buffer is extended and truncated while going down and up the tree
+            let mut path = String::new();
+            for child in &root.children {
+                Self::walk_node(child, &mut path, &mut f);
+            }
+        }
+    }
+
+    fn walk_node(node: &Node, path: &mut String, f: &mut impl FnMut(&str, &Node)) {
+        let len = path.len();
+        path.push('/');
+        path.push_str(node_name(node));
+        f(path, node);
+        if let Node::Dir(dir) = node {
+            for child in &dir.children {
+                Self::walk_node(child, path, f);
+            }
+        }
+        path.truncate(len);
+    }
+
+    // iterator over all the nodes with their paths, starting from the root ("/") like walk,
+    // in depth-first (same order as walk) or breadth-first order
+    pub fn iter(&self, order: Traversal) -> FsIter<'_> {
+        FsIter { pending: VecDeque::from([("/".to_string(), &self.root)]), order }
+    }
+
+    // same as walk, but the closure can modify the nodes
+    // the path passed for a node is the one it had before the closure was called,
+    // its children are visited after the closure, so renaming a dir doesn't change their paths
+    // every file may be changed by the closure, so sync writes all of them
+    pub fn walk_mut(&mut self, mut f: impl FnMut(&str, &mut Node)) {
+        f("/", &mut self.root);
+        if let Node::Dir(root) = &mut self.root {
+            let mut path = String::new();
+            for child in root.children.iter_mut() {
+                Self::walk_node_mut(child, &mut path, &mut f);
+            }
+        }
+    }
+
+    fn walk_node_mut(node: &mut Node, path: &mut String, f: &mut impl FnMut(&str, &mut Node)) {
+        let len = path.len();
+        path.push('/');
+        path.push_str(node_name(node));
+        if let Node::File(file) = node {
+            file.dirty = true;
+        }
+        f(path, node);
+        if let Node::Dir(dir) = node {
+            for child in dir.children.iter_mut() {
+                Self::walk_node_mut(child, path, f);
+            }
+        }
+        path.truncate(len);
+    }
 }
 
 fn demo() {
@@ -367,4 +1243,584 @@ fn demo() {
 
 pub fn main_ex2() -> Result<(), Box<dyn std::error::Error>> { 
     Ok(demo())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str) -> Node {
+        Node::File(File {
+            name: name.to_string(),
+            modified: SystemTime::now(),
+            content: Vec::new(),
+            truncated: false,
+            dirty: true,
+            metadata: Metadata::file(),
+        })
+    }
+
+    fn dir(name: &str, children: Vec<Node>) -> Node {
+        Node::Dir(Dir { name: name.to_string(), modified: SystemTime::now(), children, metadata: Metadata::dir() })
+    }
+
+    // /src/{main.rs, fs/{mod.rs, tree.rs}}, /docs/{notes.txt}, /main.rs
+    fn sample() -> Filesystem {
+        Filesystem {
+            mode: Mode::InMemory,
+            subscribers: Vec::new(),
+            root: dir(
+                "",
+                vec![
+                    dir("src", vec![file("main.rs"), dir("fs", vec![file("mod.rs"), file("tree.rs")])]),
+                    dir("docs", vec![file("notes.txt")]),
+                    file("main.rs"),
+                ],
+            ),
+        }
+    }
+
+    fn paths(results: &[MatchResult]) -> Vec<String> {
+        results.iter().map(|m| m.path.clone()).collect()
+    }
+
+    #[test]
+    fn find_by_type() {
+        let fs = sample();
+        assert_eq!(paths(&fs.find(&["type:dir"])), ["/src", "/src/fs", "/docs"]);
+        assert_eq!(
+            paths(&fs.find(&["type:file"])),
+            ["/src/main.rs", "/src/fs/mod.rs", "/src/fs/tree.rs", "/docs/notes.txt", "/main.rs"]
+        );
+    }
+
+    #[test]
+    fn find_combines_queries_in_or() {
+        let fs = sample();
+        let results = fs.find(&["name:main.rs", "partname:s", "bogus:x"]);
+        // each node once, with the first query it matched
+        assert_eq!(
+            paths(&results),
+            ["/src", "/src/main.rs", "/src/fs", "/src/fs/mod.rs", "/src/fs/tree.rs", "/docs", "/docs/notes.txt", "/main.rs"]
+        );
+        let queries: Vec<&str> = results.iter().map(|m| m.q).collect();
+        assert_eq!(queries[1], "name:main.rs");
+        assert_eq!(queries[0], "partname:s");
+        assert!(matches!(results[2].node, Node::Dir(Dir { children, .. }) if children.len() == 2));
+
+        let results = fs.find(&["type:dir", "name:notes.txt"]);
+        assert_eq!(paths(&results), ["/src", "/src/fs", "/docs", "/docs/notes.txt"]);
+        assert!(fs.find(&["name:missing", "partname:zzz"]).is_empty());
+    }
+
+    #[test]
+    fn walk_visits_every_node_with_its_path() {
+        let fs = sample();
+        let mut visited = Vec::new();
+        fs.walk(|path, node| visited.push((path.to_string(), matches!(node, Node::Dir(_)))));
+        assert_eq!(
+            visited,
+            [
+                ("/".to_string(), true),
+                ("/src".to_string(), true),
+                ("/src/main.rs".to_string(), false),
+                ("/src/fs".to_string(), true),
+                ("/src/fs/mod.rs".to_string(), false),
+                ("/src/fs/tree.rs".to_string(), false),
+                ("/docs".to_string(), true),
+                ("/docs/notes.txt".to_string(), false),
+                ("/main.rs".to_string(), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn walk_mut_transforms_the_tree() {
+        let mut fs = sample();
+        let mut renamed = Vec::new();
+        fs.walk_mut(|path, node| match node {
+            Node::File(f) if f.name.ends_with(".rs") => {
+                f.name.push_str(".bak");
+                renamed.push(path.to_string());
+            }
+            Node::Dir(d) if d.name == "fs" => d.name = "vfs".to_string(),
+            _ => {}
+        });
+        // paths are the ones before the change
+        assert_eq!(renamed, ["/src/main.rs", "/src/fs/mod.rs", "/src/fs/tree.rs", "/main.rs"]);
+        assert_eq!(
+            paths(&fs.find(&["partname:.bak"])),
+            ["/src/main.rs.bak", "/src/vfs/mod.rs.bak", "/src/vfs/tree.rs.bak", "/main.rs.bak"]
+        );
+    }
+
+    #[test]
+    fn get_and_get_mut_by_path() {
+        let mut fs = sample();
+        assert!(matches!(fs.get("/"), Ok(Node::Dir(d)) if d.children.len() == 3));
+        assert!(matches!(fs.get("/src/fs/tree.rs"), Ok(Node::File(f)) if f.name == "tree.rs"));
+        assert!(matches!(fs.get("/src/missing"), Err(FSError::NotFound)));
+        assert!(matches!(fs.get("/main.rs/inner"), Err(FSError::NotADir)));
+
+        // read-only lookups can coexist
+        let (a, b) = (fs.get("/src").unwrap(), fs.get("/docs").unwrap());
+        assert_ne!(node_name(a), node_name(b));
+
+        if let Node::File(f) = fs.get_mut("/docs/notes.txt").unwrap() {
+            f.content.extend_from_slice(b"todo");
+        }
+        assert!(matches!(fs.get("/docs/notes.txt"), Ok(Node::File(f)) if f.content == b"todo"));
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("filesystem_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn mkdir_and_create_file_stay_in_memory() {
+        let mut fs = Filesystem::new();
+        let name = format!("filesystem_virtual_{}", std::process::id());
+        fs.mkdir("/", &name).unwrap();
+        fs.create_file(&format!("/{}", name), "a.txt").unwrap();
+
+        assert!(!Path::new("/").join(&name).exists());
+        assert!(matches!(fs.mkdir("/", &name), Err(FSError::Duplicate)));
+        assert!(matches!(fs.create_file(&format!("/{}", name), "a.txt"), Err(FSError::Duplicate)));
+        assert!(matches!(fs.mkdir("/missing", "x"), Err(FSError::NotFound)));
+        assert!(matches!(fs.create_file(&format!("/{}/a.txt", name), "x"), Err(FSError::NotADir)));
+        assert!(matches!(fs.sync(), Err(FSError::GenericError(_))));
+    }
+
+    #[test]
+    fn sync_writes_the_tree_to_disk() {
+        let dir = temp_dir("sync");
+        let mut fs = Filesystem::new();
+        fs.mkdir("/", "a").unwrap();
+        fs.create_file("/a", "f.txt").unwrap().content = b"hello".to_vec();
+        fs.sync_to(&dir.to_string_lossy()).unwrap();
+        assert_eq!(fs::read(dir.join("a/f.txt")).unwrap(), b"hello");
+
+        // big file: only a preview is loaded, so sync must not overwrite it
+        fs::write(dir.join("big"), vec![7u8; 3000]).unwrap();
+        let mut loaded = Filesystem::from(&dir.to_string_lossy()).unwrap();
+        loaded.mkdir("/a", "b").unwrap();
+        loaded.touch("/big").unwrap();
+        loaded.sync().unwrap();
+        assert!(dir.join("a/b").is_dir());
+        assert_eq!(fs::read(dir.join("big")).unwrap().len(), 3000);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sync_writes_only_the_changed_files() {
+        let dir = temp_dir("dirty");
+        fs::create_dir_all(dir.join("objects")).unwrap();
+        fs::write(dir.join("objects/a1"), b"blob").unwrap();
+        fs::write(dir.join("notes.txt"), b"old").unwrap();
+        let mut loaded = Filesystem::from(&dir.to_string_lossy()).unwrap();
+
+        // the files changed on disk after the load show which ones sync rewrites
+        fs::write(dir.join("objects/a1"), b"changed on disk").unwrap();
+        loaded.write_file("/notes.txt", b"new").unwrap();
+        loaded.create_file("/", "added.txt").unwrap();
+        loaded.sync().unwrap();
+        assert_eq!(fs::read(dir.join("objects/a1")).unwrap(), b"changed on disk");
+        assert_eq!(fs::read(dir.join("notes.txt")).unwrap(), b"new");
+        assert!(dir.join("added.txt").is_file());
+
+        // once synced a file is clean again
+        fs::write(dir.join("notes.txt"), b"changed on disk").unwrap();
+        loaded.sync().unwrap();
+        assert_eq!(fs::read(dir.join("notes.txt")).unwrap(), b"changed on disk");
+
+        // a clean file missing on disk is written anyway
+        let other = temp_dir("dirty_copy");
+        loaded.sync_to(&other.to_string_lossy()).unwrap();
+        assert_eq!(fs::read(other.join("objects/a1")).unwrap(), b"blob");
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_dir_all(&other).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn from_loads_symlinks_as_links() {
+        let dir = temp_dir("links");
+        fs::create_dir_all(dir.join("a")).unwrap();
+        fs::write(dir.join("a/f.txt"), b"hello").unwrap();
+        std::os::unix::fs::symlink("a/f.txt", dir.join("to_file")).unwrap();
+        std::os::unix::fs::symlink("..", dir.join("a/up")).unwrap();
+        std::os::unix::fs::symlink("missing", dir.join("broken")).unwrap();
+
+        let mut loaded = Filesystem::from(&dir.to_string_lossy()).unwrap();
+        let target = |path: &str| match loaded.physical(&loaded.resolve(path, false).unwrap()) {
+            Ok(Node::Symlink(link)) => Some(link.target.clone()),
+            _ => None,
+        };
+        assert_eq!(target("/to_file").as_deref(), Some("a/f.txt"));
+        assert_eq!(target("/a/up").as_deref(), Some(".."));
+        assert_eq!(target("/broken").as_deref(), Some("missing"));
+        // the link is still followed when the tree is navigated
+        assert!(matches!(loaded.get("/to_file"), Ok(Node::File(f)) if f.content == b"hello"));
+
+        loaded.sync().unwrap();
+        assert!(fs::symlink_metadata(dir.join("to_file")).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read(dir.join("a/f.txt")).unwrap(), b"hello");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn delete_requires_empty_dirs() {
+        let mut fs = sample();
+        assert!(matches!(fs.delete("/src/fs"), Err(FSError::DirNotEmpty)));
+        assert!(matches!(fs.delete("/src/fs/mod.rs"), Ok(Node::File(f)) if f.name == "mod.rs"));
+        assert!(matches!(fs.delete("/src/fs/mod.rs"), Err(FSError::NotFound)));
+        assert!(matches!(fs.delete("/"), Err(FSError::GenericError(_))));
+
+        assert!(matches!(fs.delete_recursive("/src"), Ok(Node::Dir(d)) if d.children.len() == 2));
+        assert_eq!(paths(&fs.find(&["partname:"])), ["/docs", "/docs/notes.txt", "/main.rs"]);
+    }
+
+    #[test]
+    fn rename_keeps_the_node_in_place() {
+        let mut fs = sample();
+        fs.rename("/src/fs", "vfs").unwrap();
+        assert!(fs.get("/src/vfs/tree.rs").is_ok());
+        assert!(matches!(fs.rename("/src/vfs", "main.rs"), Err(FSError::Duplicate)));
+        assert!(matches!(fs.rename("/src/missing", "x"), Err(FSError::NotFound)));
+        assert!(matches!(fs.rename("/src/vfs", "a/b"), Err(FSError::GenericError(_))));
+    }
+
+    #[test]
+    fn mv_reparents_subtrees() {
+        let mut fs = sample();
+        // into an existing dir
+        assert_eq!(fs.mv("/src/fs", "/docs").unwrap(), "/docs/fs");
+        assert!(fs.get("/docs/fs/tree.rs").is_ok());
+        assert!(matches!(fs.get("/src/fs"), Err(FSError::NotFound)));
+        // with a new name
+        assert_eq!(fs.mv("/main.rs", "/src/lib.rs").unwrap(), "/src/lib.rs");
+        assert!(matches!(fs.get("/src/lib.rs"), Ok(Node::File(_))));
+
+        assert!(matches!(fs.mv("/src/lib.rs", "/src/main.rs"), Err(FSError::Duplicate)));
+        assert!(matches!(fs.mv("/missing", "/src"), Err(FSError::NotFound)));
+        assert!(matches!(fs.mv("/src/lib.rs", "/nowhere/lib.rs"), Err(FSError::NotFound)));
+        assert!(matches!(fs.mv("/src/lib.rs", "/src/main.rs/x"), Err(FSError::NotADir)));
+    }
+
+    #[test]
+    fn mv_into_own_descendant_is_rejected() {
+        let mut fs = sample();
+        assert!(matches!(fs.mv("/src", "/src/fs"), Err(FSError::GenericError(_))));
+        assert!(matches!(fs.mv("/src", "/src/fs/inner"), Err(FSError::GenericError(_))));
+        assert!(matches!(fs.mv("/src", "/src"), Err(FSError::GenericError(_))));
+        // nothing was detached
+        assert!(fs.get("/src/fs/mod.rs").is_ok());
+        // a sibling with a common prefix is not a descendant
+        fs.mkdir("/", "src2").unwrap();
+        assert_eq!(fs.mv("/src2", "/src/fs").unwrap(), "/src/fs/src2");
+    }
+
+    fn modified(fs: &Filesystem, path: &str) -> SystemTime {
+        match fs.get(path).unwrap() {
+            Node::File(f) => f.modified,
+            Node::Dir(d) => d.modified,
+            Node::Symlink(l) => l.modified,
+        }
+    }
+
+    #[test]
+    fn write_append_and_read_files() {
+        let mut fs = sample();
+        let before = modified(&fs, "/docs/notes.txt");
+        fs.write_file("/docs/notes.txt", b"first").unwrap();
+        fs.append_file("/docs/notes.txt", b", second").unwrap();
+        assert_eq!(fs.read_file("/docs/notes.txt").unwrap(), b"first, second");
+        assert!(modified(&fs, "/docs/notes.txt") > before);
+
+        fs.write_file("/docs/notes.txt", b"").unwrap();
+        assert!(fs.read_file("/docs/notes.txt").unwrap().is_empty());
+
+        assert!(matches!(fs.read_file("/docs"), Err(FSError::GenericError(_))));
+        assert!(matches!(fs.write_file("/docs/missing", b"x"), Err(FSError::NotFound)));
+    }
+
+    #[test]
+    fn append_needs_the_whole_content() {
+        let dir = temp_dir("append");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("big"), vec![1u8; 2000]).unwrap();
+        let mut loaded = Filesystem::from(&dir.to_string_lossy()).unwrap();
+        assert_eq!(loaded.read_file("/big").unwrap().len(), 1024);
+        assert!(matches!(loaded.append_file("/big", b"x"), Err(FSError::GenericError(_))));
+
+        // after a full write sync replaces the file on disk
+        loaded.write_file("/big", b"small").unwrap();
+        loaded.append_file("/big", b"er").unwrap();
+        loaded.sync().unwrap();
+        assert_eq!(fs::read(dir.join("big")).unwrap(), b"smaller");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn paths_are_normalized() {
+        let mut fs = sample();
+        assert_eq!(fs.canonicalize("//src/./fs/../fs//tree.rs/"), "/src/fs/tree.rs");
+        assert_eq!(fs.canonicalize("/../.."), "/");
+        assert_eq!(fs.canonicalize("docs/"), "/docs");
+
+        assert!(matches!(fs.get("/src/fs/../../docs/./notes.txt"), Ok(Node::File(_))));
+        assert!(matches!(fs.get("/../src//"), Ok(Node::Dir(d)) if d.name == "src"));
+        fs.write_file("/docs/../main.rs", b"fn main() {}").unwrap();
+        assert_eq!(fs.read_file("/main.rs").unwrap(), b"fn main() {}");
+
+        assert_eq!(fs.mv("/src/fs/", "/docs/./").unwrap(), "/docs/fs");
+        assert!(matches!(fs.mv("/docs/fs/..", "/docs/fs"), Err(FSError::GenericError(_))));
+        assert!(matches!(fs.delete("/docs/fs/.."), Err(FSError::DirNotEmpty)));
+    }
+
+    #[test]
+    fn cp_deep_copies_subtrees() {
+        let mut fs = sample();
+        fs.write_file("/src/fs/mod.rs", b"mod tree;").unwrap();
+        let original = modified(&fs, "/src/fs/mod.rs");
+
+        assert!(matches!(fs.cp("/src/fs", "/docs", false), Err(FSError::GenericError(_))));
+        assert_eq!(fs.cp("/src/fs", "/docs", true).unwrap(), "/docs/fs");
+        assert_eq!(fs.cp_preserve("/src/fs", "/fs_snapshot", true).unwrap(), "/fs_snapshot");
+        assert_eq!(fs.cp("/main.rs", "/src/fs/main_copy.rs", false).unwrap(), "/src/fs/main_copy.rs");
+
+        // the copies are independent from the original
+        fs.write_file("/src/fs/mod.rs", b"changed").unwrap();
+        assert_eq!(fs.read_file("/docs/fs/mod.rs").unwrap(), b"mod tree;");
+        assert!(fs.get("/docs/fs/main_copy.rs").is_err());
+        assert!(modified(&fs, "/docs/fs/mod.rs") > original);
+        assert_eq!(modified(&fs, "/fs_snapshot/mod.rs"), original);
+
+        assert!(matches!(fs.cp("/main.rs", "/src/main.rs", false), Err(FSError::Duplicate)));
+        assert!(matches!(fs.cp("/src", "/src/fs", true), Err(FSError::GenericError(_))));
+        assert!(matches!(fs.cp("/missing", "/src", true), Err(FSError::NotFound)));
+    }
+
+    #[test]
+    fn du_and_stats_describe_the_tree() {
+        let mut fs = sample();
+        fs.write_file("/src/main.rs", b"fn main() {}").unwrap();
+        fs.write_file("/src/fs/tree.rs", b"struct Tree;").unwrap();
+        fs.write_file("/main.rs", b"x").unwrap();
+
+        assert_eq!(fs.du("/src/fs").unwrap(), 12);
+        assert_eq!(fs.du("/src").unwrap(), 24);
+        assert_eq!(fs.du("/").unwrap(), 25);
+        assert_eq!(fs.du("/main.rs").unwrap(), 1);
+        assert!(matches!(fs.du("/nope"), Err(FSError::NotFound)));
+
+        assert_eq!(fs.stats(), FsStats { files: 5, dirs: 3, links: 0, max_depth: 3 });
+        assert_eq!(Filesystem::new().stats(), FsStats { files: 0, dirs: 0, links: 0, max_depth: 0 });
+    }
+
+    #[test]
+    fn find_with_glob_patterns() {
+        let fs = sample();
+        assert_eq!(paths(&fs.find(&["glob:*.rs"])), ["/src/main.rs", "/src/fs/mod.rs", "/src/fs/tree.rs", "/main.rs"]);
+        assert_eq!(paths(&fs.find(&["glob:/*.rs"])), ["/main.rs"]);
+        assert_eq!(paths(&fs.find(&["glob:/src/*/????.rs"])), ["/src/fs/tree.rs"]);
+        assert_eq!(paths(&fs.find(&["glob:/s*/**/m*.rs"])), ["/src/main.rs", "/src/fs/mod.rs"]);
+        assert_eq!(paths(&fs.find(&["glob:/**"])).len(), 8);
+        assert_eq!(paths(&fs.find(&["glob:/src/**"])), ["/src", "/src/main.rs", "/src/fs", "/src/fs/mod.rs", "/src/fs/tree.rs"]);
+        // in OR with the other constraints
+        assert_eq!(paths(&fs.find(&["glob:/d?cs/*", "name:fs"])), ["/src/fs", "/docs/notes.txt"]);
+        assert!(fs.find(&["glob:/src/*.txt", "glob:?"]).is_empty());
+    }
+
+    #[test]
+    fn glob_matching_rules() {
+        assert!(glob_match_name(b"*", b""));
+        assert!(glob_match_name(b"a*b*c", b"aXXbYc"));
+        assert!(!glob_match_name(b"a*b", b"axbc"));
+        assert!(glob_match_name("?.txt".as_bytes(), "è.txt".as_bytes()));
+        assert!(glob_match_path(&["**", "x"], &["x"]));
+        assert!(glob_match_path(&["a", "**", "**", "x"], &["a", "b", "c", "x"]));
+        assert!(!glob_match_path(&["a", "*"], &["a", "b", "c"]));
+    }
+
+    #[test]
+    fn readonly_nodes_cannot_be_written_or_deleted() {
+        let mut fs = sample();
+        fs.chmod("/docs/notes.txt", 0o444).unwrap();
+        assert!(matches!(fs.write_file("/docs/notes.txt", b"x"), Err(FSError::PermissionDenied)));
+        assert!(matches!(fs.append_file("/docs/notes.txt", b"x"), Err(FSError::PermissionDenied)));
+        assert!(matches!(fs.delete("/docs/notes.txt"), Err(FSError::PermissionDenied)));
+        // a readonly file protects the dirs containing it from recursive deletes
+        assert!(matches!(fs.delete_recursive("/docs"), Err(FSError::PermissionDenied)));
+        assert!(fs.get("/docs/notes.txt").is_ok());
+
+        fs.chmod("/docs/notes.txt", 0o644).unwrap();
+        fs.write_file("/docs/notes.txt", b"x").unwrap();
+        fs.set_readonly("/src/main.rs", true).unwrap();
+        assert!(matches!(fs.get("/src/main.rs"), Ok(node) if metadata(node).mode == 0o444));
+        fs.set_readonly("/src/main.rs", false).unwrap();
+        assert!(matches!(fs.get("/src/main.rs"), Ok(node) if metadata(node).mode == 0o644 && !metadata(node).readonly));
+        assert!(fs.delete_recursive("/docs").is_ok());
+    }
+
+    #[test]
+    fn chown_sets_the_owner() {
+        let mut fs = sample();
+        fs.chown("/src", "alice").unwrap();
+        assert_eq!(metadata(fs.get("/src").unwrap()), &Metadata { readonly: false, owner: "alice".to_string(), mode: 0o755 });
+        assert!(matches!(fs.chown("/nope", "bob"), Err(FSError::NotFound)));
+    }
+
+    #[test]
+    fn iter_in_depth_and_breadth_first_order() {
+        let fs = sample();
+        let mut walked = Vec::new();
+        fs.walk(|path, _| walked.push(path.to_string()));
+        let dfs: Vec<String> = fs.iter(Traversal::DepthFirst).map(|(path, _)| path).collect();
+        assert_eq!(dfs, walked);
+
+        let bfs: Vec<String> = fs.iter(Traversal::BreadthFirst).map(|(path, _)| path).collect();
+        assert_eq!(
+            bfs,
+            ["/", "/src", "/docs", "/main.rs", "/src/main.rs", "/src/fs", "/docs/notes.txt", "/src/fs/mod.rs", "/src/fs/tree.rs"]
+        );
+
+        // composes with the standard adapters
+        let files = fs.iter(Traversal::BreadthFirst).filter(|(_, node)| matches!(node, Node::File(_))).count();
+        assert_eq!(files, 5);
+        let first_dirs: Vec<String> = fs
+            .iter(Traversal::DepthFirst)
+            .filter(|(_, node)| matches!(node, Node::Dir(_)))
+            .skip(1)
+            .take(2)
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(first_dirs, ["/src", "/src/fs"]);
+    }
+
+    #[test]
+    fn symlinks_are_resolved_during_navigation() {
+        let mut fs = sample();
+        fs.ln("/src/fs/up", "..").unwrap();
+        fs.ln("/lib", "/src/fs").unwrap();
+        fs.ln("/docs/main", "../main.rs").unwrap();
+        fs.ln("/dangling", "/nowhere").unwrap();
+        assert!(matches!(fs.ln("/lib", "/src"), Err(FSError::Duplicate)));
+
+        assert!(matches!(fs.get("/lib/tree.rs"), Ok(Node::File(f)) if f.name == "tree.rs"));
+        assert!(matches!(fs.get("/lib/up/main.rs"), Ok(Node::File(f)) if f.name == "main.rs"));
+        // ".." after a link goes to the parent of the target
+        assert!(matches!(fs.get("/lib/.."), Ok(Node::Dir(d)) if d.name == "src"));
+        assert!(matches!(fs.get("/dangling"), Err(FSError::NotFound)));
+
+        fs.write_file("/docs/main", b"through the link").unwrap();
+        assert_eq!(fs.read_file("/main.rs").unwrap(), b"through the link");
+        assert_eq!(paths(&fs.find(&["type:link"])), ["/src/fs/up", "/docs/main", "/lib", "/dangling"]);
+        assert_eq!(fs.stats().links, 4);
+    }
+
+    #[test]
+    fn symlink_cycles_are_detected() {
+        let mut fs = sample();
+        fs.ln("/a", "/b").unwrap();
+        fs.ln("/b", "/a").unwrap();
+        fs.ln("/self", "self").unwrap();
+        assert!(matches!(fs.get("/a"), Err(FSError::TooManyLinks)));
+        assert!(matches!(fs.get("/self/x"), Err(FSError::TooManyLinks)));
+        assert!(matches!(fs.mkdir("/a", "x"), Err(FSError::TooManyLinks)));
+        // the links themselves can still be removed
+        assert!(matches!(fs.delete("/a"), Ok(Node::Symlink(l)) if l.target == "/b"));
+        assert!(matches!(fs.get("/b"), Err(FSError::NotFound)));
+    }
+
+    #[test]
+    fn structural_operations_work_on_the_link() {
+        let mut fs = sample();
+        fs.ln("/src/link", "fs").unwrap();
+        // mv and delete act on the link, not on the target
+        assert_eq!(fs.mv("/src/link", "/docs").unwrap(), "/docs/link");
+        assert!(matches!(fs.get("/docs/link"), Err(FSError::NotFound)));
+        assert_eq!(fs.mv("/docs/link", "/src/link").unwrap(), "/src/link");
+        assert!(matches!(fs.get("/src/link/mod.rs"), Ok(Node::File(_))));
+        // a dir can't be moved into itself through a link to its subtree
+        assert!(matches!(fs.mv("/src", "/src/link"), Err(FSError::GenericError(_))));
+        assert_eq!(fs.cp("/src/link", "/src/link2", false).unwrap(), "/src/link2");
+        assert!(fs.delete("/src/link").is_ok());
+        assert!(fs.get("/src/fs/mod.rs").is_ok());
+        assert!(fs.get("/src/link2/tree.rs").is_ok());
+    }
+
+    #[test]
+    fn subscribers_are_notified_of_changes() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let mut fs = sample();
+        fs.ln("/lib", "/src/fs").unwrap();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let received = Rc::clone(&events);
+        fs.subscribe(move |event| received.borrow_mut().push(event));
+
+        fs.mkdir("/lib", "util").unwrap();
+        fs.create_file("/lib/util", "a.rs").unwrap();
+        fs.write_file("/lib/util/a.rs", b"fn a() {}").unwrap();
+        fs.touch("/docs").unwrap();
+        fs.mv("/lib/util/a.rs", "/docs").unwrap();
+        fs.rename("/docs/a.rs", "b.rs").unwrap();
+        fs.delete("/lib").unwrap();
+        fs.delete_recursive("/src/fs").unwrap();
+        // failed operations send nothing
+        assert!(fs.mkdir("/docs", "b.rs").is_err());
+        assert!(fs.delete("/nowhere").is_err());
+
+        assert_eq!(
+            *events.borrow(),
+            [
+                FsEvent::Created("/src/fs/util".to_string()),
+                FsEvent::Created("/src/fs/util/a.rs".to_string()),
+                FsEvent::Modified("/src/fs/util/a.rs".to_string()),
+                FsEvent::Modified("/docs".to_string()),
+                FsEvent::Moved { from: "/src/fs/util/a.rs".to_string(), to: "/docs/a.rs".to_string() },
+                FsEvent::Moved { from: "/docs/a.rs".to_string(), to: "/docs/b.rs".to_string() },
+                FsEvent::Deleted("/lib".to_string()),
+                FsEvent::Deleted("/src/fs".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn render_tree_like_the_tree_command() {
+        use std::time::Duration;
+
+        let mut fs = sample();
+        fs.ln("/docs/main", "../main.rs").unwrap();
+        assert_eq!(
+            fs.render_tree("/").unwrap(),
+            "/\n\
+             ├── src\n\
+             │   ├── main.rs\n\
+             │   └── fs\n\
+             │       ├── mod.rs\n\
+             │       └── tree.rs\n\
+             ├── docs\n\
+             │   ├── notes.txt\n\
+             │   └── main -> ../main.rs\n\
+             └── main.rs\n"
+        );
+        assert_eq!(fs.render_tree("/docs/notes.txt").unwrap(), "/docs/notes.txt\n");
+        assert!(matches!(fs.render_tree("/nowhere"), Err(FSError::NotFound)));
+
+        fs.write_file("/src/fs/mod.rs", b"mod tree;").unwrap();
+        set_modified(fs.get_mut("/src/fs").unwrap(), UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        let options = TreeOptions { sizes: true, timestamps: true };
+        assert_eq!(
+            fs.render_tree_with("/src/fs", options).unwrap(),
+            "[       9  2023-11-14 22:13]  /src/fs\n\
+             ├── [       9  2023-11-14 22:13]  mod.rs\n\
+             └── [       0  2023-11-14 22:13]  tree.rs\n"
+        );
+        assert_eq!(format_time(UNIX_EPOCH + Duration::from_secs(951_782_400)), "2000-02-29 00:00");
+    }
+}
@@ -1,5 +1,5 @@
 
-use std::sync::Mutex;
+use std::sync::{Condvar, Mutex, MutexGuard};
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
@@ -7,6 +7,8 @@ use std::time::Duration;
 #[derive(Debug, PartialEq)]
 pub enum Err {
     Full,
+    Timeout, // operazione bloccante non completata entro il tempo indicato
+    Closed,  // buffer condiviso chiuso (e, per le letture, vuoto)
 }
 
 pub struct CircularBuffer<T> { 
@@ -29,7 +31,6 @@ impl<T: Clone> Clone for CircularBuffer<T> {
     }
 }
 
-type SharedCircularBuffer<T> = Arc<Mutex<CircularBuffer<T>>>;
 
 impl<T> CircularBuffer<T> {
     pub fn new(capacity: usize) -> Self {
@@ -75,16 +76,19 @@ impl<T> CircularBuffer<T> {
         self.size
     }
 
-    pub fn overwrite(&mut self, item: T) {
+    pub fn overwrite(&mut self, item: T) -> Option<T> {
+        // restituisce l'elemento più vecchio se è stato sovrascritto
         if self.size == self.capacity {
             // buffer pieno
-            self.buffer[self.head] = Some(item);
+            let evicted = self.buffer[self.head].replace(item);
             self.head = (self.head + 1) % self.capacity;
             self.tail = (self.tail + 1) % self.capacity;
+            evicted
         } else {
             self.buffer[self.tail] = Some(item);
             self.tail = (self.tail + 1) % self.capacity;
             self.size += 1;
+            None
         }
     }
 
@@ -93,19 +97,211 @@ impl<T> CircularBuffer<T> {
             return;
         }
 
-        let mut new_buffer: Vec<Option<T>> = (0..self.capacity).map(|_| None).collect();
-        let mut new_index = 0;
+        // rotazione sul posto: head finisce in posizione 0 senza allocare un nuovo vettore
+        // (gli slot vuoti sono None, quindi possono essere spostati insieme agli elementi)
+        self.buffer.rotate_left(self.head);
+        self.head = 0;
+        self.tail = self.size % self.capacity;
+    }
+}
+
+// statistiche di utilizzo del buffer condiviso, per dimensionarlo sulle misure reali
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BufferStats {
+    pub writes: usize,
+    pub reads: usize,
+    pub overwrites: usize,     // elementi persi perché sovrascritti a buffer pieno
+    pub peak_occupancy: usize,
+    pub full_stalls: usize,    // scritture che hanno trovato il buffer pieno
+    pub empty_stalls: usize,   // letture che hanno trovato il buffer vuoto
+}
 
-        let mut current = self.head;
-        for _ in 0..self.size {
-            new_buffer[new_index] = self.buffer[current].take();
-            current = (current + 1) % self.capacity;
-            new_index += 1;
+// stato protetto dal mutex del buffer condiviso
+struct State<T> {
+    buffer: CircularBuffer<T>,
+    closed: bool, // dopo close() non si scrive più; le letture svuotano quanto rimasto
+    stats: Option<BufferStats>, // None se le statistiche non sono state richieste
+}
+
+impl<T> State<T> {
+    fn record(&mut self, update: impl FnOnce(&mut BufferStats)) {
+        let size = self.buffer.size;
+        if let Some(stats) = self.stats.as_mut() {
+            update(stats);
+            stats.peak_occupancy = stats.peak_occupancy.max(size);
         }
+    }
 
-        self.buffer = new_buffer;
-        self.head = 0;
-        self.tail = self.size % self.capacity;
+    fn lock_for_write(mutex: &Mutex<State<T>>) -> MutexGuard<'_, State<T>> {
+        let mut state = mutex.lock().unwrap();
+        if state.is_full() && !state.closed {
+            state.record(|s| s.full_stalls += 1);
+        }
+        state
+    }
+
+    fn lock_for_read(mutex: &Mutex<State<T>>) -> MutexGuard<'_, State<T>> {
+        let mut state = mutex.lock().unwrap();
+        if state.is_empty() && !state.closed {
+            state.record(|s| s.empty_stalls += 1);
+        }
+        state
+    }
+
+    fn is_full(&self) -> bool {
+        self.buffer.size == self.buffer.capacity
+    }
+
+    fn is_empty(&self) -> bool {
+        self.buffer.size == 0
+    }
+}
+
+// buffer condiviso tra thread: write attende se il buffer è pieno, read se è vuoto
+pub struct SharedCircularBuffer<T> {
+    inner: Arc<(Mutex<State<T>>, Condvar, Condvar)>, // (stato, non pieno, non vuoto)
+}
+
+impl<T> Clone for SharedCircularBuffer<T> {
+    fn clone(&self) -> Self {
+        SharedCircularBuffer { inner: self.inner.clone() }
+    }
+}
+
+impl<T> SharedCircularBuffer<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self::build(capacity, None)
+    }
+
+    pub fn with_stats(capacity: usize) -> Self {
+        // come new, ma registra le statistiche consultabili con stats()
+        Self::build(capacity, Some(BufferStats::default()))
+    }
+
+    fn build(capacity: usize, stats: Option<BufferStats>) -> Self {
+        let state = State { buffer: CircularBuffer::new(capacity), closed: false, stats };
+        SharedCircularBuffer {
+            inner: Arc::new((Mutex::new(state), Condvar::new(), Condvar::new())),
+        }
+    }
+
+    pub fn stats(&self) -> Option<BufferStats> {
+        self.inner.0.lock().unwrap().stats.clone()
+    }
+
+    pub fn write(&self, item: T) -> Result<(), Err> {
+        // Err::Closed se il buffer è stato chiuso (anche mentre si era in attesa)
+        let (state, not_full, not_empty) = &*self.inner;
+        let mut state = not_full
+            .wait_while(State::lock_for_write(state), |s| !s.closed && s.is_full())
+            .unwrap();
+        if state.closed {
+            return Err(Err::Closed);
+        }
+        state.buffer.write(item)?;
+        state.record(|s| s.writes += 1);
+        not_empty.notify_one();
+        Ok(())
+    }
+
+    pub fn read(&self) -> Result<T, Err> {
+        // Err::Closed quando il buffer è chiuso e non ci sono più elementi da leggere
+        let (state, not_full, not_empty) = &*self.inner;
+        let mut state = not_empty
+            .wait_while(State::lock_for_read(state), |s| !s.closed && s.is_empty())
+            .unwrap();
+        let item = state.buffer.read().ok_or(Err::Closed)?;
+        state.record(|s| s.reads += 1);
+        not_full.notify_one();
+        Ok(item)
+    }
+
+    pub fn try_write(&self, item: T) -> Result<(), Err> {
+        let (state, _, not_empty) = &*self.inner;
+        let mut state = State::lock_for_write(state);
+        if state.closed {
+            return Err(Err::Closed);
+        }
+        state.buffer.write(item)?;
+        state.record(|s| s.writes += 1);
+        not_empty.notify_one();
+        Ok(())
+    }
+
+    pub fn try_read(&self) -> Option<T> {
+        let (state, not_full, _) = &*self.inner;
+        let mut state = State::lock_for_read(state);
+        let item = state.buffer.read()?;
+        state.record(|s| s.reads += 1);
+        not_full.notify_one();
+        Some(item)
+    }
+
+    pub fn write_timeout(&self, item: T, timeout: Duration) -> Result<(), Err> {
+        // Err::Timeout se allo scadere il buffer è ancora pieno (come try_write, l'elemento viene scartato)
+        let (state, not_full, not_empty) = &*self.inner;
+        let (mut state, _) = not_full
+            .wait_timeout_while(State::lock_for_write(state), timeout, |s| !s.closed && s.is_full())
+            .unwrap();
+        if state.closed {
+            return Err(Err::Closed);
+        }
+        if state.is_full() {
+            return Err(Err::Timeout);
+        }
+        state.buffer.write(item)?;
+        state.record(|s| s.writes += 1);
+        not_empty.notify_one();
+        Ok(())
+    }
+
+    pub fn read_timeout(&self, timeout: Duration) -> Result<T, Err> {
+        // Err::Timeout se allo scadere il buffer è ancora vuoto
+        let (state, not_full, not_empty) = &*self.inner;
+        let (mut state, _) = not_empty
+            .wait_timeout_while(State::lock_for_read(state), timeout, |s| !s.closed && s.is_empty())
+            .unwrap();
+        let closed = state.closed;
+        let item = state.buffer.read().ok_or(if closed { Err::Closed } else { Err::Timeout })?;
+        state.record(|s| s.reads += 1);
+        not_full.notify_one();
+        Ok(item)
+    }
+
+    pub fn overwrite(&self, item: T) -> Result<Option<T>, Err> {
+        // non blocca mai: a buffer pieno sovrascrive l'elemento più vecchio (contato tra gli overwrites)
+        // e lo restituisce
+        let (state, _, not_empty) = &*self.inner;
+        let mut state = state.lock().unwrap();
+        if state.closed {
+            return Err(Err::Closed);
+        }
+        let evicted = state.buffer.overwrite(item);
+        let overwritten = evicted.is_some();
+        state.record(|s| {
+            s.writes += 1;
+            if overwritten {
+                s.overwrites += 1;
+            }
+        });
+        not_empty.notify_one();
+        Ok(evicted)
+    }
+
+    pub fn close(&self) {
+        // chiude il buffer: le scritture falliscono, i lettori ricevono gli elementi rimasti e poi Err::Closed
+        let (state, not_full, not_empty) = &*self.inner;
+        state.lock().unwrap().closed = true;
+        not_full.notify_all();
+        not_empty.notify_all();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.0.lock().unwrap().closed
+    }
+
+    pub fn size(&self) -> usize {
+        self.inner.0.lock().unwrap().buffer.size()
     }
 }
 
@@ -113,37 +309,28 @@ pub fn main_ex2() -> Result<String, Box<dyn std::error::Error>> {
     println!("------------------------------------------------");
 
     let mut handles = Vec::new();
-    let circ_buffer: SharedCircularBuffer<i32> = Arc::new(Mutex::new(CircularBuffer::new(100)));
+    let circ_buffer: SharedCircularBuffer<i32> = SharedCircularBuffer::new(100);
 
-    // Writer
+    // Writer: al termine chiude il buffer
     let buffer_clone = circ_buffer.clone();
     let join_handle = thread::spawn(move || {
-        loop {
-            let mut buffer = buffer_clone.lock().unwrap();
-            let res = buffer.write(42);
-            match res {
-                Ok(()) => { println!("wrote to the buffer"); }
-                Err(_) => { println!("error writing to buffer"); }
+        for _ in 0..5 {
+            if buffer_clone.write(42).is_ok() {
+                println!("wrote to the buffer");
             }
-            drop(buffer);
             thread::sleep(Duration::from_secs(2)); 
         }
+        buffer_clone.close();
     });
     handles.push(join_handle);
 
-    // Reader
+    // Reader: attende sul buffer vuoto invece di fare polling, termina alla chiusura
     let buffer_clone = circ_buffer.clone();
     let join_handle = thread::spawn(move || {
-        loop {
-            let mut buffer = buffer_clone.lock().unwrap();
-            let res = buffer.read();
-            match res {
-                Some(value) => {println!("value: {}", value)}
-                _ => {println!("empty buffer")}
-            }
-            drop(buffer);
-            thread::sleep(Duration::from_secs(1)); 
+        while let Ok(value) = buffer_clone.read() {
+            println!("value: {}", value);
         }
+        println!("buffer closed");
     });
     handles.push(join_handle);
 
@@ -157,3 +344,167 @@ pub fn main_ex2() -> Result<String, Box<dyn std::error::Error>> {
 
     Ok("END".to_string())
 }
+
+
+// -------------------- TESTS ----------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn make_contiguous_keeps_capacity_and_order() {
+        let mut buf = CircularBuffer::new(5);
+        for i in 1..=4 {
+            buf.write(i).unwrap();
+        }
+        for _ in 0..3 {
+            buf.read();
+        }
+        for i in 5..=7 {
+            buf.write(i).unwrap(); // wrap-around
+        }
+        buf.make_contiguous();
+        assert_eq!(buf.head, 0);
+        assert_eq!(buf.buffer.len(), 5);
+        buf.write(8).unwrap(); // tail corretto dopo la rotazione
+        assert_eq!(buf.write(9), Err(Err::Full));
+        assert_eq!((0..5).map(|_| buf.read().unwrap()).collect::<Vec<_>>(), vec![4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn overwrite_returns_the_evicted_element() {
+        let mut buf = CircularBuffer::new(2);
+        assert_eq!(buf.overwrite(1), None);
+        assert_eq!(buf.overwrite(2), None);
+        assert_eq!(buf.overwrite(3), Some(1));
+        assert_eq!(buf.overwrite(4), Some(2));
+        assert_eq!(buf.read(), Some(3));
+
+        let shared = SharedCircularBuffer::new(1);
+        assert_eq!(shared.overwrite(1), Ok(None));
+        assert_eq!(shared.overwrite(2), Ok(Some(1)));
+        shared.close();
+        assert_eq!(shared.overwrite(3), Err(Err::Closed));
+    }
+
+    #[test]
+    fn reader_and_writer_share_the_buffer() {
+        let buffer = SharedCircularBuffer::new(2);
+        let writer = buffer.clone();
+        let handle = thread::spawn(move || {
+            for i in 0..10 {
+                writer.write(i).unwrap(); // si blocca quando il buffer è pieno
+            }
+        });
+        let values: Vec<i32> = (0..10).map(|_| buffer.read().unwrap()).collect();
+        handle.join().unwrap();
+        assert_eq!(values, (0..10).collect::<Vec<_>>());
+        assert_eq!(buffer.size(), 0);
+    }
+
+    #[test]
+    fn read_waits_for_a_writer() {
+        let buffer = SharedCircularBuffer::new(1);
+        let writer = buffer.clone();
+        let start = Instant::now();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            writer.write(7).unwrap();
+        });
+        assert_eq!(buffer.read(), Ok(7));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn try_variants_do_not_block() {
+        let buffer = SharedCircularBuffer::new(1);
+        assert_eq!(buffer.try_read(), None);
+        assert_eq!(buffer.try_write(1), Ok(()));
+        assert_eq!(buffer.try_write(2), Err(Err::Full));
+        assert_eq!(buffer.try_read(), Some(1));
+    }
+
+    #[test]
+    fn timeout_variants_give_up() {
+        let buffer = SharedCircularBuffer::new(1);
+        assert_eq!(buffer.read_timeout(Duration::from_millis(20)), Err(Err::Timeout));
+        assert_eq!(buffer.write_timeout(1, Duration::from_millis(20)), Ok(()));
+        assert_eq!(buffer.write_timeout(2, Duration::from_millis(20)), Err(Err::Timeout));
+        assert_eq!(buffer.read_timeout(Duration::from_millis(20)), Ok(1));
+    }
+
+    #[test]
+    fn timeout_variants_succeed_when_the_peer_catches_up() {
+        let buffer = SharedCircularBuffer::new(1);
+        buffer.write(1).unwrap();
+        let reader = buffer.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(30));
+            reader.read()
+        });
+        assert_eq!(buffer.write_timeout(2, Duration::from_secs(5)), Ok(()));
+        assert_eq!(handle.join().unwrap(), Ok(1));
+        assert_eq!(buffer.read_timeout(Duration::from_secs(5)), Ok(2));
+    }
+
+    #[test]
+    fn close_wakes_blocked_readers_after_draining() {
+        let buffer = SharedCircularBuffer::new(4);
+        let reader = buffer.clone();
+        let handle = thread::spawn(move || {
+            let mut values = vec![];
+            while let Ok(value) = reader.read() {
+                values.push(value);
+            }
+            values
+        });
+        buffer.write(1).unwrap();
+        buffer.write(2).unwrap();
+        thread::sleep(Duration::from_millis(30)); // il lettore resta bloccato sul buffer vuoto
+        buffer.close();
+        assert_eq!(handle.join().unwrap(), vec![1, 2]);
+        assert!(buffer.is_closed());
+        assert_eq!(buffer.read(), Err(Err::Closed));
+        assert_eq!(buffer.read_timeout(Duration::from_secs(5)), Err(Err::Closed));
+    }
+
+    #[test]
+    fn close_rejects_writers() {
+        let buffer = SharedCircularBuffer::new(1);
+        buffer.write(1).unwrap();
+        let writer = buffer.clone();
+        let handle = thread::spawn(move || writer.write(2)); // bloccato sul buffer pieno
+        thread::sleep(Duration::from_millis(30));
+        buffer.close();
+        assert_eq!(handle.join().unwrap(), Err(Err::Closed));
+        assert_eq!(buffer.try_write(3), Err(Err::Closed));
+        assert_eq!(buffer.write_timeout(3, Duration::from_millis(10)), Err(Err::Closed));
+        assert_eq!(buffer.read(), Ok(1)); // gli elementi già scritti restano leggibili
+    }
+
+    #[test]
+    fn stats_track_traffic_and_stalls() {
+        let buffer = SharedCircularBuffer::with_stats(2);
+        assert_eq!(buffer.try_read(), None);
+        buffer.write(1).unwrap();
+        buffer.write(2).unwrap();
+        assert_eq!(buffer.try_write(3), Err(Err::Full));
+        assert_eq!(buffer.overwrite(3), Ok(Some(1)));
+        assert_eq!(buffer.read(), Ok(2));
+        assert_eq!(buffer.read_timeout(Duration::from_millis(10)), Ok(3));
+        assert_eq!(
+            buffer.stats(),
+            Some(BufferStats {
+                writes: 3,
+                reads: 2,
+                overwrites: 1,
+                peak_occupancy: 2,
+                full_stalls: 1,
+                empty_stalls: 1,
+            })
+        );
+        assert_eq!(SharedCircularBuffer::<i32>::new(2).stats(), None);
+    }
+}
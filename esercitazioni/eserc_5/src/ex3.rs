@@ -1,21 +1,117 @@
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
 use std::thread;
+use std::time::{Duration, Instant};
 
 struct CyclicBarrier {
     state: Arc<(Mutex<BarrierState>, Condvar)>,
-    parties: usize, // numero totale di thread che devono aspettare
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WaitResult {
+    pub arrival_index: usize, // 0 per il primo thread arrivato nella generazione
+    is_leader: bool,
+}
+
+impl WaitResult {
+    pub fn is_leader(&self) -> bool {
+        // vero per l'ultimo thread arrivato, quello che sblocca gli altri
+        self.is_leader
+    }
 }
 
 struct BarrierState {
+    parties: usize, // numero totale di thread che devono aspettare (varia con register/deregister)
     count: usize, // thread mancanti
     generation: usize, // numero di barriere superate
+    wakers: Vec<Waker>, // task async in attesa sulla generazione corrente
+    arrivals: Vec<Instant>, // istanti di arrivo nella generazione corrente
+    stats: BarrierStats,
+}
+
+// statistiche cumulative sulle generazioni completate, per misurare lo sbilanciamento tra le fasi
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BarrierStats {
+    pub generations: usize,
+    pub total_wait: Duration, // somma dei tempi di attesa di tutti i partecipanti
+    pub last_spread: Duration, // tra il primo e l'ultimo arrivo dell'ultima generazione
+    pub max_spread: Duration,
+}
+
+impl BarrierState {
+    fn trip(&mut self, cvar: &Condvar) {
+        // tutti arrivati: si apre la barriera e si riparte con una nuova generazione
+        let now = Instant::now();
+        if let Some(first) = self.arrivals.first() {
+            let spread = now - *first;
+            self.stats.last_spread = spread;
+            self.stats.max_spread = self.stats.max_spread.max(spread);
+        }
+        self.stats.total_wait += self.arrivals.drain(..).map(|t| now - t).sum::<Duration>();
+        self.stats.generations += 1;
+        self.count = self.parties;
+        self.generation += 1;
+        cvar.notify_all();
+        for waker in self.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+
+    fn arrive(&mut self, cvar: &Condvar) -> WaitResult {
+        // registra l'arrivo di un partecipante (thread o task); l'ultimo apre la barriera
+        self.count -= 1;
+        self.arrivals.push(Instant::now());
+        let arrival_index = self.parties - self.count - 1;
+        let is_leader = self.count == 0;
+        if is_leader {
+            self.trip(cvar);
+        }
+        WaitResult { arrival_index, is_leader }
+    }
+}
+
+// future restituito da wait_async: al primo poll conta l'arrivo (anche se poi viene droppato),
+// poi resta pendente finché la generazione non cambia
+pub struct BarrierWait {
+    barrier: CyclicBarrier,
+    arrived: Option<(usize, WaitResult)>, // generazione di arrivo ed esito
+}
+
+impl Future for BarrierWait {
+    type Output = WaitResult;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<WaitResult> {
+        let (lock, cvar) = &*self.barrier.state;
+        let mut state = lock.lock().unwrap();
+        let (gen, result) = match self.arrived {
+            Some(arrived) => arrived,
+            None => {
+                let gen = state.generation;
+                let result = state.arrive(cvar);
+                if result.is_leader() {
+                    return Poll::Ready(result);
+                }
+                (gen, result)
+            }
+        };
+        if state.generation != gen {
+            return Poll::Ready(result);
+        }
+        if !state.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            state.wakers.push(cx.waker().clone());
+        }
+        drop(state);
+        self.arrived = Some((gen, result));
+        Poll::Pending
+    }
 }
 
 impl Clone for CyclicBarrier {
     fn clone(&self) -> Self {
         Self {
             state: Arc::clone(&self.state),
-            parties: self.parties,
         }
     }
 }
@@ -24,31 +120,78 @@ impl CyclicBarrier {
     fn new(n: usize) -> Self {
         Self {
             state: Arc::new((
-                Mutex::new(BarrierState { count: n, generation: 0 }),
+                Mutex::new(BarrierState {
+                    parties: n,
+                    count: n,
+                    generation: 0,
+                    wakers: Vec::new(),
+                    arrivals: Vec::new(),
+                    stats: BarrierStats::default(),
+                }),
                 Condvar::new(),
             )),
-            parties: n,
         }
     }
 
-    fn wait(&self) {
+    fn register(&self) -> usize {
+        // un nuovo partecipante: va atteso già dalla generazione corrente
+        let mut state = self.state.0.lock().unwrap();
+        state.parties += 1;
+        state.count += 1;
+        state.parties
+    }
+
+    fn deregister(&self) -> usize {
+        // un partecipante lascia il gruppo: se era l'unico mancante, la barriera si apre
         let (lock, cvar) = &*self.state;
         let mut state = lock.lock().unwrap();
-        let gen = state.generation;
-
+        assert!(state.parties > 0, "no registered parties");
+        state.parties -= 1;
         state.count -= 1;
+        if state.count == 0 && state.parties > 0 {
+            state.trip(cvar);
+        } else if state.parties == 0 {
+            state.count = 0;
+        }
+        state.parties
+    }
 
-        if state.count == 0 {
-            // reset
-            state.count = self.parties;
-            state.generation += 1;
-            cvar.notify_all();
-        } else {
+    fn parties(&self) -> usize {
+        self.state.0.lock().unwrap().parties
+    }
+
+    fn generation(&self) -> usize {
+        self.state.0.lock().unwrap().generation
+    }
+
+    fn waiting(&self) -> usize {
+        // partecipanti già arrivati nella generazione corrente
+        let state = self.state.0.lock().unwrap();
+        state.parties - state.count
+    }
+
+    fn stats(&self) -> BarrierStats {
+        self.state.0.lock().unwrap().stats.clone()
+    }
+
+    fn wait(&self) -> WaitResult {
+        let (lock, cvar) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        let gen = state.generation;
+
+        let result = state.arrive(cvar);
+        if !result.is_leader() {
             // aspetta fino alla prossima barriera
-            state = cvar
+            let _state = cvar
                 .wait_while(state, |s| s.generation == gen)
                 .unwrap();
         }
+        result
+    }
+
+    fn wait_async(&self) -> BarrierWait {
+        // stessa generazione della wait sincrona: thread e task possono condividere la barriera
+        BarrierWait { barrier: self.clone(), arrived: None }
     }
 }
 
@@ -61,7 +204,9 @@ pub fn main_ex3() -> Result<String, Box<dyn std::error::Error>> {
         vt.push(thread::spawn(move || {
             for j in 0..3 {
                 println!("Thread {} before barrier {}", i, j);
-                b.wait();
+                if b.wait().is_leader() {
+                    println!("Thread {} closed barrier {}", i, j);
+                }
                 println!("Thread {} after  barrier {}", i, j);
             }
         }));
@@ -73,3 +218,149 @@ pub fn main_ex3() -> Result<String, Box<dyn std::error::Error>> {
 
     Ok("OK".to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_generation_has_one_leader_and_distinct_indices() {
+        let barrier = CyclicBarrier::new(4);
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let b = barrier.clone();
+                thread::spawn(move || (0..10).map(|_| b.wait()).collect::<Vec<_>>())
+            })
+            .collect();
+        let results: Vec<Vec<WaitResult>> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+        for round in 0..10 {
+            let mut indices: Vec<usize> = results.iter().map(|r| r[round].arrival_index).collect();
+            indices.sort();
+            assert_eq!(indices, vec![0, 1, 2, 3]);
+            let leaders: Vec<_> = results.iter().filter(|r| r[round].is_leader()).collect();
+            assert_eq!(leaders.len(), 1);
+            assert_eq!(leaders[0][round].arrival_index, 3);
+        }
+    }
+
+    #[test]
+    fn parties_can_join_and_leave_between_phases() {
+        let barrier = CyclicBarrier::new(2);
+        let leaving = {
+            let b = barrier.clone();
+            thread::spawn(move || {
+                b.wait();
+                b.deregister();
+            })
+        };
+        barrier.wait();
+        leaving.join().unwrap();
+        assert_eq!(barrier.parties(), 1);
+
+        // da solo la barriera non blocca più
+        assert!(barrier.wait().is_leader());
+
+        assert_eq!(barrier.register(), 2);
+        let joining = {
+            let b = barrier.clone();
+            thread::spawn(move || b.wait())
+        };
+        let mine = barrier.wait();
+        let theirs = joining.join().unwrap();
+        assert!(mine.is_leader() != theirs.is_leader());
+    }
+
+    #[test]
+    fn deregister_releases_waiters_of_the_current_generation() {
+        let barrier = CyclicBarrier::new(3);
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let b = barrier.clone();
+                thread::spawn(move || b.wait())
+            })
+            .collect();
+        thread::sleep(std::time::Duration::from_millis(20));
+        // il terzo partecipante rinuncia: i due in attesa vengono sbloccati
+        barrier.deregister();
+        for h in handles {
+            h.join().unwrap();
+        }
+        assert_eq!(barrier.parties(), 2);
+    }
+
+    // esecutore minimo per i test: parcheggia il thread finché il waker non lo risveglia
+    fn block_on<F: Future>(future: F) -> F::Output {
+        struct ThreadWaker(thread::Thread);
+        impl std::task::Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    #[test]
+    fn async_and_sync_waiters_share_generations() {
+        let barrier = CyclicBarrier::new(3);
+        let handles: Vec<_> = (0..2)
+            .map(|i| {
+                let b = barrier.clone();
+                thread::spawn(move || {
+                    (0..5)
+                        .map(|_| if i == 0 { block_on(b.wait_async()) } else { b.wait() })
+                        .filter(|r| r.is_leader())
+                        .count()
+                })
+            })
+            .collect();
+        let mut leaders = (0..5).filter(|_| block_on(barrier.wait_async()).is_leader()).count();
+        for h in handles {
+            leaders += h.join().unwrap();
+        }
+        assert_eq!(leaders, 5);
+    }
+
+    #[test]
+    fn stats_measure_phase_imbalance() {
+        let barrier = CyclicBarrier::new(2);
+        let slow = {
+            let b = barrier.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                b.wait();
+            })
+        };
+        assert_eq!(barrier.generation(), 0);
+        barrier.wait();
+        slow.join().unwrap();
+
+        assert_eq!(barrier.generation(), 1);
+        assert_eq!(barrier.waiting(), 0);
+        let stats = barrier.stats();
+        assert_eq!(stats.generations, 1);
+        assert!(stats.last_spread >= Duration::from_millis(40));
+        assert_eq!(stats.max_spread, stats.last_spread);
+        assert!(stats.total_wait >= stats.last_spread);
+
+        let waiter = {
+            let b = barrier.clone();
+            thread::spawn(move || b.wait())
+        };
+        while barrier.waiting() == 0 {
+            thread::yield_now();
+        }
+        assert_eq!(barrier.waiting(), 1);
+        barrier.wait();
+        waiter.join().unwrap();
+        assert_eq!(barrier.stats().generations, 2);
+    }
+}
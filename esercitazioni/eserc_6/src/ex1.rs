@@ -1,15 +1,41 @@
-use std::sync::mpsc::{channel, Sender, Receiver};
+use std::cell::Cell;
+use std::sync::mpsc::{channel, Sender, Receiver, RecvTimeoutError};
+use std::sync::Mutex;
 use std::thread;
+use std::time::{Duration, Instant};
+
+// messaggi scambiati tra i waiter, etichettati con la generazione della barriera
+#[derive(Clone, Copy)]
+enum Signal {
+    Arrived(u64),
+    Broken(u64), // un partecipante è andato in timeout: la generazione è rotta
+}
+
+#[derive(Debug, PartialEq)]
+pub struct BarrierTimeout;
+
+impl std::fmt::Display for BarrierTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "barrier generation broken by a timeout")
+    }
+}
+
+impl std::error::Error for BarrierTimeout {}
 
 pub struct CyclicBarrier {
-    n: usize,
-    senders: Vec<Sender<()>>,
-    receivers: Vec<Option<Receiver<()>>>, // ogni receiver verrà "consumato" con take()
+    senders: Vec<Sender<Signal>>,
+    // receiver non ancora assegnati, con l'indice del loro canale: waiter() li consuma uno alla volta
+    unclaimed: Mutex<Vec<(usize, Receiver<Signal>)>>,
 }
 
 pub struct Waiter {
-    my_receiver: Receiver<()>,
-    my_senders: Vec<Sender<()>>,
+    my_receiver: Receiver<Signal>,
+    my_senders: Vec<Sender<Signal>>,
+    generation: Cell<u64>,
+    // un altro thread può essere già alla generazione successiva mentre noi riceviamo ancora
+    // i messaggi di quella corrente: i suoi segnali vengono messi da parte qui
+    early_arrivals: Cell<usize>,
+    early_broken: Cell<bool>,
 }
 
 impl CyclicBarrier {
@@ -17,67 +43,135 @@ impl CyclicBarrier {
         assert!(n > 0, "CyclicBarrier size must be > 0");
 
         let mut senders = Vec::with_capacity(n);
-        let mut receivers: Vec<Option<Receiver<()>>> = Vec::with_capacity(n);
+        let mut unclaimed = Vec::with_capacity(n);
 
         // Crea n canali indipendenti (ognuno ha un receiver dedicato a un thread)
-        for _ in 0..n {
+        for id in 0..n {
             let (tx, rx) = channel();
             senders.push(tx);
-            receivers.push(Some(rx)); 
+            unclaimed.push((id, rx));
         }
+        // waiter() li estrae dal fondo: invertendo, il primo waiter usa il canale 0
+        unclaimed.reverse();
 
-        CyclicBarrier { n, senders, receivers }
+        CyclicBarrier { senders, unclaimed: Mutex::new(unclaimed) }
     }
 
-    // Restituisce il Waiter per l'indice `id` spostando il suo Receiver
-    pub fn get_waiter(&mut self, id: usize) -> Waiter {
-        assert!(id < self.n, "waiter id out of range");
+    // Crea la barriera e restituisce subito tutti gli n waiter
+    pub fn with_waiters(n: usize) -> Vec<Waiter> {
+        let barrier = CyclicBarrier::new(n);
+        (0..n).map(|_| barrier.waiter().expect("n waiters available")).collect()
+    }
 
-        // Sposta (move) il receiver fuori dal vettore; fallisce se già preso
-        let my_receiver = self.receivers[id]
-            .take()
-            .expect("Waiter already taken for this id");
+    // Restituisce il prossimo Waiter libero, o None se sono già stati presi tutti gli n
+    pub fn waiter(&self) -> Option<Waiter> {
+        // Sposta (move) un receiver non ancora assegnato fuori dal vettore
+        let (id, my_receiver) = self.unclaimed.lock().unwrap().pop()?;
 
         // Colleziona tutti i sender verso gli ALTRI thread (n-1)
-        let mut my_senders = Vec::with_capacity(self.n - 1);
+        let mut my_senders = Vec::with_capacity(self.senders.len() - 1);
         for (j, s) in self.senders.iter().enumerate() {
             if j != id {
                 my_senders.push(s.clone());
             }
         }
 
-        Waiter { my_receiver, my_senders }
+        Some(Waiter {
+            my_receiver,
+            my_senders,
+            generation: Cell::new(0),
+            early_arrivals: Cell::new(0),
+            early_broken: Cell::new(false),
+        })
+    }
+
+    pub fn remaining_waiters(&self) -> usize {
+        self.unclaimed.lock().unwrap().len()
     }
 }
 
 impl Waiter {
-    pub fn wait(&self) {
+    pub fn wait(&self) -> Result<(), BarrierTimeout> {
+        // senza scadenza: fallisce solo se un altro partecipante va in timeout
+        self.wait_until(None)
+    }
+
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<(), BarrierTimeout> {
+        self.wait_until(Some(Instant::now() + timeout))
+    }
+
+    fn wait_until(&self, deadline: Option<Instant>) -> Result<(), BarrierTimeout> {
+        let generation = self.generation.get();
+        // in ogni caso si passa alla generazione successiva
+        self.generation.set(generation + 1);
+
         // 1) segnala a tutti gli altri thread
         for s in &self.my_senders {
-            // Se un thread è morto, send può fallire: qui ignoriamo l'errore e lasciamo che recv blocchi;
-            // in una versione robusta potresti gestire l'errore e abortire.
-            let _ = s.send(());
+            // Se un thread è morto, send può fallire: qui ignoriamo l'errore e lasciamo che recv blocchi
+            // (o vada in timeout)
+            let _ = s.send(Signal::Arrived(generation));
+        }
+
+        // segnali della generazione corrente arrivati in anticipo
+        let mut arrived = self.early_arrivals.replace(0);
+        if self.early_broken.replace(false) {
+            return Err(BarrierTimeout);
         }
 
         // 2) attende n-1 segnali sul proprio receiver
-        for _ in 0..self.my_senders.len() {
-            // Se un mittente è chiuso e non arriveranno abbastanza messaggi, qui si bloccherebbe per sempre.
-            // È il comportamento atteso di una barriera: se qualcuno non arriva, gli altri restano in attesa.
-            let _ = self.my_receiver.recv();
+        while arrived < self.my_senders.len() {
+            let signal = match deadline {
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    match self.my_receiver.recv_timeout(left) {
+                        Ok(signal) => signal,
+                        Err(RecvTimeoutError::Timeout) => return Err(self.break_generation(generation)),
+                        Err(RecvTimeoutError::Disconnected) => return Err(BarrierTimeout),
+                    }
+                }
+                // Se nessuno arriva e nessuno va in timeout, qui si resta bloccati:
+                // è il comportamento atteso di una barriera senza scadenza.
+                None => self.my_receiver.recv().map_err(|_| BarrierTimeout)?,
+            };
+            self.record(signal, generation, &mut arrived)?;
+        }
+
+        // chi arriva in ritardo trova in coda gli Arrived degli altri seguiti dai loro Broken:
+        // contare gli Arrived non basta, se qualcuno ha rotto la generazione è fallita anche per noi
+        while let Ok(signal) = self.my_receiver.try_recv() {
+            self.record(signal, generation, &mut arrived)?;
+        }
+        Ok(())
+    }
+
+    fn record(&self, signal: Signal, generation: u64, arrived: &mut usize) -> Result<(), BarrierTimeout> {
+        match signal {
+            Signal::Arrived(g) if g == generation => *arrived += 1,
+            Signal::Arrived(g) if g > generation => self.early_arrivals.set(self.early_arrivals.get() + 1),
+            Signal::Broken(g) if g == generation => return Err(BarrierTimeout),
+            Signal::Broken(g) if g > generation => self.early_broken.set(true),
+            _ => {} // residui di una generazione rotta
+        }
+        Ok(())
+    }
+
+    fn break_generation(&self, generation: u64) -> BarrierTimeout {
+        // sblocca gli altri partecipanti ancora in attesa su questa generazione
+        for s in &self.my_senders {
+            let _ = s.send(Signal::Broken(generation));
         }
+        BarrierTimeout
     }
 }
 
 // Barriera ciclica con canali
 pub fn main_ex1() -> Result<String, Box<dyn std::error::Error>> {
-    let mut cbarrier = CyclicBarrier::new(3);
     let mut vt = Vec::new();
 
-    for i in 0..3 {
-        let waiter = cbarrier.get_waiter(i);
+    for (i, waiter) in CyclicBarrier::with_waiters(3).into_iter().enumerate() {
         vt.push(thread::spawn(move || {
             for j in 0..10 {
-                waiter.wait();
+                waiter.wait().expect("barrier broken");
                 println!("after barrier {} {}", i, j);
             }
         }));
@@ -90,3 +184,77 @@ pub fn main_ex1() -> Result<String, Box<dyn std::error::Error>> {
 
     Ok("OK".to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waiters_pass_together_for_many_generations() {
+        let handles: Vec<_> = CyclicBarrier::with_waiters(3)
+            .into_iter()
+            .map(|waiter| {
+                thread::spawn(move || {
+                    for _ in 0..50 {
+                        waiter.wait_timeout(Duration::from_secs(5)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn missing_participant_breaks_the_generation() {
+        let barrier = CyclicBarrier::new(3);
+        let late = barrier.waiter().unwrap();
+        let handles: Vec<_> = (0..2)
+            .map(|i| {
+                let waiter = barrier.waiter().unwrap();
+                thread::spawn(move || {
+                    // il primo va in timeout, il secondo viene sbloccato dalla generazione rotta
+                    let timeout = Duration::from_millis(if i == 0 { 50 } else { 5000 });
+                    let start = Instant::now();
+                    let result = waiter.wait_timeout(timeout);
+                    (result, start.elapsed())
+                })
+            })
+            .collect();
+        for h in handles {
+            let (result, elapsed) = h.join().unwrap();
+            assert_eq!(result, Err(BarrierTimeout));
+            assert!(elapsed < Duration::from_secs(1));
+        }
+        drop(late);
+    }
+
+    #[test]
+    fn a_late_participant_sees_the_broken_generation() {
+        let mut waiters = CyclicBarrier::with_waiters(3);
+        let late = waiters.pop().unwrap();
+        let handles: Vec<_> = waiters
+            .into_iter()
+            .map(|waiter| thread::spawn(move || waiter.wait_timeout(Duration::from_millis(50))))
+            .collect();
+        for h in handles {
+            assert_eq!(h.join().unwrap(), Err(BarrierTimeout));
+        }
+        // gli Arrived degli altri due sono in coda, ma prima di scadere hanno rotto la generazione
+        assert_eq!(late.wait_timeout(Duration::from_secs(1)), Err(BarrierTimeout));
+    }
+
+    #[test]
+    fn waiter_hands_out_at_most_n_handles() {
+        let barrier = CyclicBarrier::new(2);
+        let first = barrier.waiter().unwrap();
+        let second = barrier.waiter().unwrap();
+        assert!(barrier.waiter().is_none());
+        assert_eq!(barrier.remaining_waiters(), 0);
+
+        let h = thread::spawn(move || first.wait_timeout(Duration::from_secs(5)));
+        assert_eq!(second.wait_timeout(Duration::from_secs(5)), Ok(()));
+        assert_eq!(h.join().unwrap(), Ok(()));
+    }
+}
@@ -1,104 +1,881 @@
+use std::cmp::Ordering;
+use std::any::Any;
+use std::cell::Cell;
+use std::collections::{BinaryHeap, HashMap};
+use std::marker::PhantomData;
+use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering as AtomicOrdering};
 use std::thread;
-use std::time::Duration;
-use std::sync::mpsc::{channel, Sender, Receiver};
+use std::time::{Duration, Instant};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::panic::{self, AssertUnwindSafe};
+use cancelable_latch::{CancelableLatch, Counter};
 
 type Job = Box<dyn FnOnce() + Send + 'static>;
 
+// numero di job inviati ma non ancora assegnati a un worker, con la condvar per chi attende spazio
+type Pending = Arc<(Mutex<usize>, Condvar)>;
+
+// ogni livello di priorità vale PRIORITY_AGING job di vantaggio: un job a bassa priorità
+// viene superato al massimo da 2 * PRIORITY_AGING job arrivati dopo di lui, poi passa comunque
+const PRIORITY_AGING: u64 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    High,
+    Normal,
+    Low,
+}
+
+// job in coda, ordinato per "scadenza virtuale": numero di arrivo + ritardo dovuto alla priorità
+struct Queued {
+    deadline: u64,
+    arrival: u64, // a parità di scadenza vince chi è arrivato prima
+    submitted: Instant,
+    job: Job,
+}
+
+impl PartialEq for Queued {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Queued {}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // invertito: BinaryHeap è un max-heap, a noi serve la scadenza minore
+        (other.deadline, other.arrival).cmp(&(self.deadline, self.arrival))
+    }
+}
+
+// limiti superiori (esclusi) dei bucket dell'istogramma dei tempi di attesa; l'ultimo bucket è ">= 1s"
+pub const WAIT_BUCKETS_MILLIS: [u64; 4] = [1, 10, 100, 1000];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PoolStats {
+    pub queued: usize,          // job in coda in attesa di un worker
+    pub workers: usize,
+    pub active_workers: usize,  // worker che stanno eseguendo un job
+    pub completed: usize,
+    pub panicked: usize,
+    pub busy_time: Duration,    // tempo totale di esecuzione dei job completati
+    pub wait_histogram: [usize; WAIT_BUCKETS_MILLIS.len() + 1],
+}
+
+fn wait_bucket(wait: Duration) -> usize {
+    let millis = wait.as_millis();
+    WAIT_BUCKETS_MILLIS
+        .iter()
+        .position(|&limit| millis < limit as u128)
+        .unwrap_or(WAIT_BUCKETS_MILLIS.len())
+}
+
+// chiamata con l'id del worker e il messaggio quando un job va in panic
+type PanicHook = dyn Fn(usize, &str) + Send;
+
 pub struct ThreadPool {
-    event_tx: Sender<Events>,
-    handles: Vec<thread::JoinHandle<()>>,
+    shared: Arc<Shared>,
+}
+
+// niente scheduler centrale: ogni worker ha la sua coda, così chi sottomette e chi esegue non
+// contendono un unico lock, e prende il job migliore fra le teste di tutte le code (la sua e quelle
+// degli altri). Il lock di `control` serve solo per dormire, svegliare, ridimensionare e fermare
+// il pool, non per ogni job
+struct Shared {
+    id: usize, // distingue i pool nella thread-local WORKER
+    threads: ThreadConfig,
+    queues: RwLock<Vec<Arc<WorkerQueue>>>,
+    next: AtomicUsize,     // round robin per i job sottomessi da fuori
+    arrivals: AtomicU64,   // numero di arrivo, per l'ordine FIFO e l'aging delle priorità
+    queued: AtomicUsize,   // job nelle code non ancora presi da un worker
+    // copie di campi di Control, lette senza lock da execute e dai worker
+    idle: AtomicUsize,
+    live: AtomicUsize,
+    ceiling: AtomicUsize,
+    closed: AtomicBool, // il pool non accetta più job da fuori
+    pending: Pending,
+    max_pending: Option<usize>, // None = coda illimitata
+    control: Mutex<Control>,
+    wake: Condvar,    // worker inattivi in attesa di lavoro, di un resize o dello stop
+    retired: Condvar, // set_workers attende che i worker in eccesso si ritirino
+    panic_hook: Mutex<Option<Box<PanicHook>>>,
+    counters: Counters,
+}
+
+// coda di un worker, ordinata per scadenza virtuale come la coda centrale di prima
+struct WorkerQueue {
+    worker: usize,
+    jobs: Mutex<BinaryHeap<Queued>>,
+}
+
+struct Control {
+    workers: HashMap<usize, thread::JoinHandle<()>>,
+    finished: Vec<thread::JoinHandle<()>>, // worker ritirati, ancora da joinare
+    next_id: usize,
+    target: usize, // numero di worker di base (set_workers)
+    max_workers: Option<usize>, // tetto per i worker extra creati sotto carico (None = nessun extra)
+    idle: usize, // worker addormentati sulla condvar wake
+    shutting_down: bool,
+}
+
+impl Control {
+    fn ceiling(&self) -> usize {
+        // senza max_workers esplicito il tetto segue set_workers, anche quando riduce il pool
+        self.max_workers.map_or(self.target, |max| max.max(self.target))
+    }
+}
+
+// statistiche aggiornate dai worker senza passare da un lock
+#[derive(Default)]
+struct Counters {
+    active: AtomicUsize,
+    completed: AtomicUsize,
+    panicked: AtomicUsize,
+    busy_nanos: AtomicU64,
+    wait_histogram: [AtomicUsize; WAIT_BUCKETS_MILLIS.len() + 1],
+}
+
+static NEXT_POOL_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // (id del pool, id del worker) se il thread corrente è un worker di un ThreadPool
+    static WORKER: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PoolError {
+    ShutDown, // il pool è stato fermato e non accetta più job
+    QueueFull, // raggiunto il massimo di job in attesa (solo try_execute)
+}
+
+impl std::fmt::Display for PoolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PoolError::ShutDown => write!(f, "thread pool is shut down"),
+            PoolError::QueueFull => write!(f, "thread pool queue is full"),
+        }
+    }
+}
+
+impl std::error::Error for PoolError {}
+
+#[derive(Debug, PartialEq)]
+pub enum JobError {
+    Panicked(String), // messaggio del panic del job
+    Timeout,
+    Canceled,         // job scartato prima di essere eseguito (es. stop_now)
+}
+
+impl std::fmt::Display for JobError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobError::Panicked(msg) => write!(f, "job panicked: {}", msg),
+            JobError::Timeout => write!(f, "timed out waiting for job"),
+            JobError::Canceled => write!(f, "job was canceled"),
+        }
+    }
+}
+
+impl std::error::Error for JobError {}
+
+pub struct JobHandle<R> {
+    result_rx: Receiver<Result<R, JobError>>,
+}
+
+impl<R> JobHandle<R> {
+    pub fn wait(self) -> Result<R, JobError> {
+        // se il sender è stato droppato senza risultato il job non è mai stato eseguito
+        self.result_rx.recv().unwrap_or(Err(JobError::Canceled))
+    }
+
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<R, JobError> {
+        match self.result_rx.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => Err(JobError::Timeout),
+            Err(RecvTimeoutError::Disconnected) => Err(JobError::Canceled),
+        }
+    }
+}
+
+fn chunked<T>(items: impl IntoIterator<Item = T>, size: usize) -> Vec<Vec<T>> {
+    let mut chunks = Vec::new();
+    let mut current = Vec::with_capacity(size);
+    for item in items {
+        current.push(item);
+        if current.len() == size {
+            chunks.push(std::mem::replace(&mut current, Vec::with_capacity(size)));
+        }
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+fn release(pending: &Pending, n: usize) {
+    // libera n posti in coda e sveglia chi è bloccato in execute
+    let (count, space) = &**pending;
+    *count.lock().unwrap() -= n;
+    space.notify_all();
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+// chiamata con l'id del worker all'avvio / alla terminazione del suo thread
+type ThreadHook = dyn Fn(usize) + Send + Sync;
+
+pub struct ThreadPoolBuilder {
+    workers: usize,
+    max_workers: Option<usize>,
+    max_pending: Option<usize>,
+    threads: ThreadConfig,
+}
+
+// configurazione dei thread worker, condivisa da tutti i worker creati dal pool
+#[derive(Clone, Default)]
+struct ThreadConfig {
+    name_prefix: Option<String>,
+    stack_size: Option<usize>,
+    on_start: Option<Arc<ThreadHook>>,
+    on_stop: Option<Arc<ThreadHook>>,
+    keep_alive: Option<Duration>, // dopo quanto un worker extra inattivo si ritira
+}
+
+impl ThreadPoolBuilder {
+    pub fn new(workers: usize) -> Self {
+        ThreadPoolBuilder { workers, max_workers: None, max_pending: None, threads: ThreadConfig::default() }
+    }
+
+    pub fn max_workers(mut self, max_workers: usize) -> Self {
+        // oltre ai `workers` di base, execute crea worker extra su richiesta fino a max_workers
+        self.max_workers = Some(max_workers);
+        self
+    }
+
+    pub fn keep_alive(mut self, idle: Duration) -> Self {
+        // i worker extra inattivi da più di `idle` vengono terminati (senza, restano fino allo stop)
+        self.threads.keep_alive = Some(idle);
+        self
+    }
+
+    pub fn max_pending(mut self, max_pending: usize) -> Self {
+        self.max_pending = Some(max_pending);
+        self
+    }
+
+    pub fn name_prefix(mut self, prefix: &str) -> Self {
+        // i thread si chiameranno "<prefix>-<id>"
+        self.threads.name_prefix = Some(prefix.to_string());
+        self
+    }
+
+    pub fn stack_size(mut self, bytes: usize) -> Self {
+        self.threads.stack_size = Some(bytes);
+        self
+    }
+
+    pub fn on_thread_start<F: Fn(usize) + Send + Sync + 'static>(mut self, hook: F) -> Self {
+        self.threads.on_start = Some(Arc::new(hook));
+        self
+    }
+
+    pub fn on_thread_stop<F: Fn(usize) + Send + Sync + 'static>(mut self, hook: F) -> Self {
+        self.threads.on_stop = Some(Arc::new(hook));
+        self
+    }
+
+    pub fn build(self) -> ThreadPool {
+        ThreadPool::build(self)
+    }
+}
+
+// latch di execute_batch: si attende che tutti i job del batch terminino con successo,
+// oppure si viene sbloccati subito se uno di essi va in panic
+pub type BatchLatch = Arc<Counter>;
+
+// job di uno scope: può prendere in prestito dati che vivono almeno quanto 'env
+type ScopedJob<'env> = Box<dyn FnOnce() + Send + 'env>;
+
+// job di uno scope non ancora terminati, più il primo panic da rilanciare a chi ha aperto lo scope
+#[derive(Default)]
+struct ScopeState {
+    running: Mutex<usize>,
+    done: Condvar,
+    panic: Mutex<Option<Box<dyn Any + Send>>>,
+}
+
+impl ScopeState {
+    fn wait(&self) {
+        let running = self.running.lock().unwrap();
+        let _running = self.done.wait_while(running, |r| *r > 0).unwrap();
+    }
+}
+
+// posto occupato da un job dello scope: si libera quando il job termina o viene scartato
+struct ScopeSlot(Arc<ScopeState>);
+
+impl Drop for ScopeSlot {
+    fn drop(&mut self) {
+        let mut running = self.0.running.lock().unwrap();
+        *running -= 1;
+        if *running == 0 {
+            self.0.done.notify_all();
+        }
+    }
+}
+
+// i campi sono distrutti nell'ordine di dichiarazione: il job (con i suoi prestiti) prima dello slot
+struct ScopedTask<F> {
+    job: F,
+    slot: ScopeSlot,
+}
+
+impl<F: FnOnce()> ScopedTask<F> {
+    fn run(self) {
+        let ScopedTask { job, slot } = self;
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(job)) {
+            slot.0.panic.lock().unwrap().get_or_insert(payload);
+        }
+    }
+}
+
+// attende i job dello scope anche se la closure passata a scope() va in panic
+struct ScopeGuard<'a>(&'a ScopeState);
+
+impl Drop for ScopeGuard<'_> {
+    fn drop(&mut self) {
+        self.0.wait();
+    }
+}
+
+pub struct Scope<'env> {
+    pool: &'env ThreadPool,
+    state: Arc<ScopeState>,
+    // rende 'env invariante: la closure di scope() non può accorciarlo per prestare ai job i suoi dati locali
+    _env: PhantomData<Cell<&'env mut ()>>,
+}
+
+impl<'env> Scope<'env> {
+    pub fn execute<F: FnOnce() + Send + 'env>(&self, job: F) {
+        *self.state.running.lock().unwrap() += 1;
+        let task = ScopedTask { job, slot: ScopeSlot(self.state.clone()) };
+        let job: ScopedJob<'env> = Box::new(move || task.run());
+        // SAFETY: scope() non ritorna, nemmeno in caso di panic, finché lo slot di ogni job non è
+        // stato rilasciato, cioè finché il job non è terminato o è stato scartato: i dati presi in
+        // prestito per 'env restano validi per tutto il tempo in cui un worker può usarli
+        let job = unsafe { mem::transmute::<ScopedJob<'env>, Job>(job) };
+        // il pool è in prestito allo scope e non può essere fermato nel frattempo
+        if let Err(e) = self.pool.execute(job) {
+            panic!("scope su un pool già fermato: {}", e);
+        }
+    }
 }
 
 struct Worker {
     id: usize,
-    job_rx: Receiver<Job>,
-    event_tx: Sender<Events>,
+    shared: Arc<Shared>,
 }
 
-enum Events {
-    NewJob(Job),
-    WorkerDone(usize),
+impl Shared {
+    fn push(self: &Arc<Self>, job: Job, priority: Priority) -> Result<(), PoolError> {
+        // da un worker dello stesso pool si accoda localmente (anche durante lo stop, così i job
+        // in esecuzione possono ancora sottomettere lavoro), altrimenti round robin
+        let local = WORKER.with(|w| w.get()).filter(|&(pool, _)| pool == self.id).map(|(_, worker)| worker);
+        if local.is_none() && self.closed.load(AtomicOrdering::SeqCst) {
+            self.release(1);
+            return Err(PoolError::ShutDown);
+        }
+
+        let arrival = self.arrivals.fetch_add(1, AtomicOrdering::Relaxed);
+        let delay = match priority {
+            Priority::High => 0,
+            Priority::Normal => PRIORITY_AGING,
+            Priority::Low => 2 * PRIORITY_AGING,
+        };
+        let queued = Queued { deadline: arrival + delay, arrival, submitted: Instant::now(), job };
+        {
+            let queues = self.queues.read().unwrap();
+            let index = local
+                .and_then(|worker| queues.iter().position(|q| q.worker == worker))
+                .unwrap_or_else(|| self.next.fetch_add(1, AtomicOrdering::Relaxed) % queues.len());
+            queues[index].jobs.lock().unwrap().push(queued);
+            self.queued.fetch_add(1, AtomicOrdering::SeqCst);
+        }
+
+        // il lock serve solo se c'è un worker da svegliare o se si possono creare worker extra
+        if self.idle.load(AtomicOrdering::SeqCst) > 0
+            || self.live.load(AtomicOrdering::SeqCst) < self.ceiling.load(AtomicOrdering::SeqCst)
+        {
+            let mut control = self.control.lock().unwrap();
+            if control.idle > 0 {
+                self.wake.notify_one();
+            }
+            // più job in coda che worker liberi e c'è margine: nuovo worker extra
+            let waiting = self.queued.load(AtomicOrdering::SeqCst);
+            if waiting > control.idle && control.workers.len() < control.ceiling() && !control.shutting_down {
+                self.spawn_worker(&mut control);
+            }
+        }
+        Ok(())
+    }
+
+    fn release(&self, n: usize) {
+        if self.max_pending.is_some() {
+            release(&self.pending, n);
+        }
+    }
+
+    fn mirror(&self, control: &Control) {
+        self.live.store(control.workers.len(), AtomicOrdering::SeqCst);
+        self.ceiling.store(control.ceiling(), AtomicOrdering::SeqCst);
+    }
+
+    fn spawn_worker(self: &Arc<Self>, control: &mut Control) {
+        let id = control.next_id;
+        control.next_id += 1;
+        let queue = Arc::new(WorkerQueue { worker: id, jobs: Mutex::new(BinaryHeap::new()) });
+        self.queues.write().unwrap().push(queue);
+        let handle = Worker::spawn(id, self.clone());
+        control.workers.insert(id, handle);
+        self.mirror(control);
+    }
+
+    fn retire(&self, control: &mut Control, id: usize) {
+        if let Some(handle) = control.workers.remove(&id) {
+            control.finished.push(handle);
+        }
+        // i job rimasti nella coda del worker passano a un altro worker
+        let mut queues = self.queues.write().unwrap();
+        if let Some(pos) = queues.iter().position(|q| q.worker == id) {
+            let leftover = std::mem::take(&mut *queues.remove(pos).jobs.lock().unwrap());
+            if let Some(other) = queues.first() {
+                if !leftover.is_empty() {
+                    other.jobs.lock().unwrap().extend(leftover);
+                    self.wake.notify_all();
+                }
+            }
+        }
+        drop(queues);
+        self.mirror(control);
+        self.retired.notify_all();
+    }
 }
 
 impl ThreadPool {
     pub fn new(n: usize) -> Self {
-        let (event_tx, event_rx) = channel::<Events>();
+        ThreadPoolBuilder::new(n).build()
+    }
 
-        // canali per i worker
-        let mut worker_senders = Vec::new();
-        let mut handles = Vec::new();
+    pub fn with_max_pending(n: usize, max_pending: usize) -> Self {
+        // execute blocca e try_execute fallisce quando ci sono già max_pending job in attesa
+        ThreadPoolBuilder::new(n).max_pending(max_pending).build()
+    }
 
-        for id in 0..n {
-            let (job_tx, job_rx) = channel::<Job>();
-            worker_senders.push(job_tx);
+    fn build(builder: ThreadPoolBuilder) -> Self {
+        let ThreadPoolBuilder { workers: n, max_workers, max_pending, threads } = builder;
+        // almeno un worker, altrimenti i job in coda non verrebbero mai eseguiti
+        let n = n.max(1);
+        let control = Control {
+            workers: HashMap::new(),
+            finished: Vec::new(),
+            next_id: 0,
+            target: n,
+            max_workers,
+            idle: 0,
+            shutting_down: false,
+        };
+        let shared = Arc::new(Shared {
+            id: NEXT_POOL_ID.fetch_add(1, AtomicOrdering::Relaxed),
+            threads,
+            queues: RwLock::new(Vec::new()),
+            next: AtomicUsize::new(0),
+            arrivals: AtomicU64::new(0),
+            queued: AtomicUsize::new(0),
+            idle: AtomicUsize::new(0),
+            live: AtomicUsize::new(0),
+            ceiling: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+            pending: Arc::new((Mutex::new(0), Condvar::new())),
+            max_pending,
+            control: Mutex::new(control),
+            wake: Condvar::new(),
+            retired: Condvar::new(),
+            panic_hook: Mutex::new(None),
+            counters: Counters::default(),
+        });
 
-            let event_tx_clone = event_tx.clone();
+        {
+            let mut control = shared.control.lock().unwrap();
+            for _ in 0..n {
+                shared.spawn_worker(&mut control);
+            }
+        }
+        ThreadPool { shared }
+    }
 
-            // ogni worker gira su un thread
-            let handle = thread::spawn(move || {
-                let worker = Worker { id, job_rx, event_tx: event_tx_clone };
-                worker.run();
-            });
-            handles.push(handle);
+    pub fn stats(&self) -> PoolStats {
+        // fotografia dei contatori aggiornati dai worker
+        let shared = &self.shared;
+        let counters = &shared.counters;
+        PoolStats {
+            queued: shared.queued.load(AtomicOrdering::SeqCst),
+            workers: shared.control.lock().unwrap().workers.len(),
+            active_workers: counters.active.load(AtomicOrdering::Relaxed),
+            completed: counters.completed.load(AtomicOrdering::Relaxed),
+            panicked: counters.panicked.load(AtomicOrdering::Relaxed),
+            busy_time: Duration::from_nanos(counters.busy_nanos.load(AtomicOrdering::Relaxed)),
+            wait_histogram: std::array::from_fn(|i| counters.wait_histogram[i].load(AtomicOrdering::Relaxed)),
         }
+    }
 
-        // scheduler thread
-        {
-            let worker_senders = worker_senders.clone();
-            let event_tx_clone = event_tx.clone();
-            thread::spawn(move || {
-                let mut queue: Vec<Job> = Vec::new();
-                let mut free_workers: Vec<usize> = (0..n).collect();
+    pub fn execute(&self, job: Job) -> Result<(), PoolError> {
+        self.execute_with_priority(job, Priority::Normal)
+    }
 
-                while let Ok(event) = event_rx.recv() {
-                    match event {
-                        Events::NewJob(job) => {
-                            if let Some(worker_id) = free_workers.pop() {
-                                // assegna subito
-                                worker_senders[worker_id].send(job).unwrap();
-                            } else {
-                                // accoda
-                                queue.push(job);
-                            }
-                        }
-                        Events::WorkerDone(id) => {
-                            if let Some(job) = queue.pop() {
-                                // assegna un job in attesa
-                                worker_senders[id].send(job).unwrap();
-                            } else {
-                                // non ci sono job, segno worker come libero
-                                free_workers.push(id);
-                            }
-                        }
+    pub fn execute_with_priority(&self, job: Job, priority: Priority) -> Result<(), PoolError> {
+        // con la coda limitata attende che si liberi un posto
+        if let Some(max) = self.shared.max_pending {
+            let (count, space) = &*self.shared.pending;
+            let mut count = space.wait_while(count.lock().unwrap(), |c| *c >= max).unwrap();
+            *count += 1;
+        }
+        self.shared.push(job, priority)
+    }
+
+    pub fn try_execute(&self, job: Job) -> Result<(), PoolError> {
+        if let Some(max) = self.shared.max_pending {
+            let mut count = self.shared.pending.0.lock().unwrap();
+            if *count >= max {
+                return Err(PoolError::QueueFull);
+            }
+            *count += 1;
+        }
+        self.shared.push(job, Priority::Normal)
+    }
+
+    pub fn submit<F, R>(&self, f: F) -> Result<JobHandle<R>, PoolError>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (result_tx, result_rx) = channel();
+        self.execute(Box::new(move || {
+            // il panic viene catturato e restituito al chiamante come errore
+            let result = panic::catch_unwind(AssertUnwindSafe(f))
+                .map_err(|payload| JobError::Panicked(panic_message(payload)));
+            let _ = result_tx.send(result); // il chiamante potrebbe aver scartato l'handle
+        }))?;
+        Ok(JobHandle { result_rx })
+    }
+
+    pub fn scope<'env, F, R>(&'env self, f: F) -> R
+    where
+        F: FnOnce(&Scope<'env>) -> R,
+    {
+        // i job dello scope girano sui worker del pool (con coda, priorità e statistiche) e possono
+        // prendere in prestito dati dello stack perché scope() attende che siano tutti terminati.
+        // Non va chiamato da un job dello stesso pool: se tutti i worker aspettano, nessuno esegue
+        let scope = Scope { pool: self, state: Arc::new(ScopeState::default()), _env: PhantomData };
+        let result = {
+            let _wait = ScopeGuard(&scope.state);
+            f(&scope)
+        };
+        // il panic di un job viene propagato solo dopo che tutti gli altri sono terminati
+        if let Some(payload) = scope.state.panic.lock().unwrap().take() {
+            panic::resume_unwind(payload);
+        }
+        result
+    }
+
+    pub fn map<I, T, R, F>(&self, items: I, f: F) -> Vec<R>
+    where
+        I: IntoIterator<Item = T>,
+        T: Send,
+        R: Send,
+        F: Fn(T) -> R + Sync,
+    {
+        let items: Vec<T> = items.into_iter().collect();
+        // un blocco contiguo per ogni worker del pool, eseguito come job dello scope;
+        // i risultati tornano nell'ordine dell'input
+        let chunk_size = items.len().div_ceil(self.stats().workers.max(1)).max(1);
+        let mut results: Vec<Option<R>> = (0..items.len()).map(|_| None).collect();
+        let f = &f;
+        self.scope(|s| {
+            for (chunk, slots) in chunked(items, chunk_size).into_iter().zip(results.chunks_mut(chunk_size)) {
+                s.execute(move || {
+                    for (item, slot) in chunk.into_iter().zip(slots) {
+                        *slot = Some(f(item));
                     }
-                }
+                });
+            }
+        });
+        results.into_iter().map(|r| r.expect("every slot is filled by its job")).collect()
+    }
 
-                drop(event_tx_clone);
-            });
+    pub fn for_each_chunked<I, T, F>(&self, items: I, chunk_size: usize, f: F)
+    where
+        I: IntoIterator<Item = T>,
+        T: Send,
+        F: Fn(T) + Sync,
+    {
+        // ogni chunk diventa un job del pool: chunk piccoli si distribuiscono meglio tra i worker,
+        // chunk grandi costano meno in job da accodare
+        let f = &f;
+        self.scope(|s| {
+            for chunk in chunked(items, chunk_size.max(1)) {
+                s.execute(move || chunk.into_iter().for_each(f));
+            }
+        });
+    }
+
+    pub fn set_workers(&self, n: usize) {
+        let shared = &self.shared;
+        let finished = {
+            let mut control = shared.control.lock().unwrap();
+            if control.shutting_down {
+                return;
+            }
+            // almeno un worker, altrimenti i job in coda non verrebbero mai eseguiti
+            control.target = n.max(1);
+            while control.workers.len() < control.target {
+                shared.spawn_worker(&mut control);
+            }
+            shared.mirror(&control);
+            // i worker liberi in eccesso si ritirano subito, quelli occupati appena finiscono il job
+            shared.wake.notify_all();
+            let mut control = shared
+                .retired
+                .wait_while(control, |c| c.workers.len() > c.ceiling() && c.idle > 0)
+                .unwrap();
+            std::mem::take(&mut control.finished)
+        };
+        for handle in finished {
+            let _ = handle.join();
         }
+    }
 
-        ThreadPool { event_tx, handles }
+    pub fn on_panic<F: Fn(usize, &str) + Send + 'static>(&self, hook: F) {
+        // l'hook viene eseguito dal worker in cui il job è andato in panic
+        *self.shared.panic_hook.lock().unwrap() = Some(Box::new(hook));
     }
 
-    pub fn execute(&self, job: Job) {
-        self.event_tx.send(Events::NewJob(job)).unwrap();
+    pub fn execute_batch(&self, jobs: Vec<Job>) -> Result<BatchLatch, PoolError> {
+        // il latch scende di uno per ogni job completato e viene cancellato al primo panic
+        let latch: BatchLatch = Arc::new(Counter::new(jobs.len()));
+        for job in jobs {
+            let job_latch = latch.clone();
+            let sent = self.execute(Box::new(move || match panic::catch_unwind(AssertUnwindSafe(job)) {
+                Ok(()) => job_latch.count_down(),
+                Err(payload) => {
+                    job_latch.cancel();
+                    // il panic prosegue: il worker lo conta e l'hook on_panic viene avvisato
+                    panic::resume_unwind(payload);
+                }
+            }));
+            if let Err(e) = sent {
+                // il batch non potrà mai completarsi
+                latch.cancel();
+                return Err(e);
+            }
+        }
+        Ok(latch)
     }
 
     pub fn stop(&mut self) {
-        for handle in self.handles.drain(..) {
-            handle.join().unwrap();
+        // attende che i job in coda siano completati
+        self.shutdown(false);
+    }
+
+    pub fn stop_now(&mut self) {
+        // scarta i job in coda, attende solo quelli già in esecuzione
+        self.shutdown(true);
+    }
+
+    fn shutdown(&mut self, discard: bool) {
+        let shared = &self.shared;
+        let (handles, discarded) = {
+            let mut control = shared.control.lock().unwrap();
+            if control.shutting_down {
+                return; // già fermato
+            }
+            control.shutting_down = true;
+            shared.closed.store(true, AtomicOrdering::SeqCst);
+
+            let mut discarded = Vec::new();
+            if discard {
+                for queue in shared.queues.read().unwrap().iter() {
+                    discarded.extend(std::mem::take(&mut *queue.jobs.lock().unwrap()).into_vec());
+                }
+                shared.queued.fetch_sub(discarded.len(), AtomicOrdering::SeqCst);
+                shared.release(discarded.len());
+            }
+            // i worker escono appena non trovano più lavoro in nessuna coda
+            shared.wake.notify_all();
+            let mut handles = std::mem::take(&mut control.finished);
+            handles.extend(control.workers.drain().map(|(_, handle)| handle));
+            (handles, discarded)
+        };
+        drop(discarded); // i JobHandle dei job scartati ricevono Canceled
+        for handle in handles {
+            let _ = handle.join();
         }
     }
 }
 
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
 impl Worker {
+    fn spawn(id: usize, shared: Arc<Shared>) -> thread::JoinHandle<()> {
+        let config = &shared.threads;
+        let mut builder = thread::Builder::new();
+        if let Some(prefix) = &config.name_prefix {
+            builder = builder.name(format!("{}-{}", prefix, id));
+        }
+        if let Some(bytes) = config.stack_size {
+            builder = builder.stack_size(bytes);
+        }
+
+        // ogni worker gira su un thread
+        builder
+            .spawn(move || {
+                WORKER.with(|w| w.set(Some((shared.id, id))));
+                let config = shared.threads.clone();
+                if let Some(hook) = &config.on_start {
+                    hook(id);
+                }
+                Worker { id, shared }.run();
+                if let Some(hook) = &config.on_stop {
+                    hook(id);
+                }
+            })
+            .expect("failed to spawn worker thread")
+    }
+
+    fn find_job(&self) -> Option<Job> {
+        // si prende il job con la scadenza più vicina fra le teste di tutte le code, così FIFO e
+        // priorità valgono per l'intero pool come con la coda centrale, non solo per la singola coda.
+        // Le code si bloccano una alla volta: se nel frattempo la migliore è stata svuotata si riprova
+        let shared = &*self.shared;
+        let Queued { submitted, job, .. } = loop {
+            if shared.queued.load(AtomicOrdering::SeqCst) == 0 {
+                return None;
+            }
+            let queues = shared.queues.read().unwrap();
+            let best = queues
+                .iter()
+                .filter_map(|q| q.jobs.lock().unwrap().peek().map(|head| (head.deadline, head.arrival, q)))
+                .min_by_key(|&(deadline, arrival, _)| (deadline, arrival));
+            let (_, _, queue) = best?;
+            let popped = queue.jobs.lock().unwrap().pop();
+            if let Some(queued) = popped {
+                break queued;
+            }
+        };
+        shared.queued.fetch_sub(1, AtomicOrdering::SeqCst);
+        shared.release(1);
+        shared.counters.wait_histogram[wait_bucket(submitted.elapsed())].fetch_add(1, AtomicOrdering::Relaxed);
+        Some(job)
+    }
+
+    fn run_job(&self, job: Job) {
+        let counters = &self.shared.counters;
+        counters.active.fetch_add(1, AtomicOrdering::Relaxed);
+        let start = Instant::now();
+        match panic::catch_unwind(AssertUnwindSafe(job)) {
+            Ok(()) => {
+                counters.completed.fetch_add(1, AtomicOrdering::Relaxed);
+                counters.busy_nanos.fetch_add(start.elapsed().as_nanos() as u64, AtomicOrdering::Relaxed);
+            }
+            Err(payload) => {
+                // il worker sopravvive al panic: si conta e si avvisa l'hook on_panic
+                counters.panicked.fetch_add(1, AtomicOrdering::Relaxed);
+                if let Some(hook) = &*self.shared.panic_hook.lock().unwrap() {
+                    hook(self.id, &panic_message(payload));
+                }
+            }
+        }
+        counters.active.fetch_sub(1, AtomicOrdering::Relaxed);
+    }
+
     fn run(self) {
-        while let Ok(job) = self.job_rx.recv() {
-            // esegui job
-            job();
+        loop {
+            if let Some(job) = self.find_job() {
+                self.run_job(job);
+                // il pool è stato ridotto: il worker si ritira invece di prendere altro lavoro
+                if self.shared.live.load(AtomicOrdering::SeqCst) > self.shared.ceiling.load(AtomicOrdering::SeqCst) {
+                    let mut control = self.shared.control.lock().unwrap();
+                    if control.workers.len() > control.ceiling() {
+                        self.shared.retire(&mut control, self.id);
+                        return;
+                    }
+                }
+            } else if !self.wait_for_job() {
+                return;
+            }
+        }
+    }
+
+    // false quando il worker deve terminare: pool fermato e senza lavoro, o worker in eccesso
+    fn wait_for_job(&self) -> bool {
+        let shared = &*self.shared;
+        let mut control = shared.control.lock().unwrap();
+        loop {
+            if control.workers.len() > control.ceiling() {
+                shared.retire(&mut control, self.id);
+                return false;
+            }
+
+            control.idle += 1;
+            shared.idle.store(control.idle, AtomicOrdering::SeqCst);
+            // ricontrollo dopo essersi dichiarati inattivi: un execute concorrente
+            // vede idle > 0 e ci sveglia, oppure noi vediamo il suo job
+            let has_work = shared.queued.load(AtomicOrdering::SeqCst) > 0;
+            let timed_out = if has_work || control.shutting_down {
+                false
+            } else if let Some(idle) = shared.threads.keep_alive {
+                let (guard, timeout) = shared.wake.wait_timeout(control, idle).unwrap();
+                control = guard;
+                timeout.timed_out()
+            } else {
+                control = shared.wake.wait(control).unwrap();
+                false
+            };
+            control.idle -= 1;
+            shared.idle.store(control.idle, AtomicOrdering::SeqCst);
+            shared.retired.notify_all(); // set_workers attende solo finché ci sono worker inattivi
 
-            // notifica fine
-            self.event_tx.send(Events::WorkerDone(self.id)).unwrap();
+            if has_work || shared.queued.load(AtomicOrdering::SeqCst) > 0 {
+                return true;
+            }
+            if control.shutting_down {
+                return false;
+            }
+            // si ritirano solo i worker extra rimasti inattivi per tutto il keep-alive
+            if timed_out && control.workers.len() > control.target {
+                shared.retire(&mut control, self.id);
+                return false;
+            }
         }
     }
 }
@@ -106,13 +883,611 @@ impl Worker {
 // Threadpool
 pub fn main_ex2() -> Result<String, Box<dyn std::error::Error>> {
     // alloca i worker
-    let threadpool = ThreadPool::new(10);
+    let mut threadpool = ThreadPool::new(10);
     for x in 0..100 {
         threadpool.execute(Box::new(move || {
             println!("long running task {}", x);
             thread::sleep(Duration::from_millis(1000))
+        }))?;
+    }
+    // attende il completamento di tutti i job prima di uscire
+    threadpool.stop();
+    Ok("all tasks completed".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::Sender;
+    use cancelable_latch::WaitResult;
+    use std::sync::Arc;
+
+    fn counting_jobs(pool: &ThreadPool, n: usize, sleep: Duration) -> Arc<AtomicUsize> {
+        let done = Arc::new(AtomicUsize::new(0));
+        for _ in 0..n {
+            let done = done.clone();
+            pool.execute(Box::new(move || {
+                thread::sleep(sleep);
+                done.fetch_add(1, Ordering::SeqCst);
+            }))
+            .unwrap();
+        }
+        done
+    }
+
+    #[test]
+    fn stop_runs_queued_jobs_and_joins() {
+        let mut pool = ThreadPool::new(2);
+        let done = counting_jobs(&pool, 6, Duration::from_millis(10));
+        pool.stop();
+        assert_eq!(done.load(Ordering::SeqCst), 6);
+        assert_eq!(pool.execute(Box::new(|| {})), Err(PoolError::ShutDown));
+    }
+
+    #[test]
+    fn stop_now_discards_queued_jobs() {
+        let mut pool = ThreadPool::new(1);
+        let done = counting_jobs(&pool, 5, Duration::from_millis(50));
+        thread::sleep(Duration::from_millis(10));
+        pool.stop_now();
+        assert!(done.load(Ordering::SeqCst) < 5);
+    }
+
+    #[test]
+    fn drop_waits_for_jobs() {
+        let done = {
+            let pool = ThreadPool::new(3);
+            counting_jobs(&pool, 9, Duration::from_millis(5))
+        };
+        assert_eq!(done.load(Ordering::SeqCst), 9);
+    }
+
+    #[test]
+    fn submit_returns_the_job_result() {
+        let pool = ThreadPool::new(2);
+        let handles: Vec<_> = (0..4).map(|x| pool.submit(move || x * 10).unwrap()).collect();
+        let results: Vec<_> = handles.into_iter().map(|h| h.wait().unwrap()).collect();
+        assert_eq!(results, vec![0, 10, 20, 30]);
+    }
+
+    #[test]
+    fn submit_reports_panics_and_timeouts() {
+        let pool = ThreadPool::new(1);
+        let failing = pool.submit(|| -> i32 { panic!("boom") }).unwrap();
+        assert_eq!(failing.wait(), Err(JobError::Panicked("boom".to_string())));
+
+        let slow = pool.submit(|| thread::sleep(Duration::from_millis(100))).unwrap();
+        assert_eq!(slow.wait_timeout(Duration::from_millis(10)), Err(JobError::Timeout));
+        assert_eq!(slow.wait_timeout(Duration::from_secs(1)), Ok(()));
+    }
+
+    #[test]
+    fn discarded_jobs_are_canceled() {
+        let mut pool = ThreadPool::new(1);
+        let _busy = pool.submit(|| thread::sleep(Duration::from_millis(50))).unwrap();
+        let queued = pool.submit(|| 1).unwrap();
+        pool.stop_now();
+        assert_eq!(queued.wait(), Err(JobError::Canceled));
+    }
+
+    #[test]
+    fn bounded_queue_applies_backpressure() {
+        let pool = ThreadPool::with_max_pending(1, 2);
+        let (gate_tx, gate_rx) = channel::<()>();
+        let gate = Arc::new(Mutex::new(gate_rx));
+        for _ in 0..3 {
+            let gate = gate.clone();
+            // il primo job occupa il worker, gli altri due riempiono la coda
+            pool.execute(Box::new(move || {
+                let _ = gate.lock().unwrap().recv();
+            }))
+            .unwrap();
+        }
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(pool.try_execute(Box::new(|| {})), Err(PoolError::QueueFull));
+
+        gate_tx.send(()).unwrap();
+        let handle = thread::spawn(move || {
+            pool.execute(Box::new(|| {})).unwrap();
+            pool
+        });
+        drop(gate_tx);
+        drop(handle.join().unwrap());
+    }
+
+    #[test]
+    fn panicking_jobs_do_not_shrink_the_pool() {
+        let mut pool = ThreadPool::new(2);
+        let (panic_tx, panic_rx) = channel();
+        pool.on_panic(move |id, msg| panic_tx.send((id, msg.to_string())).unwrap());
+
+        for _ in 0..2 {
+            pool.execute(Box::new(|| panic!("job failed"))).unwrap();
+        }
+        let done = counting_jobs(&pool, 4, Duration::from_millis(5));
+        pool.stop();
+
+        assert_eq!(done.load(Ordering::SeqCst), 4);
+        let reports: Vec<_> = panic_rx.try_iter().collect();
+        assert_eq!(reports.len(), 2);
+        assert!(reports.iter().all(|(_, msg)| msg == "job failed"));
+    }
+
+    #[test]
+    fn queued_jobs_run_in_submission_order() {
+        let mut pool = ThreadPool::new(1);
+        let order = Arc::new(Mutex::new(Vec::new()));
+        // il primo job tiene occupato l'unico worker, gli altri finiscono in coda
+        pool.execute(Box::new(|| thread::sleep(Duration::from_millis(20)))).unwrap();
+        for x in 0..10 {
+            let order = order.clone();
+            pool.execute(Box::new(move || order.lock().unwrap().push(x))).unwrap();
+        }
+        pool.stop();
+        assert_eq!(*order.lock().unwrap(), (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn submission_order_holds_across_worker_queues() {
+        let mut pool = ThreadPool::new(2);
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let (ready_tx, ready_rx) = channel();
+        let (release_tx, release_rx) = channel::<()>();
+        // un worker resta bloccato, l'altro si libera dopo che i job sono stati distribuiti fra le code
+        let ready = ready_tx.clone();
+        pool.execute(Box::new(move || {
+            ready.send(()).unwrap();
+            let _ = release_rx.recv();
         }))
+        .unwrap();
+        pool.execute(Box::new(move || {
+            ready_tx.send(()).unwrap();
+            thread::sleep(Duration::from_millis(20));
+        }))
+        .unwrap();
+        ready_rx.recv().unwrap();
+        ready_rx.recv().unwrap();
+        for x in 0..10 {
+            let order = order.clone();
+            pool.execute(Box::new(move || order.lock().unwrap().push(x))).unwrap();
+        }
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while order.lock().unwrap().len() < 10 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        release_tx.send(()).unwrap();
+        pool.stop();
+        assert_eq!(*order.lock().unwrap(), (0..10).collect::<Vec<_>>());
     }
-    // just to keep the main thread alive
-    loop {thread::sleep(Duration::from_millis(1000))};
-}
\ No newline at end of file
+
+    #[test]
+    fn high_priority_jobs_jump_the_queue() {
+        let mut pool = ThreadPool::new(1);
+        let order = Arc::new(Mutex::new(Vec::new()));
+        pool.execute(Box::new(|| thread::sleep(Duration::from_millis(20)))).unwrap();
+        for (x, priority) in [(0, Priority::Low), (1, Priority::Normal), (2, Priority::High)] {
+            let order = order.clone();
+            pool.execute_with_priority(Box::new(move || order.lock().unwrap().push(x)), priority)
+                .unwrap();
+        }
+        pool.stop();
+        assert_eq!(*order.lock().unwrap(), vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn low_priority_jobs_are_not_starved() {
+        let mut pool = ThreadPool::new(1);
+        let order = Arc::new(Mutex::new(Vec::new()));
+        pool.execute(Box::new(|| thread::sleep(Duration::from_millis(20)))).unwrap();
+        let push = |x: u64, priority| {
+            let order = order.clone();
+            pool.execute_with_priority(Box::new(move || order.lock().unwrap().push(x)), priority)
+                .unwrap();
+        };
+        push(u64::MAX, Priority::Low);
+        for x in 0..100 {
+            push(x, Priority::High);
+        }
+        pool.stop();
+        let position = order.lock().unwrap().iter().position(|&x| x == u64::MAX).unwrap();
+        // superato solo dai job ad alta priorità arrivati entro la sua scadenza virtuale
+        assert_eq!(position as u64, 2 * PRIORITY_AGING - 1);
+    }
+
+    #[test]
+    fn builder_configures_worker_threads() {
+        let started = Arc::new(AtomicUsize::new(0));
+        let stopped = Arc::new(AtomicUsize::new(0));
+        let mut pool = {
+            let (started, stopped) = (started.clone(), stopped.clone());
+            ThreadPoolBuilder::new(3)
+                .name_prefix("pool")
+                .stack_size(256 * 1024)
+                .on_thread_start(move |_| {
+                    started.fetch_add(1, Ordering::SeqCst);
+                })
+                .on_thread_stop(move |_| {
+                    stopped.fetch_add(1, Ordering::SeqCst);
+                })
+                .build()
+        };
+        let name = pool.submit(|| thread::current().name().map(str::to_string)).unwrap();
+        let name = name.wait().unwrap().unwrap();
+        assert!(name.starts_with("pool-"), "{}", name);
+
+        pool.stop();
+        assert_eq!(started.load(Ordering::SeqCst), 3);
+        assert_eq!(stopped.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn set_workers_grows_and_shrinks_the_pool() {
+        let started = Arc::new(AtomicUsize::new(0));
+        let stopped = Arc::new(AtomicUsize::new(0));
+        let mut pool = {
+            let (started, stopped) = (started.clone(), stopped.clone());
+            ThreadPoolBuilder::new(1)
+                .on_thread_start(move |_| {
+                    started.fetch_add(1, Ordering::SeqCst);
+                })
+                .on_thread_stop(move |_| {
+                    stopped.fetch_add(1, Ordering::SeqCst);
+                })
+                .build()
+        };
+
+        pool.set_workers(4);
+        // con 4 worker 4 job da 50ms finiscono ben prima di 4 * 50ms
+        let start = std::time::Instant::now();
+        let handles: Vec<_> = (0..4)
+            .map(|_| pool.submit(|| thread::sleep(Duration::from_millis(50))).unwrap())
+            .collect();
+        for handle in handles {
+            handle.wait().unwrap();
+        }
+        assert!(start.elapsed() < Duration::from_millis(150));
+        assert_eq!(started.load(Ordering::SeqCst), 4);
+
+        pool.set_workers(2);
+        pool.submit(|| ()).unwrap().wait().unwrap();
+        assert_eq!(stopped.load(Ordering::SeqCst), 2);
+
+        pool.stop();
+        assert_eq!(stopped.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn set_workers_shrinks_a_pool_created_larger() {
+        let stopped = Arc::new(AtomicUsize::new(0));
+        let pool = {
+            let stopped = stopped.clone();
+            ThreadPoolBuilder::new(4)
+                .on_thread_stop(move |_| {
+                    stopped.fetch_add(1, Ordering::SeqCst);
+                })
+                .build()
+        };
+
+        pool.set_workers(1);
+        // i worker che non erano ancora inattivi si ritirano appena cercano lavoro
+        let deadline = std::time::Instant::now() + Duration::from_secs(1);
+        while stopped.load(Ordering::SeqCst) < 3 && std::time::Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(stopped.load(Ordering::SeqCst), 3);
+        assert_eq!(pool.stats().workers, 1);
+        pool.submit(|| ()).unwrap().wait().unwrap();
+    }
+
+    #[test]
+    fn stats_report_queue_and_throughput() {
+        let mut pool = ThreadPool::new(2);
+        let (gate_tx, gate_rx) = channel::<()>();
+        let gate = Arc::new(Mutex::new(gate_rx));
+        for _ in 0..2 {
+            let gate = gate.clone();
+            pool.execute(Box::new(move || {
+                let _ = gate.lock().unwrap().recv();
+            }))
+            .unwrap();
+        }
+        let done = counting_jobs(&pool, 3, Duration::from_millis(1));
+        pool.execute(Box::new(|| panic!("stats"))).unwrap();
+        thread::sleep(Duration::from_millis(20));
+
+        let stats = pool.stats();
+        assert_eq!(stats.workers, 2);
+        assert_eq!(stats.active_workers, 2);
+        assert_eq!(stats.queued, 4);
+        assert_eq!(stats.completed, 0);
+
+        drop(gate_tx);
+        pool.stop();
+        let stats = pool.stats();
+        assert_eq!(done.load(Ordering::SeqCst), 3);
+        assert_eq!((stats.queued, stats.active_workers), (0, 0));
+        assert_eq!((stats.completed, stats.panicked), (5, 1));
+        assert!(stats.busy_time >= Duration::from_millis(20));
+        assert_eq!(stats.wait_histogram.iter().sum::<usize>(), 6);
+        // i job in coda hanno aspettato almeno 20ms
+        assert!(stats.wait_histogram[2..].iter().sum::<usize>() >= 4);
+    }
+
+    #[test]
+    fn scoped_jobs_borrow_stack_data() {
+        let pool = ThreadPool::new(3);
+        let data: Vec<usize> = (1..=100).collect();
+        let total = AtomicUsize::new(0);
+        let mut results = vec![0; 4];
+
+        let returned = pool.scope(|s| {
+            for (chunk, result) in data.chunks(25).zip(results.iter_mut()) {
+                let total = &total;
+                s.execute(move || {
+                    *result = chunk.iter().sum();
+                    total.fetch_add(*result, Ordering::SeqCst);
+                });
+            }
+            "submitted"
+        });
+
+        assert_eq!(returned, "submitted");
+        assert_eq!(total.load(Ordering::SeqCst), 5050);
+        assert_eq!(results, vec![325, 950, 1575, 2200]);
+    }
+
+    #[test]
+    fn scoped_jobs_run_on_the_pool_workers() {
+        let pool = ThreadPoolBuilder::new(2).name_prefix("scoped").build();
+        let names = Mutex::new(Vec::new());
+        pool.scope(|s| {
+            for _ in 0..8 {
+                s.execute(|| names.lock().unwrap().push(thread::current().name().map(str::to_string)));
+            }
+        });
+        let names = names.into_inner().unwrap();
+        assert_eq!(names.len(), 8);
+        assert!(names.iter().all(|n| n.as_deref().is_some_and(|n| n.starts_with("scoped-"))), "{:?}", names);
+        assert_eq!(pool.stats().workers, 2);
+    }
+
+    #[test]
+    fn scope_waits_for_every_job_before_propagating_a_panic() {
+        let pool = ThreadPool::new(2);
+        let done = AtomicUsize::new(0);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            pool.scope(|s| {
+                s.execute(|| panic!("scoped job failed"));
+                for _ in 0..4 {
+                    s.execute(|| {
+                        thread::sleep(Duration::from_millis(10));
+                        done.fetch_add(1, Ordering::SeqCst);
+                    });
+                }
+            })
+        }));
+        assert_eq!(panic_message(result.unwrap_err()), "scoped job failed");
+        assert_eq!(done.load(Ordering::SeqCst), 4);
+        // il panic è stato catturato nel job: nessun worker è andato perso
+        assert_eq!(pool.stats().panicked, 0);
+        assert_eq!(pool.map(0..4, |x| x + 1), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn map_keeps_input_order() {
+        let pool = ThreadPool::new(4);
+        let squares = pool.map(0..1000u64, |x| x * x);
+        assert_eq!(squares, (0..1000u64).map(|x| x * x).collect::<Vec<_>>());
+        assert!(pool.map(Vec::<u64>::new(), |x| x).is_empty());
+    }
+
+    #[test]
+    fn map_and_for_each_chunked_use_the_pool_workers() {
+        let pool = ThreadPoolBuilder::new(2).name_prefix("mapper").build();
+        let on_worker = || thread::current().name().is_some_and(|n| n.starts_with("mapper-"));
+        assert!(pool.map(0..10, |_| on_worker()).into_iter().all(|w| w));
+
+        let outside = AtomicUsize::new(0);
+        pool.for_each_chunked(0..10, 3, |_| {
+            if !on_worker() {
+                outside.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        assert_eq!(outside.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn for_each_chunked_visits_every_item() {
+        let pool = ThreadPool::new(3);
+        let total = AtomicUsize::new(0);
+        let threads = Mutex::new(std::collections::HashSet::new());
+        pool.for_each_chunked(1..=100, 7, |x| {
+            total.fetch_add(x, Ordering::SeqCst);
+            threads.lock().unwrap().insert(thread::current().id());
+        });
+        assert_eq!(total.load(Ordering::SeqCst), 5050);
+        assert!(threads.lock().unwrap().len() <= 3);
+    }
+
+    #[test]
+    fn idle_workers_steal_from_busy_ones() {
+        let pool = Arc::new(ThreadPool::new(2));
+        let (result_tx, result_rx) = channel();
+        let inner = pool.clone();
+        pool.execute(Box::new(move || {
+            let (ran_tx, ran_rx) = channel();
+            // sottomesso da un worker finisce nella sua coda, ma lui resta occupato ad aspettarlo
+            inner
+                .execute(Box::new(move || ran_tx.send(thread::current().id()).unwrap()))
+                .unwrap();
+            drop(inner); // l'ultimo riferimento al pool resta al test
+            let stolen_by = ran_rx.recv_timeout(Duration::from_secs(1));
+            result_tx.send(stolen_by.map(|id| id != thread::current().id())).unwrap();
+        }))
+        .unwrap();
+        assert_eq!(result_rx.recv().unwrap(), Ok(true));
+    }
+
+    // il vecchio design, tenuto come termine di paragone per il benchmark: un thread scheduler
+    // riceve tutti gli eventi e assegna i job ai worker liberi tramite un canale per worker
+    enum Event {
+        NewJob(Job),
+        WorkerDone(usize),
+        Shutdown,
+    }
+
+    struct CentralSchedulerPool {
+        event_tx: Sender<Event>,
+        scheduler: Option<thread::JoinHandle<()>>,
+    }
+
+    impl CentralSchedulerPool {
+        fn new(n: usize) -> Self {
+            let (event_tx, event_rx) = channel::<Event>();
+            let done_tx = event_tx.clone();
+            let scheduler = thread::spawn(move || {
+                let workers: Vec<_> = (0..n)
+                    .map(|id| {
+                        let (job_tx, job_rx) = channel::<Job>();
+                        let done_tx = done_tx.clone();
+                        let handle = thread::spawn(move || {
+                            for job in job_rx {
+                                job();
+                                let _ = done_tx.send(Event::WorkerDone(id));
+                            }
+                        });
+                        (job_tx, handle)
+                    })
+                    .collect();
+                let mut free: Vec<usize> = (0..n).collect();
+                let mut queue = std::collections::VecDeque::new();
+                let mut shutting_down = false;
+                for event in event_rx {
+                    match event {
+                        Event::NewJob(job) => match free.pop() {
+                            Some(id) => workers[id].0.send(job).unwrap(),
+                            None => queue.push_back(job),
+                        },
+                        Event::WorkerDone(id) => match queue.pop_front() {
+                            Some(job) => workers[id].0.send(job).unwrap(),
+                            None => free.push(id),
+                        },
+                        Event::Shutdown => shutting_down = true,
+                    }
+                    if shutting_down && queue.is_empty() && free.len() == n {
+                        break;
+                    }
+                }
+                for (job_tx, handle) in workers {
+                    drop(job_tx);
+                    let _ = handle.join();
+                }
+            });
+            CentralSchedulerPool { event_tx, scheduler: Some(scheduler) }
+        }
+
+        fn execute(&self, job: Job) {
+            self.event_tx.send(Event::NewJob(job)).unwrap();
+        }
+
+        fn stop(&mut self) {
+            let _ = self.event_tx.send(Event::Shutdown);
+            if let Some(scheduler) = self.scheduler.take() {
+                let _ = scheduler.join();
+            }
+        }
+    }
+
+    // benchmark: cargo test --release -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_scheduler_vs_work_stealing() {
+        use std::time::Instant;
+        const TASKS: usize = 1_000_000;
+
+        let done = Arc::new(AtomicUsize::new(0));
+        let start = Instant::now();
+        let mut pool = CentralSchedulerPool::new(8);
+        for _ in 0..TASKS {
+            let done = done.clone();
+            pool.execute(Box::new(move || {
+                done.fetch_add(1, Ordering::Relaxed);
+            }));
+        }
+        pool.stop();
+        let scheduler_time = start.elapsed();
+        assert_eq!(done.load(Ordering::SeqCst), TASKS);
+
+        let done = Arc::new(AtomicUsize::new(0));
+        let start = Instant::now();
+        let mut pool = ThreadPool::new(8);
+        for _ in 0..TASKS {
+            let done = done.clone();
+            pool.execute(Box::new(move || {
+                done.fetch_add(1, Ordering::Relaxed);
+            }))
+            .unwrap();
+        }
+        pool.stop();
+        let stealing_time = start.elapsed();
+        assert_eq!(done.load(Ordering::SeqCst), TASKS);
+
+        println!("central scheduler: {scheduler_time:?}, work stealing: {stealing_time:?}");
+    }
+
+    #[test]
+    fn execute_batch_counts_down_per_job() {
+        let pool = ThreadPool::new(3);
+        let jobs: Vec<Job> = (0..6)
+            .map(|_| -> Job { Box::new(|| thread::sleep(Duration::from_millis(20))) })
+            .collect();
+        let latch = pool.execute_batch(jobs).unwrap();
+        assert_eq!(latch.wait_timeout(Duration::from_millis(1)), WaitResult::Timeout);
+        assert_eq!(latch.wait(), WaitResult::Success);
+        assert_eq!(latch.remaining(), 0);
+        assert_eq!(pool.execute_batch(Vec::new()).unwrap().wait(), WaitResult::Success);
+    }
+
+    #[test]
+    fn execute_batch_is_canceled_by_a_panic() {
+        let pool = ThreadPool::new(2);
+        let jobs: Vec<Job> = vec![
+            Box::new(|| thread::sleep(Duration::from_millis(200))),
+            Box::new(|| panic!("batch job failed")),
+        ];
+        let latch = pool.execute_batch(jobs).unwrap();
+        assert_eq!(latch.wait_timeout(Duration::from_millis(100)), WaitResult::Canceled);
+        assert!(latch.is_canceled());
+    }
+
+    #[test]
+    fn extra_workers_time_out_when_idle() {
+        let mut pool = ThreadPoolBuilder::new(1)
+            .max_workers(4)
+            .keep_alive(Duration::from_millis(30))
+            .build();
+        let handles: Vec<_> = (0..4)
+            .map(|_| pool.submit(|| thread::sleep(Duration::from_millis(20))).unwrap())
+            .collect();
+        thread::sleep(Duration::from_millis(5));
+        assert_eq!(pool.stats().workers, 4);
+        for handle in handles {
+            handle.wait().unwrap();
+        }
+
+        thread::sleep(Duration::from_millis(150));
+        assert_eq!(pool.stats().workers, 1);
+
+        // sotto carico i worker extra vengono ricreati
+        let handles: Vec<_> = (0..2)
+            .map(|_| pool.submit(|| thread::sleep(Duration::from_millis(20))).unwrap())
+            .collect();
+        thread::sleep(Duration::from_millis(5));
+        assert_eq!(pool.stats().workers, 2);
+        for handle in handles {
+            handle.wait().unwrap();
+        }
+        pool.stop();
+    }
+}
@@ -1,47 +1,693 @@
 use std::thread;
 use reqwest::blocking;
-use std::time::Duration;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::Receiver;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use cache_manager::CacheManager;
+use esame_17_06_2025::TokenManager;
+use permit_manager::PermitManager;
+
+type DownloadResult = Result<String, DownloadError>;
+
+// Errori restituiti ai chiamanti del Downloader
+#[derive(Debug)]
+enum DownloadError {
+    Timeout,                                            // timeout del download o dell'attesa di una connessione
+    Status(u16),                                        // risposta HTTP di errore
+    Io(std::io::Error),                                 // file locale o connessione interrotta durante il body
+    ChecksumMismatch { expected: String, actual: String }, // SHA-256 diverso da quello atteso
+    Transport(reqwest::Error),                          // connessione fallita, URL non valido...
+    Canceled,
+    Auth(String),     // il TokenManager non è riuscito a fornire un token
+    NoPermit(String), // nessuna connessione libera verso l'host (host:porta) entro il timeout
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DownloadError::Timeout => write!(f, "Request timed out"),
+            DownloadError::Status(status) => write!(f, "Request failed with status: {}", status),
+            DownloadError::Io(e) => write!(f, "I/O error: {}", e),
+            DownloadError::ChecksumMismatch { expected, actual } => {
+                write!(f, "Checksum mismatch: expected {}, got {}", expected, actual)
+            }
+            DownloadError::Transport(e) => write!(f, "Request failed: {}", e),
+            DownloadError::Canceled => write!(f, "Download canceled"),
+            DownloadError::Auth(e) => write!(f, "Unable to obtain a token: {}", e),
+            DownloadError::NoPermit(host) => write!(f, "No free connection to {} before the timeout", host),
+        }
+    }
+}
+
+impl std::error::Error for DownloadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DownloadError::Io(e) => Some(e),
+            DownloadError::Transport(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+// Politica di ritentativo: dopo un fallimento transitorio si riprova attendendo un backoff
+// che raddoppia ad ogni tentativo (fino a max_backoff)
+#[derive(Debug, Clone)]
+struct RetryPolicy {
+    attempts: u32, // tentativi totali, 1 = nessun ritentativo
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    retry_on: Vec<u16>, // status HTTP considerati transitori
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 1,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(5),
+            retry_on: vec![502, 503, 504],
+        }
+    }
+}
+
+// esito negativo di un singolo tentativo
+enum AttemptError {
+    Status(reqwest::StatusCode),
+    Transport(reqwest::Error),
+    Body(std::io::Error), // connessione interrotta durante la lettura del body
+    Io(std::io::Error),   // errore sul file locale: inutile riprovare
+    Canceled,
+    NoPermit(String), // nessuna connessione libera verso l'host entro il timeout
+    Auth(String),     // il TokenManager non è riuscito a fornire un token
+    Checksum { expected: String, actual: String },
+}
+
+impl From<AttemptError> for DownloadError {
+    fn from(error: AttemptError) -> Self {
+        match error {
+            AttemptError::Status(status) => DownloadError::Status(status.as_u16()),
+            AttemptError::Transport(e) if e.is_timeout() => DownloadError::Timeout,
+            AttemptError::Transport(e) => DownloadError::Transport(e),
+            AttemptError::Body(e) | AttemptError::Io(e) => DownloadError::Io(e),
+            AttemptError::Canceled => DownloadError::Canceled,
+            AttemptError::NoPermit(host) => DownloadError::NoPermit(host),
+            AttemptError::Auth(e) => DownloadError::Auth(e),
+            AttemptError::Checksum { expected, actual } => DownloadError::ChecksumMismatch { expected, actual },
+        }
+    }
+}
+
+// Confronta il digest calcolato con quello atteso (esadecimale); senza hash atteso non c'è nulla da verificare
+fn verify_sha256(expected: &Option<String>, hasher: Option<Sha256>) -> Result<(), AttemptError> {
+    let (Some(expected), Some(hasher)) = (expected, hasher) else { return Ok(()) };
+    let actual: String = hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect();
+    if actual.eq_ignore_ascii_case(expected) {
+        Ok(())
+    } else {
+        Err(AttemptError::Checksum { expected: expected.clone(), actual })
+    }
+}
+
+// hasher solo se serve, già alimentato con `bytes`
+fn hasher_for(expected: &Option<String>, bytes: &[u8]) -> Option<Sha256> {
+    expected.as_ref().map(|_| Sha256::new_with_prefix(bytes))
+}
+
+impl RetryPolicy {
+    fn should_retry(&self, error: &AttemptError) -> bool {
+        match error {
+            AttemptError::Status(status) => self.retry_on.contains(&status.as_u16()),
+            // connessione rifiutata/resettata, timeout...: si riprova, ma non per un URL malformato
+            AttemptError::Transport(e) => !e.is_builder(),
+            AttemptError::Body(_) => true,
+            AttemptError::Io(_)
+            | AttemptError::Canceled
+            | AttemptError::NoPermit(_)
+            | AttemptError::Auth(_)
+            | AttemptError::Checksum { .. } => false,
+        }
+    }
+
+    // il backoff si interrompe con la cancellazione; se terminerebbe oltre la scadenza del download
+    // non c'è tempo per un altro tentativo e si restituisce subito l'ultimo errore
+    fn run<T>(
+        &self,
+        canceled: &AtomicBool,
+        deadline: Instant,
+        mut attempt: impl FnMut() -> Result<T, AttemptError>,
+    ) -> Result<T, AttemptError> {
+        let mut backoff = self.initial_backoff;
+        let mut tries = 1;
+        loop {
+            match attempt() {
+                Err(e) if tries < self.attempts && self.should_retry(&e) => {
+                    let wake = Instant::now() + backoff;
+                    if wake >= deadline {
+                        return Err(e);
+                    }
+                    loop {
+                        if canceled.load(Ordering::SeqCst) {
+                            return Err(AttemptError::Canceled);
+                        }
+                        let left = wake.saturating_duration_since(Instant::now());
+                        if left.is_zero() {
+                            break;
+                        }
+                        thread::sleep(left.min(WAIT_STEP));
+                    }
+                    backoff = (backoff * 2).min(self.max_backoff);
+                    tries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+// connessioni contemporanee verso uno stesso host (host:porta) se non configurato diversamente
+const DEFAULT_CONNECTIONS_PER_HOST: usize = 6;
+
+// passo delle attese interrompibili (permesso dell'host, backoff): ogni quanto si controlla la cancellazione
+const WAIT_STEP: Duration = Duration::from_millis(50);
+
+// Un PermitManager per host, condiviso da tutti i Downloader del processo
+fn host_permits() -> &'static Mutex<HashMap<String, Arc<PermitManager>>> {
+    static HOSTS: OnceLock<Mutex<HashMap<String, Arc<PermitManager>>>> = OnceLock::new();
+    HOSTS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn host_key(url: &str) -> Option<String> {
+    let url = reqwest::Url::parse(url).ok()?;
+    Some(format!("{}:{}", url.host_str()?, url.port_or_known_default()?))
+}
+
+// Permesso di connessione verso un host: viene restituito quando esce dallo scope
+struct HostPermit(Arc<PermitManager>);
+
+impl HostPermit {
+    // attende a piccoli passi, così una cancellazione non resta bloccata sul semaforo
+    fn acquire(url: &str, canceled: &AtomicBool, deadline: Instant) -> Result<Option<HostPermit>, AttemptError> {
+        // un URL non valido fallirà comunque alla send(), senza occupare permessi
+        let Some(host) = host_key(url) else { return Ok(None) };
+        let permits = host_permits()
+            .lock()
+            .unwrap()
+            .entry(host.clone())
+            .or_insert_with(|| Arc::new(PermitManager::new(DEFAULT_CONNECTIONS_PER_HOST)))
+            .clone();
+        loop {
+            if canceled.load(Ordering::SeqCst) {
+                return Err(AttemptError::Canceled);
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(AttemptError::NoPermit(host));
+            }
+            if permits.acquire_timeout(left.min(WAIT_STEP)) {
+                return Ok(Some(HostPermit(permits)));
+            }
+        }
+    }
+}
+
+impl Drop for HostPermit {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+// Risposta salvata in cache: `expires` indica fin quando si può usare senza contattare il server,
+// dopo si rivalida con l'ETag (If-None-Match) se il server l'ha fornito
+#[derive(Debug, Clone)]
+struct CachedResponse {
+    body: String,
+    etag: Option<String>,
+    expires: Instant,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct CacheStats {
+    hits: u64,          // risposte servite dalla cache senza rete
+    misses: u64,        // download completi
+    revalidations: u64, // 304 Not Modified: body preso dalla cache
+}
+
+// le risposte con ETag restano in cache anche dopo la scadenza, per poterle rivalidare
+const REVALIDATION_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Cache delle risposte, con chiave l'URL, sopra il CacheManager di cache_manager/:
+// la scadenza la decidono gli header di ogni risposta invece di un TTL fisso
+struct ResponseCache {
+    entries: CacheManager<String, CachedResponse>,
+    stats: Mutex<CacheStats>,
+}
+
+impl ResponseCache {
+    fn new(max_capacity: usize) -> Self {
+        ResponseCache { entries: CacheManager::new(Duration::ZERO, max_capacity), stats: Mutex::new(CacheStats::default()) }
+    }
+
+    fn get(&self, url: &str) -> Option<CachedResponse> {
+        self.entries.get_cached_only(&url.to_string())
+    }
+
+    fn put(&self, url: &str, response: CachedResponse) -> Result<(), String> {
+        if self.entries.is_full() {
+            // prima di rifiutare si fa posto togliendo le entry scadute che non si possono rivalidare
+            self.entries.cleanup_expired();
+        }
+        let ttl = match response.etag {
+            Some(_) => REVALIDATION_TTL,
+            None => response.expires.saturating_duration_since(Instant::now()),
+        };
+        self.entries.put_with_ttl(url.to_string(), response, ttl)
+    }
+
+    fn remove(&self, url: &str) -> bool {
+        self.entries.remove(&url.to_string())
+    }
+
+    fn get_stats(&self) -> CacheStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    fn record(&self, update: impl FnOnce(&mut CacheStats)) {
+        update(&mut self.stats.lock().unwrap());
+    }
+}
+
+// Scadenza secondo Cache-Control: None se la risposta non va salvata (no-store),
+// "adesso" se va sempre rivalidata (no-cache o nessun max-age)
+fn cache_expiry(headers: &reqwest::header::HeaderMap) -> Option<Instant> {
+    let control = headers
+        .get(reqwest::header::CACHE_CONTROL)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let mut max_age = None;
+    for directive in control.split(',').map(str::trim) {
+        match directive {
+            "no-store" => return None,
+            "no-cache" => return Some(Instant::now()),
+            _ => {
+                if let Some(secs) = directive.strip_prefix("max-age=").and_then(|v| v.parse().ok()) {
+                    max_age = Some(Duration::from_secs(secs));
+                }
+            }
+        }
+    }
+    Some(Instant::now() + max_age.unwrap_or_default())
+}
+
+// Invia la richiesta con "Authorization: Bearer <token>" se c'è un TokenManager;
+// a un 401 si riprova una volta sola con un token rinnovato
+fn send_authorized(
+    tokens: &Option<Arc<TokenManager>>,
+    request: blocking::RequestBuilder,
+) -> Result<blocking::Response, AttemptError> {
+    let Some(tokens) = tokens else {
+        return request.send().map_err(AttemptError::Transport);
+    };
+    let token = tokens.get_token().map_err(AttemptError::Auth)?;
+    // le GET non hanno body, quindi la richiesta si può sempre clonare
+    let retry = request.try_clone().expect("requests without body can be cloned");
+    let resp = request.bearer_auth(&token).send().map_err(AttemptError::Transport)?;
+    if resp.status() != reqwest::StatusCode::UNAUTHORIZED {
+        return Ok(resp);
+    }
+    let token = tokens.refresh_token(&token).map_err(AttemptError::Auth)?;
+    retry.bearer_auth(&token).send().map_err(AttemptError::Transport)
+}
 
 struct Downloader {
     source: String,
-    timeout: u64
+    timeout: u64,
+    retry: RetryPolicy,
+    cache: Option<Arc<ResponseCache>>,
+    tokens: Option<Arc<TokenManager>>,
+    sha256: Option<String>,
+    mirrors: Vec<String>,
+    served_by: Mutex<Option<String>>, // URL che ha fornito l'ultimo contenuto scaricato
 }
 
 impl Downloader {
     fn new(source: &str, timeout: u64) -> Self {
-        Downloader { source: source.to_string(), timeout: timeout }
+        Downloader {
+            source: source.to_string(),
+            timeout,
+            retry: RetryPolicy::default(),
+            cache: None,
+            tokens: None,
+            sha256: None,
+            mirrors: Vec::new(),
+            served_by: Mutex::new(None),
+        }
     }
 
-    fn start(&self) -> Result<String, Box<dyn std::error::Error + Send>> {
-        let (tx, rx) = std::sync::mpsc::channel();
-        let url = self.source.clone();
+    // start/spawn/start_many passano dalla cache (condivisibile tra più Downloader);
+    // i download su file non vengono salvati
+    fn with_cache(mut self, cache: Arc<ResponseCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
 
-        thread::spawn(move || {
-            let result = (|| {
-                // thread::sleep(Duration::from_secs(10));  // TEST
-                let resp = blocking::get(&url).map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send>)?;
-                if resp.status().is_success() {
-                    let text = resp.text().map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send>)?;
-                    Ok(text)
-                } else {
-                    Err(Box::new(std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        format!("Request failed with status: {}", resp.status()),
-                    )) as Box<dyn std::error::Error + Send>)
+    // Ogni richiesta porta il token del manager; Arc e non un riferimento perché
+    // i download girano su thread propri che possono sopravvivere al chiamante
+    fn with_token_manager(mut self, tokens: Arc<TokenManager>) -> Self {
+        self.tokens = Some(tokens);
+        self
+    }
+
+    // Il contenuto scaricato deve avere questo SHA-256 (in esadecimale), altrimenti si ottiene
+    // ChecksumMismatch e, per i download su file, il file viene rimosso
+    fn expect_sha256(mut self, hash: &str) -> Self {
+        self.sha256 = Some(hash.to_string());
+        self
+    }
+
+    // URL alternativi con lo stesso contenuto: start, start_to_file e resume li provano in ordine
+    // quando la sorgente principale (o il mirror precedente) fallisce o va in timeout
+    fn with_mirrors(mut self, mirrors: &[&str]) -> Self {
+        self.mirrors = mirrors.iter().map(|m| m.to_string()).collect();
+        self
+    }
+
+    // sorgente o mirror che ha servito l'ultimo download riuscito
+    fn served_by(&self) -> Option<String> {
+        self.served_by.lock().unwrap().clone()
+    }
+
+    fn try_mirrors<T>(&self, mut fetch: impl FnMut(&str) -> Result<T, DownloadError>) -> Result<T, DownloadError> {
+        *self.served_by.lock().unwrap() = None;
+        let mut last_error = None;
+        for url in std::iter::once(&self.source).chain(&self.mirrors) {
+            match fetch(url) {
+                Ok(result) => {
+                    *self.served_by.lock().unwrap() = Some(url.clone());
+                    return Ok(result);
                 }
-            })();
+                // una cancellazione vale per tutti i mirror
+                Err(DownloadError::Canceled) => return Err(DownloadError::Canceled),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.expect("the primary source is always tried"))
+    }
 
-            // Manda il risultato al main thread
-            let _ = tx.send(result);
+    fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        // il timeout complessivo comprende anche i ritentativi
+        self.retry = retry;
+        self
+    }
+
+    // Fissa il numero massimo di connessioni contemporanee verso l'host di `url`, per tutti
+    // i Downloader; i download già in corso verso quell'host restano sul limite precedente
+    fn limit_host(url: &str, max_connections: usize) {
+        if let Some(host) = host_key(url) {
+            host_permits().lock().unwrap().insert(host, Arc::new(PermitManager::new(max_connections.max(1))));
+        }
+    }
+
+    fn start(&self) -> DownloadResult {
+        // il timeout vale per ciascun mirror
+        self.try_mirrors(|url| self.spawn_fetch(url).wait())
+    }
+
+    fn spawn(&self) -> DownloadHandle {
+        // avvia il download senza bloccare; il risultato si ottiene dall'handle (solo sorgente principale)
+        self.spawn_fetch(&self.source)
+    }
+
+    // Scarica più URL in parallelo (al massimo `parallelism` alla volta) con la stessa
+    // configurazione; i risultati sono nello stesso ordine degli URL, anche se alcuni falliscono
+    fn start_many(&self, urls: &[&str], parallelism: usize) -> Vec<(String, DownloadResult)> {
+        let next = AtomicUsize::new(0);
+        let results: Mutex<Vec<Option<DownloadResult>>> = Mutex::new((0..urls.len()).map(|_| None).collect());
+
+        thread::scope(|s| {
+            for _ in 0..parallelism.clamp(1, urls.len().max(1)) {
+                s.spawn(|| loop {
+                    // ogni thread prende il prossimo URL non ancora assegnato
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(url) = urls.get(i) else { break };
+                    let result = self.spawn_fetch(url).wait();
+                    results.lock().unwrap()[i] = Some(result);
+                });
+            }
         });
 
+        let results = results.into_inner().unwrap();
+        urls.iter()
+            .zip(results)
+            .map(|(url, result)| (url.to_string(), result.expect("every url is downloaded")))
+            .collect()
+    }
+
+    // Scarica il body a blocchi direttamente su file, senza tenerlo tutto in memoria;
+    // `progress` riceve i byte scritti finora e il totale atteso (se il server manda Content-Length)
+    fn start_to_file(
+        &self,
+        path: &Path,
+        progress: impl FnMut(u64, Option<u64>),
+    ) -> Result<u64, DownloadError> {
+        let mut progress = progress;
+        self.try_mirrors(|url| self.download_to(url, path, false, &mut progress))
+    }
+
+    // Come start_to_file, ma se il file esiste già chiede solo i byte mancanti (header Range)
+    // e li accoda; se il server non supporta i range riscarica tutto da capo
+    fn resume(
+        &self,
+        path: &Path,
+        progress: impl FnMut(u64, Option<u64>),
+    ) -> Result<u64, DownloadError> {
+        let mut progress = progress;
+        self.try_mirrors(|url| self.download_to(url, path, true, &mut progress))
+    }
+
+    fn download_to(
+        &self,
+        url: &str,
+        path: &Path,
+        resume: bool,
+        mut progress: impl FnMut(u64, Option<u64>),
+    ) -> Result<u64, DownloadError> {
+        let client = Self::client(Duration::from_secs(self.timeout)).map_err(DownloadError::Transport)?;
+        let deadline = Instant::now() + Duration::from_secs(self.timeout);
+        let never_canceled = AtomicBool::new(false);
+
+        self.retry
+            .run(&never_canceled, deadline, || {
+                // riprendendo, anche un tentativo ripetuto riparte da quanto già scritto
+                let offset = match std::fs::metadata(path) {
+                    Ok(meta) if resume => meta.len(),
+                    _ => 0,
+                };
+                let _permit = HostPermit::acquire(url, &never_canceled, deadline)?;
+                let mut request = client.get(url);
+                if offset > 0 {
+                    request = request.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+                }
+                let mut resp = send_authorized(&self.tokens, request)?;
+
+                if offset > 0 && resp.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+                    // niente oltre la fine del file: era già completo
+                    if self.sha256.is_some() {
+                        let existing = std::fs::read(path).map_err(AttemptError::Io)?;
+                        verify_sha256(&self.sha256, hasher_for(&self.sha256, &existing))?;
+                    }
+                    progress(offset, Some(offset));
+                    return Ok(offset);
+                }
+                if !resp.status().is_success() {
+                    return Err(AttemptError::Status(resp.status()));
+                }
+                let partial = offset > 0 && resp.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+                let mut written = if partial { offset } else { 0 };
+                let total = resp.content_length().map(|len| len + written);
+
+                let mut file = if partial {
+                    std::fs::OpenOptions::new().append(true).open(path)
+                } else {
+                    // download completo: si riparte da un file vuoto
+                    File::create(path)
+                }
+                .map_err(AttemptError::Io)?;
+                // accodando, il digest deve comprendere anche la parte già scaricata
+                let mut hasher = match (&self.sha256, partial) {
+                    (Some(_), true) => hasher_for(&self.sha256, &std::fs::read(path).map_err(AttemptError::Io)?),
+                    _ => hasher_for(&self.sha256, &[]),
+                };
+
+                let mut buf = vec![0; 64 * 1024];
+                progress(written, total);
+                loop {
+                    let n = resp.read(&mut buf).map_err(AttemptError::Body)?;
+                    if n == 0 {
+                        break;
+                    }
+                    file.write_all(&buf[..n]).map_err(AttemptError::Io)?;
+                    if let Some(hasher) = hasher.as_mut() {
+                        hasher.update(&buf[..n]);
+                    }
+                    written += n as u64;
+                    progress(written, total);
+                }
+                file.flush().map_err(AttemptError::Io)?;
+                if let Err(e) = verify_sha256(&self.sha256, hasher) {
+                    // un file corrotto non deve sembrare un download riuscito
+                    let _ = std::fs::remove_file(path);
+                    return Err(e);
+                }
+                Ok(written)
+            })
+            .map_err(DownloadError::from)
+    }
+
+    fn client(timeout: Duration) -> Result<blocking::Client, reqwest::Error> {
+        // il timeout del client copre connessione e lettura dell'intero body
+        blocking::Client::builder().timeout(timeout).build()
+    }
+
+    fn spawn_fetch(&self, url: &str) -> DownloadHandle {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let url = url.to_string();
+        let retry = self.retry.clone();
+        let timeout = Duration::from_secs(self.timeout);
+        let canceled = Arc::new(AtomicBool::new(false));
+        let deadline = Instant::now() + timeout;
+        let cache = self.cache.clone();
+        let tokens = self.tokens.clone();
+        let sha256 = self.sha256.clone();
+
+        {
+            let canceled = canceled.clone();
+            thread::spawn(move || {
+                let result = Self::client(timeout)
+                    .map_err(AttemptError::Transport)
+                    .and_then(|client| {
+                        retry.run(&canceled, deadline, || {
+                            // thread::sleep(Duration::from_secs(10));  // TEST
+                            if canceled.load(Ordering::SeqCst) {
+                                return Err(AttemptError::Canceled);
+                            }
+                            let cached = cache.as_ref().and_then(|cache| cache.get(&url));
+                            if let (Some(cache), Some(entry)) = (&cache, &cached) {
+                                if entry.expires > Instant::now() {
+                                    cache.record(|stats| stats.hits += 1);
+                                    verify_sha256(&sha256, hasher_for(&sha256, entry.body.as_bytes()))?;
+                                    return Ok(entry.body.clone());
+                                }
+                            }
+                            // il permesso resta occupato fino alla fine del body
+                            let _permit = HostPermit::acquire(&url, &canceled, deadline)?;
+                            let mut request = client.get(&url);
+                            if let Some(etag) = cached.as_ref().and_then(|entry| entry.etag.as_ref()) {
+                                request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+                            }
+                            let mut resp = send_authorized(&tokens, request)?;
+                            if let (Some(cache), Some(entry)) = (&cache, cached) {
+                                if resp.status() == reqwest::StatusCode::NOT_MODIFIED {
+                                    // ancora valida: si aggiorna solo la scadenza
+                                    cache.record(|stats| stats.revalidations += 1);
+                                    match cache_expiry(resp.headers()) {
+                                        Some(expires) => {
+                                            let _ = cache.put(&url, CachedResponse { expires, ..entry.clone() });
+                                        }
+                                        None => {
+                                            cache.remove(&url);
+                                        }
+                                    }
+                                    verify_sha256(&sha256, hasher_for(&sha256, entry.body.as_bytes()))?;
+                                    return Ok(entry.body);
+                                }
+                            }
+                            if !resp.status().is_success() {
+                                return Err(AttemptError::Status(resp.status()));
+                            }
+                            // lettura a blocchi: tra un blocco e l'altro si controlla la cancellazione
+                            let mut body = Vec::new();
+                            let mut buf = vec![0; 16 * 1024];
+                            loop {
+                                if canceled.load(Ordering::SeqCst) {
+                                    return Err(AttemptError::Canceled);
+                                }
+                                let n = resp.read(&mut buf).map_err(AttemptError::Body)?;
+                                if n == 0 {
+                                    break;
+                                }
+                                body.extend_from_slice(&buf[..n]);
+                            }
+                            // verifica sui byte ricevuti, prima di metterli in cache
+                            verify_sha256(&sha256, hasher_for(&sha256, &body))?;
+                            let body = String::from_utf8_lossy(&body).into_owned();
+                            if let Some(cache) = &cache {
+                                cache.record(|stats| stats.misses += 1);
+                                let etag = resp
+                                    .headers()
+                                    .get(reqwest::header::ETAG)
+                                    .and_then(|v| v.to_str().ok())
+                                    .map(str::to_string);
+                                match cache_expiry(resp.headers()) {
+                                    // cache piena: il download è comunque riuscito
+                                    Some(expires) => {
+                                        let _ = cache.put(&url, CachedResponse { body: body.clone(), etag, expires });
+                                    }
+                                    None => {
+                                        cache.remove(&url);
+                                    }
+                                }
+                            }
+                            Ok(body)
+                        })
+                    })
+                    .map_err(DownloadError::from);
+
+                // Manda il risultato al main thread
+                let _ = tx.send(result);
+            });
+        }
+
+        DownloadHandle { rx, canceled, timeout }
+    }
+}
+
+// Download in corso: wait() attende il risultato, cancel() interrompe il thread che scarica
+// (al più tardi al prossimo blocco letto, o alla scadenza del timeout del client)
+struct DownloadHandle {
+    rx: Receiver<DownloadResult>,
+    canceled: Arc<AtomicBool>,
+    timeout: Duration,
+}
+
+impl DownloadHandle {
+    fn cancel(&self) {
+        self.canceled.store(true, Ordering::SeqCst);
+    }
+
+    fn is_canceled(&self) -> bool {
+        self.canceled.load(Ordering::SeqCst)
+    }
+
+    fn wait(self) -> DownloadResult {
+        if self.is_canceled() {
+            return Err(DownloadError::Canceled);
+        }
         // Timeout gestito fuori dal thread
-        match rx.recv_timeout(Duration::from_secs(self.timeout)) {
+        match self.rx.recv_timeout(self.timeout) {
             Ok(res) => res,
-            Err(_) => Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::TimedOut,
-                "Request timed out",
-            ))),
+            Err(_) => {
+                // il thread non deve continuare a scaricare dopo il timeout
+                self.cancel();
+                Err(DownloadError::Timeout)
+            }
         }
     }
 }
@@ -55,4 +701,453 @@ pub fn main_ex3() -> Result<String, Box<dyn std::error::Error + Send>> {
     }
 
     Ok("OK".to_string())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    // server HTTP minimale in locale: `handler` riceve la richiesta e restituisce (status, body)
+    fn serve<F>(handler: F) -> String
+    where
+        F: Fn(&str) -> (u16, Vec<u8>) + Send + Sync + 'static,
+    {
+        serve_with_headers(move |req| {
+            let (status, body) = handler(req);
+            (status, String::new(), body)
+        })
+    }
+
+    // come serve, ma `handler` può aggiungere header alla risposta ("Nome: valore\r\n" ciascuno)
+    fn serve_with_headers<F>(handler: F) -> String
+    where
+        F: Fn(&str) -> (u16, String, Vec<u8>) + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handler = Arc::new(handler);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let handler = handler.clone();
+                thread::spawn(move || {
+                    let mut request = Vec::new();
+                    let mut buf = [0; 1024];
+                    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                        match stream.read(&mut buf) {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => request.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let (status, headers, body) = handler(&String::from_utf8_lossy(&request));
+                    let head = format!(
+                        "HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n",
+                        status,
+                        body.len(),
+                        headers
+                    );
+                    let _ = stream.write_all(head.as_bytes());
+                    let _ = stream.write_all(&body);
+                });
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    // path della request line ("GET /path HTTP/1.1")
+    fn path(request: &str) -> &str {
+        request.split_whitespace().nth(1).unwrap_or("/")
+    }
+
+    #[test]
+    fn start_many_keeps_order_and_partial_failures() {
+        let base = serve(|req| match path(req) {
+            "/missing" => (404, Vec::new()),
+            p => {
+                thread::sleep(Duration::from_millis(20));
+                (200, p.as_bytes().to_vec())
+            }
+        });
+        let urls: Vec<String> = ["/a", "/missing", "/b", "/c"].iter().map(|p| format!("{}{}", base, p)).collect();
+        let urls: Vec<&str> = urls.iter().map(String::as_str).collect();
+
+        let downloader = Downloader::new(&base, 5);
+        let results = downloader.start_many(&urls, 2);
+
+        assert_eq!(results.len(), 4);
+        for ((url, result), expected) in results.iter().zip(urls.iter()) {
+            assert_eq!(url, expected);
+            if url.ends_with("/missing") {
+                assert!(result.is_err());
+            } else {
+                assert_eq!(result.as_ref().unwrap(), &url[base.len()..]);
+            }
+        }
+    }
+
+    #[test]
+    fn transient_statuses_are_retried_with_backoff() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let base = {
+            let hits = hits.clone();
+            serve(move |req| match path(req) {
+                // i primi due tentativi falliscono con 502
+                "/flaky" if hits.fetch_add(1, Ordering::SeqCst) < 2 => (502, Vec::new()),
+                "/flaky" => (200, b"ok".to_vec()),
+                _ => (404, Vec::new()),
+            })
+        };
+        let retry = RetryPolicy { attempts: 3, initial_backoff: Duration::from_millis(1), ..RetryPolicy::default() };
+
+        let flaky = format!("{}/flaky", base);
+        assert!(Downloader::new(&flaky, 5).start().is_err());
+        hits.store(0, Ordering::SeqCst);
+        assert_eq!(Downloader::new(&flaky, 5).with_retry(retry.clone()).start().unwrap(), "ok");
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+        // un 404 non è transitorio: un solo tentativo
+        hits.store(0, Ordering::SeqCst);
+        let missing = format!("{}/missing", base);
+        assert!(Downloader::new(&missing, 5).with_retry(retry).start().is_err());
+    }
+
+    #[test]
+    fn backoff_stops_at_the_deadline_and_on_cancel() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let base = {
+            let hits = hits.clone();
+            serve(move |_| {
+                hits.fetch_add(1, Ordering::SeqCst);
+                (503, Vec::new())
+            })
+        };
+        let retry = RetryPolicy { attempts: 3, initial_backoff: Duration::from_secs(10), ..RetryPolicy::default() };
+
+        // un backoff oltre la scadenza non viene atteso: si restituisce subito l'ultimo errore
+        let start = Instant::now();
+        let result = Downloader::new(&base, 1).with_retry(retry.clone()).start();
+        assert!(matches!(result, Err(DownloadError::Status(503))));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // con una scadenza lontana il thread dorme nel backoff, ma la cancellazione lo sveglia
+        let handle = Downloader::new(&base, 30).with_retry(retry).spawn();
+        thread::sleep(Duration::from_millis(100));
+        handle.cancel();
+        let result = handle.rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert!(matches!(result, Err(DownloadError::Canceled)));
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn start_to_file_streams_with_progress() {
+        let body: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let base = {
+            let body = body.clone();
+            serve(move |_| (200, body.clone()))
+        };
+        let path = std::env::temp_dir().join(format!("downloader_stream_{}", std::process::id()));
+
+        let mut updates = Vec::new();
+        let written = Downloader::new(&format!("{}/big", base), 5)
+            .start_to_file(&path, |done, total| updates.push((done, total)))
+            .unwrap();
+
+        assert_eq!(written, body.len() as u64);
+        assert_eq!(std::fs::read(&path).unwrap(), body);
+        assert_eq!(updates.first(), Some(&(0, Some(body.len() as u64))));
+        assert_eq!(updates.last(), Some(&(body.len() as u64, Some(body.len() as u64))));
+        assert!(updates.windows(2).all(|w| w[0].0 < w[1].0));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    // offset richiesto dall'header Range ("range: bytes=N-"), se presente
+    fn range_start(request: &str) -> Option<usize> {
+        request
+            .lines()
+            .find_map(|line| line.to_ascii_lowercase().strip_prefix("range: bytes=").map(str::to_string))
+            .and_then(|range| range.trim_end_matches('-').parse().ok())
+    }
+
+    #[test]
+    fn resume_appends_the_missing_bytes() {
+        let body: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let base = {
+            let body = body.clone();
+            serve(move |req| match (path(req), range_start(req)) {
+                ("/ranges", Some(start)) if start >= body.len() => (416, Vec::new()),
+                ("/ranges", Some(start)) => (206, body[start..].to_vec()),
+                // questo endpoint ignora Range e manda sempre tutto
+                _ => (200, body.clone()),
+            })
+        };
+        let path = std::env::temp_dir().join(format!("downloader_resume_{}", std::process::id()));
+
+        std::fs::write(&path, &body[..4000]).unwrap();
+        let mut first = None;
+        let written = Downloader::new(&format!("{}/ranges", base), 5)
+            .resume(&path, |done, _| {
+                first.get_or_insert(done);
+            })
+            .unwrap();
+        assert_eq!(written, body.len() as u64);
+        assert_eq!(first, Some(4000));
+        assert_eq!(std::fs::read(&path).unwrap(), body);
+
+        // file già completo
+        let downloader = Downloader::new(&format!("{}/ranges", base), 5);
+        assert_eq!(downloader.resume(&path, |_, _| {}).unwrap(), body.len() as u64);
+
+        // senza supporto ai range si riscarica tutto
+        std::fs::write(&path, &body[..4000]).unwrap();
+        let written = Downloader::new(&format!("{}/plain", base), 5).resume(&path, |_, _| {}).unwrap();
+        assert_eq!(written, body.len() as u64);
+        assert_eq!(std::fs::read(&path).unwrap(), body);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn cancel_stops_the_download_thread() {
+        // server che manda il body lentamente e segnala quando il client chiude la connessione
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/slow", listener.local_addr().unwrap());
+        let (started_tx, started_rx) = std::sync::mpsc::channel();
+        let (closed_tx, closed_rx) = std::sync::mpsc::channel();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf);
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 100000000\r\n\r\n");
+            let _ = started_tx.send(());
+            let chunk = [b'x'; 1024];
+            while stream.write_all(&chunk).is_ok() {
+                thread::sleep(Duration::from_millis(5));
+            }
+            let _ = closed_tx.send(());
+        });
+
+        let handle = Downloader::new(&url, 30).spawn();
+        // si cancella a download iniziato, altrimenti il server non riceverebbe mai la richiesta
+        started_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        handle.cancel();
+        assert!(handle.is_canceled());
+        let err = handle.wait().unwrap_err();
+        assert!(err.to_string().contains("canceled"));
+        // il thread ha smesso di leggere e ha chiuso la connessione
+        assert!(closed_rx.recv_timeout(Duration::from_secs(5)).is_ok());
+    }
+
+    #[test]
+    fn connections_per_host_are_shared_across_downloaders() {
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let base = {
+            let (active, peak) = (active.clone(), peak.clone());
+            serve(move |req| {
+                let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(30));
+                active.fetch_sub(1, Ordering::SeqCst);
+                (200, path(req).as_bytes().to_vec())
+            })
+        };
+        Downloader::limit_host(&base, 2);
+        let urls: Vec<String> = (0..6).map(|i| format!("{}/{}", base, i)).collect();
+        let urls: Vec<&str> = urls.iter().map(String::as_str).collect();
+
+        // due Downloader distinti, ciascuno con parallelismo 3, verso lo stesso host
+        thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| {
+                    let results = Downloader::new(&base, 5).start_many(&urls, 3);
+                    assert!(results.iter().all(|(_, result)| result.is_ok()));
+                });
+            }
+        });
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn waiting_too_long_for_a_connection_reports_the_host() {
+        let base = serve(|_| {
+            thread::sleep(Duration::from_millis(1500));
+            (200, Vec::new())
+        });
+        Downloader::limit_host(&base, 1);
+        // l'unica connessione verso l'host resta occupata oltre il timeout del secondo download
+        let busy = Downloader::new(&base, 5).spawn();
+        thread::sleep(Duration::from_millis(100));
+        let path = std::env::temp_dir().join(format!("downloader_no_permit_{}", std::process::id()));
+        let err = Downloader::new(&base, 1).start_to_file(&path, |_, _| {}).unwrap_err();
+        let host = base.trim_start_matches("http://").to_string();
+        assert!(matches!(&err, DownloadError::NoPermit(h) if *h == host));
+        assert!(err.to_string().contains(&host));
+        assert!(busy.wait().is_ok());
+    }
+
+    // valore dell'header `name` nella richiesta, se presente
+    fn header<'a>(request: &'a str, name: &str) -> Option<&'a str> {
+        request.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.eq_ignore_ascii_case(name).then(|| value.trim())
+        })
+    }
+
+    #[test]
+    fn cache_honors_max_age_and_revalidates_with_etag() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let base = {
+            let hits = hits.clone();
+            serve_with_headers(move |req| {
+                hits.fetch_add(1, Ordering::SeqCst);
+                let etag = "ETag: \"v1\"\r\n";
+                match (path(req), header(req, "if-none-match")) {
+                    ("/config", _) => (200, format!("Cache-Control: max-age=60\r\n{}", etag), b"fresh".to_vec()),
+                    ("/etag", Some("\"v1\"")) => (304, "Cache-Control: no-cache\r\n".to_string(), Vec::new()),
+                    ("/etag", _) => (200, format!("Cache-Control: no-cache\r\n{}", etag), b"tagged".to_vec()),
+                    _ => (200, "Cache-Control: no-store\r\n".to_string(), b"private".to_vec()),
+                }
+            })
+        };
+        let cache = Arc::new(ResponseCache::new(10));
+        let fetch = |p: &str| Downloader::new(&format!("{}{}", base, p), 5).with_cache(cache.clone()).start().unwrap();
+
+        // ancora fresca: niente rete
+        assert_eq!(fetch("/config"), "fresh");
+        assert_eq!(fetch("/config"), "fresh");
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // da rivalidare ogni volta, ma il body arriva dalla cache
+        assert_eq!(fetch("/etag"), "tagged");
+        assert_eq!(fetch("/etag"), "tagged");
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+        // no-store: mai salvata
+        assert_eq!(fetch("/private"), "private");
+        assert_eq!(fetch("/private"), "private");
+        assert_eq!(hits.load(Ordering::SeqCst), 5);
+
+        assert_eq!(cache.get_stats(), CacheStats { hits: 1, misses: 4, revalidations: 1 });
+    }
+
+    #[test]
+    fn rejected_tokens_are_refreshed_once() {
+        // il server accetta solo l'ultimo token emesso
+        let issued = Arc::new(AtomicUsize::new(0));
+        let base = {
+            let issued = issued.clone();
+            serve(move |req| {
+                let valid = format!("Bearer t{}", issued.load(Ordering::SeqCst));
+                match header(req, "authorization") {
+                    Some(auth) if auth == valid => (200, b"secret".to_vec()),
+                    _ => (401, Vec::new()),
+                }
+            })
+        };
+        let acquired = Arc::new(AtomicUsize::new(0));
+        let tokens = {
+            let acquired = acquired.clone();
+            Arc::new(TokenManager::new(Box::new(move || {
+                let n = acquired.fetch_add(1, Ordering::SeqCst) + 1;
+                Ok((format!("t{}", n), Instant::now() + Duration::from_secs(60)))
+            })))
+        };
+        let downloader = Downloader::new(&format!("{}/data", base), 5).with_token_manager(tokens.clone());
+
+        issued.store(1, Ordering::SeqCst);
+        assert_eq!(downloader.start().unwrap(), "secret");
+        assert_eq!(downloader.start().unwrap(), "secret");
+        assert_eq!(acquired.load(Ordering::SeqCst), 1);
+
+        // il server revoca t1 prima della scadenza: un rinnovo e si riprova
+        issued.store(2, Ordering::SeqCst);
+        assert_eq!(downloader.start().unwrap(), "secret");
+        assert_eq!(acquired.load(Ordering::SeqCst), 2);
+
+        // anche il token nuovo è rifiutato: un solo rinnovo, poi l'errore
+        issued.store(99, Ordering::SeqCst);
+        assert!(downloader.start().is_err());
+        assert_eq!(acquired.load(Ordering::SeqCst), 3);
+    }
+
+    fn sha256_hex(bytes: &[u8]) -> String {
+        Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn checksum_is_verified_and_errors_are_typed() {
+        let base = serve(|req| match (path(req), range_start(req)) {
+            ("/artifact", Some(start)) => (206, b"release artifact"[start..].to_vec()),
+            ("/artifact", None) => (200, b"release artifact".to_vec()),
+            ("/slow", _) => {
+                thread::sleep(Duration::from_millis(1500));
+                (200, Vec::new())
+            }
+            _ => (404, Vec::new()),
+        });
+        let artifact = format!("{}/artifact", base);
+        let good = sha256_hex(b"release artifact");
+
+        let downloader = Downloader::new(&artifact, 5).expect_sha256(&good.to_uppercase());
+        assert_eq!(downloader.start().unwrap(), "release artifact");
+
+        let bad = sha256_hex(b"something else");
+        match Downloader::new(&artifact, 5).expect_sha256(&bad).start() {
+            Err(DownloadError::ChecksumMismatch { expected, actual }) => {
+                assert_eq!(expected, bad);
+                assert_eq!(actual, good);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // su file: verificato anche accodando a un download parziale, e rimosso se non corrisponde
+        let path = std::env::temp_dir().join(format!("downloader_sha256_{}", std::process::id()));
+        std::fs::write(&path, b"release").unwrap();
+        let mut first = None;
+        let written = Downloader::new(&artifact, 5)
+            .expect_sha256(&good)
+            .resume(&path, |done, _| {
+                first.get_or_insert(done);
+            })
+            .unwrap();
+        assert_eq!(first, Some(7));
+        assert_eq!(written, 16);
+        let err = Downloader::new(&artifact, 5).expect_sha256(&bad).start_to_file(&path, |_, _| {}).unwrap_err();
+        assert!(matches!(err, DownloadError::ChecksumMismatch { .. }));
+        assert!(!path.exists());
+
+        assert!(matches!(Downloader::new(&format!("{}/missing", base), 5).start(), Err(DownloadError::Status(404))));
+        assert!(matches!(Downloader::new(&format!("{}/slow", base), 1).start(), Err(DownloadError::Timeout)));
+    }
+
+    #[test]
+    fn mirrors_are_tried_in_order() {
+        // sorgente principale irraggiungibile: porta chiusa
+        let down = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}/file", listener.local_addr().unwrap())
+        };
+        let base = serve(|req| match path(req) {
+            "/busy" => (503, Vec::new()),
+            _ => (200, b"payload".to_vec()),
+        });
+        let busy = format!("{}/busy", base);
+        let ok = format!("{}/file", base);
+
+        let downloader = Downloader::new(&down, 5).with_mirrors(&[&busy, &ok]);
+        assert_eq!(downloader.start().unwrap(), "payload");
+        assert_eq!(downloader.served_by(), Some(ok.clone()));
+
+        let path = std::env::temp_dir().join(format!("downloader_mirrors_{}", std::process::id()));
+        assert_eq!(downloader.start_to_file(&path, |_, _| {}).unwrap(), 7);
+        assert_eq!(std::fs::read(&path).unwrap(), b"payload");
+        std::fs::remove_file(&path).unwrap();
+
+        // tutti falliscono: si ottiene l'errore dell'ultimo mirror
+        let downloader = Downloader::new(&down, 5).with_mirrors(&[&busy]);
+        assert!(matches!(downloader.start(), Err(DownloadError::Status(503))));
+        assert_eq!(downloader.served_by(), None);
+    }
+}
@@ -5,48 +5,534 @@
 // centralizzata e thread-safe per raccogliere e consultare in tempo reale il numero di eventi per ciascuna categoria.
 // A tale scopo, si implementi una struttura EventCounter dotata dei seguenti metodi:
 
-use std::sync::Mutex;
+use std::collections::hash_map::DefaultHasher;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 
 fn main() {
     println!("Hello, world!");
 }
 
+const SHARDS: usize = 16;
+
+/// Categoria in cui confluiscono gli eventi delle categorie rifiutate dai limiti di cardinalità.
+pub const OTHER_CATEGORY: &str = "other";
+
+/// Finestra massima (in secondi) su cui può essere calcolato `get_rate`.
+pub const MAX_RATE_WINDOW_SECS: u64 = 300;
+
+/// Callback di soglia: riceve la categoria e il conteggio che ha raggiunto la soglia.
+pub type ThresholdFn = dyn Fn(&str, usize) + Send + Sync;
+
+/// Contatori di una categoria: il totale e gli eventi degli ultimi secondi, a bucket di un secondo.
+struct Category {
+    count: AtomicUsize,
+    recent: Mutex<VecDeque<(u64, usize)>>, // (secondo dalla creazione del contatore, eventi)
+    thresholds: Mutex<Vec<(usize, Arc<ThresholdFn>)>>,
+    sources: Mutex<HashMap<String, usize>>, // eventi per sensore, solo per quelli con sorgente
+    history: Mutex<VecDeque<(u64, usize)>>, // (indice del bucket, eventi), solo con storico attivo
+}
+
+/// Configurazione dello storico a bucket: durata di un bucket e numero di bucket conservati.
+#[derive(Clone, Copy, Debug)]
+struct HistoryConfig {
+    bucket: Duration,
+    buckets: u64,
+}
+
+/// Istante di un evento, già tradotto nei bucket usati dai contatori.
+struct Stamp {
+    second: u64,
+    bucket: Option<(u64, u64)>, // (indice del bucket, bucket conservati)
+}
+
+impl Category {
+    fn new() -> Self {
+        Category {
+            count: AtomicUsize::new(0),
+            recent: Mutex::new(VecDeque::new()),
+            thresholds: Mutex::new(Vec::new()),
+            sources: Mutex::new(HashMap::new()),
+            history: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Aggiunge `n` eventi e restituisce le callback delle soglie appena raggiunte,
+    /// da invocare dopo aver rilasciato i lock. `notify` riceve il nuovo conteggio sotto il lock
+    /// della categoria, così gli aggiornamenti di una stessa categoria sono notificati in ordine.
+    fn add(
+        &self,
+        n: usize,
+        stamp: &Stamp,
+        source: Option<&str>,
+        notify: impl FnOnce(usize),
+    ) -> Vec<(usize, Arc<ThresholdFn>)> {
+        if let Some(source) = source {
+            *self.sources.lock().unwrap().entry(source.to_string()).or_insert(0) += n;
+        }
+        if let Some((index, retained)) = stamp.bucket {
+            push_bucket(&mut self.history.lock().unwrap(), index, n, retained);
+        }
+        let second = stamp.second;
+        let mut recent = self.recent.lock().unwrap();
+        let old = self.count.fetch_add(n, Ordering::Relaxed);
+        notify(old + n);
+        push_bucket(&mut recent, second, n, MAX_RATE_WINDOW_SECS);
+        drop(recent);
+        let new = old + n;
+        self.thresholds
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(limit, _)| old < *limit && *limit <= new)
+            .map(|(_, callback)| (new, callback.clone()))
+            .collect()
+    }
+
+    /// Azzera i contatori e restituisce il conteggio precedente: gli incrementi avvengono sotto
+    /// lo stesso lock, quindi ogni evento è contato o nel valore restituito o dopo l'azzeramento.
+    fn reset(&self, notify: impl FnOnce(usize)) -> usize {
+        let mut recent = self.recent.lock().unwrap();
+        let previous = self.count.swap(0, Ordering::Relaxed);
+        notify(0);
+        recent.clear();
+        self.sources.lock().unwrap().clear();
+        previous
+    }
+}
+
+/// Aggiunge `n` eventi al bucket `index` e scarta i bucket più vecchi degli ultimi `retained`.
+/// L'indice viene calcolato prima di prendere il lock, quindi due eventi a cavallo di un bucket
+/// possono arrivare in ordine inverso: i bucket restano ordinati e ogni indice compare una volta sola.
+fn push_bucket(buckets: &mut VecDeque<(u64, usize)>, index: u64, n: usize, retained: u64) {
+    // in genere il bucket è l'ultimo, quindi la ricerca parte dal fondo
+    let after = buckets.iter().rposition(|(i, _)| *i <= index);
+    match after {
+        Some(pos) if buckets[pos].0 == index => buckets[pos].1 += n,
+        Some(pos) => buckets.insert(pos + 1, (index, n)),
+        None => buckets.push_front((index, n)),
+    }
+    let newest = buckets.back().map_or(index, |(i, _)| *i);
+    while buckets.front().is_some_and(|(i, _)| *i + retained <= newest) {
+        buckets.pop_front();
+    }
+}
+
+/// Notifica del flusso di aggiornamenti: (categoria, nuovo conteggio).
+pub type Update = (String, usize);
+
+/// Canali degli iscritti al flusso di aggiornamenti, identificati per poter rimuovere quelli chiusi.
+struct Feed {
+    subscribers: RwLock<Vec<(usize, Sender<Update>)>>,
+    next_id: AtomicUsize,
+}
+
+impl Feed {
+    fn publish(&self, category: &str, count: usize) {
+        let subscribers = self.subscribers.read().unwrap();
+        if subscribers.is_empty() {
+            return;
+        }
+        let closed: Vec<usize> = subscribers
+            .iter()
+            .filter(|(_, tx)| tx.send((category.to_string(), count)).is_err())
+            .map(|(id, _)| *id)
+            .collect();
+        drop(subscribers);
+        if !closed.is_empty() {
+            self.subscribers.write().unwrap().retain(|(id, _)| !closed.contains(id));
+        }
+    }
+}
+
+/// Stato salvabile del contatore: conteggi totali e ripartizione per sorgente di ogni categoria.
+/// Frequenze e soglie non fanno parte del checkpoint.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CounterSnapshot {
+    pub categories: Vec<CategorySnapshot>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CategorySnapshot {
+    pub category: String,
+    pub count: usize,
+    pub sources: Vec<(String, usize)>,
+}
+
+/// Una porzione delle categorie, scelta in base all'hash del nome.
+/// I contatori sono atomici: il lock in scrittura serve solo per creare nuove categorie.
+type Shard = RwLock<HashMap<String, Category>>;
+
 pub struct EventCounter { 
-    category_counter: Mutex<Vec<(String, usize)>>,
+    shards: Vec<Shard>,
+    start: Instant,
+    feed: Feed,
+    history: Option<HistoryConfig>,
+    max_categories: Option<usize>,   // OTHER_CATEGORY esclusa
+    allowed: Option<HashSet<String>>, // solo queste categorie possono essere create
+    categories: AtomicUsize,          // categorie create, OTHER_CATEGORY esclusa
 }
 
 impl EventCounter {
     pub fn new() -> Self {
-        EventCounter { category_counter: Mutex::new(Vec::new()) }
+        Self::build(None)
+    }
+
+    /// Come `new`, ma conserva per ogni categoria i conteggi a bucket di durata `bucket`
+    /// (es. un minuto) per gli ultimi `retention`, consultabili con `get_series`.
+    pub fn with_history(bucket: Duration, retention: Duration) -> Self {
+        let bucket = bucket.max(Duration::from_millis(1));
+        let buckets = (retention.as_nanos() / bucket.as_nanos()).max(1) as u64;
+        Self::build(Some(HistoryConfig { bucket, buckets }))
+    }
+
+    fn build(history: Option<HistoryConfig>) -> Self {
+        EventCounter {
+            shards: (0..SHARDS).map(|_| RwLock::new(HashMap::new())).collect(),
+            start: Instant::now(),
+            feed: Feed { subscribers: RwLock::new(Vec::new()), next_id: AtomicUsize::new(0) },
+            history,
+            max_categories: None,
+            allowed: None,
+            categories: AtomicUsize::new(0),
+        }
+    }
+
+    /// Limita il numero di categorie distinte: oltre `max`, gli eventi delle nuove categorie
+    /// vengono contati in `OTHER_CATEGORY`, così la memoria resta limitata anche con input non fidati.
+    pub fn limit_categories(mut self, max: usize) -> Self {
+        self.max_categories = Some(max);
+        self
+    }
+
+    /// Accetta solo le categorie indicate: gli eventi di tutte le altre vengono contati in `OTHER_CATEGORY`.
+    pub fn only_categories(mut self, categories: &[&str]) -> Self {
+        self.allowed = Some(categories.iter().map(|cat| cat.to_string()).collect());
+        self
+    }
+
+    /// Riserva il posto per una nuova categoria; false se i limiti non lo consentono.
+    fn admit(&self, category: &str) -> bool {
+        if category == OTHER_CATEGORY {
+            return true;
+        }
+        if self.allowed.as_ref().is_some_and(|allowed| !allowed.contains(category)) {
+            return false;
+        }
+        match self.max_categories {
+            Some(max) => self
+                .categories
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| (n < max).then_some(n + 1))
+                .is_ok(),
+            None => {
+                self.categories.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+        }
+    }
+
+    fn current_second(&self) -> u64 {
+        self.start.elapsed().as_secs()
+    }
+
+    fn bucket_index(&self, config: &HistoryConfig, at: Instant) -> u64 {
+        (at.saturating_duration_since(self.start).as_nanos() / config.bucket.as_nanos()) as u64
+    }
+
+    fn stamp(&self) -> Stamp {
+        let now = Instant::now();
+        Stamp {
+            second: now.saturating_duration_since(self.start).as_secs(),
+            bucket: self.history.map(|config| (self.bucket_index(&config, now), config.buckets)),
+        }
+    }
+
+    /// Esegue `f` sui contatori della categoria, creandola se necessario; se i limiti non
+    /// consentono di crearla, `f` riceve `OTHER_CATEGORY` con i suoi contatori.
+    fn with_category<'a, R>(&self, category: &'a str, f: impl FnOnce(&'a str, &Category) -> R) -> R {
+        match self.with_admitted(category, f) {
+            Ok(result) => result,
+            Err(f) => self.with_category(OTHER_CATEGORY, f),
+        }
+    }
+
+    /// Come `with_category`, ma se i limiti non consentono di creare la categoria
+    /// restituisce `f` senza eseguirla (e senza lock, OTHER_CATEGORY potrebbe stare nello stesso shard).
+    fn with_admitted<'a, R, F: FnOnce(&'a str, &Category) -> R>(&self, category: &'a str, f: F) -> Result<R, F> {
+        let shard = self.shard(category);
+        // caso frequente: la categoria esiste già, basta il lock in lettura
+        if let Some(counters) = shard.read().unwrap().get(category) {
+            return Ok(f(category, counters));
+        }
+        let mut collection = shard.write().unwrap();
+        if let Some(counters) = collection.get(category) {
+            return Ok(f(category, counters));
+        }
+        if self.admit(category) {
+            return Ok(f(category, collection.entry(category.to_string()).or_insert_with(Category::new)));
+        }
+        Err(f)
+    }
+
+    fn shard(&self, category: &str) -> &Shard {
+        let mut hasher = DefaultHasher::new();
+        category.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % SHARDS]
     }
 
     /// Registra un nuovo evento per la categoria specificata.
     /// Se la categoria non è ancora presente, viene creata.
     pub fn record_event(&self, category: &str) {
-        let mut collection = self.category_counter.lock().unwrap();
-        if let Some((_, count)) = collection.iter_mut().find(|(cat, _)| cat == category) {
-            *count += 1;
-        } else {
-            collection.push((category.to_string(), 1));
+        self.record_events(category, 1);
+    }
+
+    /// Registra `n` eventi della stessa categoria con un'unica operazione.
+    /// Se la categoria non è ancora presente, viene creata (anche con `n == 0`).
+    pub fn record_events(&self, category: &str, n: usize) {
+        self.record(category, None, n);
+    }
+
+    /// Come `record_event`, indicando anche la sorgente (es. l'id del sensore) che ha generato l'evento.
+    pub fn record_event_from(&self, category: &str, source: Option<&str>) {
+        self.record(category, source, 1);
+    }
+
+    fn record(&self, category: &str, source: Option<&str>, n: usize) {
+        let stamp = self.stamp();
+        let (category, reached) = self.with_category(category, |category, counters| {
+            (category, counters.add(n, &stamp, source, |count| self.feed.publish(category, count)))
+        });
+        for (count, callback) in reached {
+            callback(category, count);
+        }
+    }
+
+    /// Registra una callback invocata (dal thread che registra l'evento) quando il conteggio
+    /// della categoria raggiunge o supera `limit`. Dopo un `reset` la soglia può scattare di nuovo.
+    /// La categoria viene creata se non esiste ancora; se i limiti non consentono di crearla
+    /// la soglia non viene registrata e si ottiene false (non viene spostata su `OTHER_CATEGORY`).
+    pub fn on_threshold(&self, category: &str, limit: usize, callback: impl Fn(&str, usize) + Send + Sync + 'static) -> bool {
+        self.with_admitted(category, |_, counters| {
+            counters.thresholds.lock().unwrap().push((limit, Arc::new(callback)));
+        })
+        .is_ok()
+    }
+
+    /// Azzera il conteggio (e la frequenza) della categoria, che resta registrata.
+    pub fn reset(&self, category: &str) {
+        if let Some(counters) = self.shard(category).read().unwrap().get(category) {
+            counters.reset(|count| self.feed.publish(category, count));
         }
     }
 
+    /// Azzera tutte le categorie, che restano registrate.
+    pub fn reset_all(&self) {
+        for shard in &self.shards {
+            for (cat, counters) in shard.read().unwrap().iter() {
+                counters.reset(|count| self.feed.publish(cat, count));
+            }
+        }
+    }
+
+    /// Restituisce un canale su cui viene inviato (categoria, nuovo conteggio) a ogni modifica,
+    /// reset compresi. Gli aggiornamenti di una stessa categoria arrivano in ordine;
+    /// il canale viene dimenticato alla prima notifica dopo la chiusura del ricevitore.
+    pub fn subscribe(&self) -> Receiver<Update> {
+        let (tx, rx) = channel();
+        let id = self.feed.next_id.fetch_add(1, Ordering::Relaxed);
+        self.feed.subscribers.write().unwrap().push((id, tx));
+        rx
+    }
+
+    /// Restituisce tutte le categorie con i relativi conteggi (come `snapshot`) e le azzera,
+    /// senza perdere gli eventi registrati nel frattempo: ognuno finisce o nel risultato
+    /// o nel conteggio successivo. L'ordine non è rilevante.
+    pub fn drain(&self) -> Vec<(String, usize)> {
+        let mut drained = Vec::new();
+        for shard in &self.shards {
+            for (cat, counters) in shard.read().unwrap().iter() {
+                drained.push((cat.clone(), counters.reset(|count| self.feed.publish(cat, count))));
+            }
+        }
+        drained
+    }
+
     /// Restituisce il numero di eventi registrati per una data categoria.
     /// Se la categoria non è mai stata vista, restituisce 0.
     pub fn get_count(&self, category: &str) -> usize {
-        let collection = self.category_counter.lock().unwrap();
-        if let Some((_, count)) = collection.iter().find(|(cat, _)| cat == category) {
-            return *count
-        } else {
-            return 0
+        let collection = self.shard(category).read().unwrap();
+        collection.get(category).map_or(0, |counters| counters.count.load(Ordering::Relaxed))
+    }
+
+    /// Restituisce la somma dei conteggi della categoria `prefix` e di tutte le sue sottocategorie
+    /// (separate da '.'): "sensor.temperature" include "sensor.temperature.room1" ma non "sensor.temperatures".
+    /// Con prefisso vuoto somma tutte le categorie.
+    pub fn get_count_prefix(&self, prefix: &str) -> usize {
+        let is_child = |cat: &str| {
+            cat.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+        };
+        self.shards
+            .iter()
+            .map(|shard| {
+                shard
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .filter(|(cat, _)| prefix.is_empty() || is_child(cat))
+                    .map(|(_, counters)| counters.count.load(Ordering::Relaxed))
+                    .sum::<usize>()
+            })
+            .sum()
+    }
+
+    /// Restituisce il numero di eventi della categoria per ciascuna sorgente.
+    /// Gli eventi registrati senza sorgente non compaiono (ma sono inclusi in `get_count`).
+    pub fn get_count_by_source(&self, category: &str) -> Vec<(String, usize)> {
+        match self.shard(category).read().unwrap().get(category) {
+            Some(counters) => counters.sources.lock().unwrap().iter().map(|(src, n)| (src.clone(), *n)).collect(),
+            None => Vec::new(),
         }
     }
 
     /// Restituisce una lista di tutte le categorie e i relativi conteggi.
     /// L'ordine non è rilevante.
     pub fn snapshot(&self) -> Vec<(String, usize)> {
-        let collection = self.category_counter.lock().unwrap();
-        return collection.clone()
+        let mut snapshot = Vec::new();
+        for shard in &self.shards {
+            let collection = shard.read().unwrap();
+            snapshot.extend(collection.iter().map(|(cat, counters)| (cat.clone(), counters.count.load(Ordering::Relaxed))));
+        }
+        snapshot
+    }
+
+    /// Fotografa conteggi e sorgenti di tutte le categorie, per salvarli e ripristinarli con `restore` o `restore_into`.
+    pub fn export(&self) -> CounterSnapshot {
+        let mut categories = Vec::new();
+        for shard in &self.shards {
+            for (cat, counters) in shard.read().unwrap().iter() {
+                categories.push(CategorySnapshot {
+                    category: cat.clone(),
+                    count: counters.count.load(Ordering::Relaxed),
+                    sources: counters.sources.lock().unwrap().iter().map(|(src, n)| (src.clone(), *n)).collect(),
+                });
+            }
+        }
+        CounterSnapshot { categories }
+    }
+
+    /// Crea un contatore senza storico né limiti a partire da un checkpoint
+    /// (per mantenere la configurazione originale si usa `restore_into`).
+    pub fn restore(snapshot: CounterSnapshot) -> Self {
+        let counter = EventCounter::new();
+        counter.restore_into(snapshot);
+        counter
+    }
+
+    /// Aggiunge i conteggi di un checkpoint a questo contatore, già configurato come l'originale
+    /// (storico, limiti e categorie ammesse): le categorie rifiutate dai limiti confluiscono in `OTHER_CATEGORY`.
+    pub fn restore_into(&self, snapshot: CounterSnapshot) {
+        for saved in snapshot.categories {
+            self.with_category(&saved.category, |_, counters| {
+                counters.count.fetch_add(saved.count, Ordering::Relaxed);
+                let mut sources = counters.sources.lock().unwrap();
+                for (source, n) in saved.sources {
+                    *sources.entry(source).or_insert(0) += n;
+                }
+            });
+        }
+    }
+
+    /// Serializza il checkpoint in JSON.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(&self.export())
+    }
+
+    /// Ricostruisce un contatore da un checkpoint JSON prodotto da `to_json`.
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        Ok(Self::restore(serde_json::from_str(json)?))
+    }
+
+    /// Come `restore_into`, per un checkpoint JSON prodotto da `to_json`.
+    pub fn restore_json(&self, json: &str) -> serde_json::Result<()> {
+        self.restore_into(serde_json::from_str(json)?);
+        Ok(())
+    }
+
+    /// Restituisce le `k` categorie con più eventi, in ordine decrescente di conteggio
+    /// (a parità di conteggio, in ordine alfabetico). Usa un heap di dimensione `k`
+    /// invece di ordinare tutte le categorie.
+    pub fn top_k(&self, k: usize) -> Vec<(String, usize)> {
+        if k == 0 {
+            return Vec::new();
+        }
+        // min-heap: in cima c'è la peggiore delle k migliori finora
+        let mut heap: BinaryHeap<Reverse<(usize, Reverse<String>)>> = BinaryHeap::with_capacity(k + 1);
+        for shard in &self.shards {
+            for (cat, counters) in shard.read().unwrap().iter() {
+                let count = counters.count.load(Ordering::Relaxed);
+                if heap.len() == k && heap.peek().is_some_and(|Reverse((min, _))| count < *min) {
+                    continue; // evita di clonare il nome se non può entrare
+                }
+                heap.push(Reverse((count, Reverse(cat.clone()))));
+                if heap.len() > k {
+                    heap.pop();
+                }
+            }
+        }
+        heap.into_sorted_vec().into_iter().map(|Reverse((count, Reverse(cat)))| (cat, count)).collect()
+    }
+
+    /// Restituisce gli eventi della categoria per bucket, come (inizio del bucket, eventi), per i bucket
+    /// che iniziano in `[from, to)` e sono ancora conservati; i bucket senza eventi valgono 0.
+    /// Senza storico attivo (vedi `with_history`) restituisce un vettore vuoto.
+    pub fn get_series(&self, category: &str, from: Instant, to: Instant) -> Vec<(Instant, usize)> {
+        let Some(config) = self.history else {
+            return Vec::new();
+        };
+        let current = self.bucket_index(&config, Instant::now());
+        let oldest = (current + 1).saturating_sub(config.buckets);
+        let bucket_start = |i: u64| self.start + config.bucket * i as u32;
+        // primo bucket che inizia in `from` o dopo, ultimo che inizia prima di `to`
+        let mut first = self.bucket_index(&config, from);
+        if bucket_start(first) < from {
+            first += 1;
+        }
+        let first = first.max(oldest);
+        let last = match to.checked_sub(Duration::from_nanos(1)) {
+            Some(t) if t >= self.start => self.bucket_index(&config, t).min(current),
+            _ => return Vec::new(),
+        };
+
+        let collection = self.shard(category).read().unwrap();
+        let history = collection.get(category).map(|counters| counters.history.lock().unwrap().clone());
+        (first..=last)
+            .map(|i| {
+                let count = history
+                    .as_ref()
+                    .map_or(0, |h| h.iter().filter(|(index, _)| *index == i).map(|(_, n)| n).sum());
+                (bucket_start(i), count)
+            })
+            .collect()
+    }
+
+    /// Restituisce la frequenza (eventi al secondo) della categoria nell'ultima finestra `window`,
+    /// arrotondata al secondo e limitata a `MAX_RATE_WINDOW_SECS`; il secondo corrente è incluso.
+    pub fn get_rate(&self, category: &str, window: Duration) -> f64 {
+        let secs = window.as_secs().clamp(1, MAX_RATE_WINDOW_SECS);
+        let now = self.current_second();
+        let collection = self.shard(category).read().unwrap();
+        let Some(counters) = collection.get(category) else {
+            return 0.0;
+        };
+        let recent = counters.recent.lock().unwrap();
+        let events: usize = recent.iter().filter(|(s, _)| s + secs > now).map(|(_, n)| n).sum();
+        events as f64 / secs as f64
     }
 }
 
@@ -80,6 +566,351 @@ fn snapshot_returns_all_counts() {
     assert_eq!(snapshot, vec![("a".to_string(), 2), ("b".to_string(), 1)]);
 }
 
+#[test]
+fn rate_counts_only_recent_events() {
+    let counter = EventCounter::new();
+    assert_eq!(counter.get_rate("motion", Duration::from_secs(2)), 0.0);
+    for _ in 0..10 {
+        counter.record_event("motion");
+    }
+    assert_eq!(counter.get_rate("motion", Duration::from_secs(2)), 5.0);
+    assert_eq!(counter.get_rate("motion", Duration::from_secs(10)), 1.0);
+    assert_eq!(counter.get_rate("power_loss", Duration::from_secs(2)), 0.0);
+}
+
+#[test]
+fn rate_forgets_events_outside_the_window() {
+    let counter = EventCounter::new();
+    counter.record_event("motion");
+    std::thread::sleep(Duration::from_millis(2100));
+    assert_eq!(counter.get_rate("motion", Duration::from_secs(1)), 0.0);
+    assert_eq!(counter.get_count("motion"), 1);
+}
+
+#[test]
+fn record_events_adds_in_bulk() {
+    let counter = EventCounter::new();
+    counter.record_events("motion", 5);
+    counter.record_event("motion");
+    counter.record_events("power_loss", 0);
+    assert_eq!(counter.get_count("motion"), 6);
+    let mut snapshot = counter.snapshot();
+    snapshot.sort();
+    assert_eq!(snapshot, vec![("motion".to_string(), 6), ("power_loss".to_string(), 0)]);
+}
+
+#[test]
+fn reset_zeroes_counts_but_keeps_categories() {
+    let counter = EventCounter::new();
+    counter.record_events("a", 3);
+    counter.record_events("b", 2);
+    counter.reset("a");
+    counter.reset("unknown");
+    assert_eq!(counter.get_count("a"), 0);
+    assert_eq!(counter.get_count("b"), 2);
+    assert_eq!(counter.get_rate("a", Duration::from_secs(1)), 0.0);
+
+    counter.reset_all();
+    let mut snapshot = counter.snapshot();
+    snapshot.sort();
+    assert_eq!(snapshot, vec![("a".to_string(), 0), ("b".to_string(), 0)]);
+}
+
+#[test]
+fn top_k_returns_the_busiest_categories() {
+    let counter = EventCounter::new();
+    counter.record_events("motion", 5);
+    counter.record_events("temperature", 9);
+    counter.record_events("power_loss", 1);
+    counter.record_events("door", 5);
+
+    assert_eq!(
+        counter.top_k(3),
+        vec![("temperature".to_string(), 9), ("door".to_string(), 5), ("motion".to_string(), 5)]
+    );
+    assert_eq!(counter.top_k(10).len(), 4);
+    assert!(counter.top_k(0).is_empty());
+}
+
+#[test]
+fn threshold_callback_fires_once_when_crossed() {
+    use std::sync::Arc;
+
+    let counter = EventCounter::new();
+    let fired = Arc::new(Mutex::new(Vec::new()));
+    let f = fired.clone();
+    counter.on_threshold("power_loss", 3, move |cat, count| f.lock().unwrap().push((cat.to_string(), count)));
+
+    counter.record_event("power_loss");
+    counter.record_event("power_loss");
+    assert!(fired.lock().unwrap().is_empty());
+    counter.record_events("power_loss", 2);
+    counter.record_event("power_loss");
+    assert_eq!(*fired.lock().unwrap(), vec![("power_loss".to_string(), 4)]);
+
+    counter.reset("power_loss");
+    counter.record_events("power_loss", 3);
+    assert_eq!(fired.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn threshold_callback_can_record_events() {
+    use std::sync::Arc;
+
+    let counter = Arc::new(EventCounter::new());
+    let c = counter.clone();
+    counter.on_threshold("motion", 1, move |_, _| c.record_event("alarm"));
+    counter.record_event("motion");
+    assert_eq!(counter.get_count("alarm"), 1);
+}
+
+#[test]
+fn threshold_on_a_rejected_category_is_not_registered() {
+    use std::sync::Arc;
+
+    let counter = EventCounter::new().limit_categories(1).only_categories(&["motion", "power_loss"]);
+    let fired = Arc::new(AtomicUsize::new(0));
+    let f = fired.clone();
+    assert!(!counter.on_threshold("temperature", 1, move |_, _| {
+        f.fetch_add(1, Ordering::Relaxed);
+    }));
+    assert!(counter.on_threshold("motion", 1, |_, _| {}));
+    assert!(!counter.on_threshold("power_loss", 1, |_, _| {})); // il limite è già raggiunto
+
+    counter.record_event("temperature");
+    counter.record_event("power_loss");
+    assert_eq!(counter.get_count(OTHER_CATEGORY), 2);
+    assert_eq!(fired.load(Ordering::Relaxed), 0);
+    assert_eq!(counter.snapshot().len(), 2); // motion e OTHER_CATEGORY
+}
+
+#[test]
+fn prefix_count_sums_the_hierarchy() {
+    let counter = EventCounter::new();
+    counter.record_events("sensor.temperature.room1", 2);
+    counter.record_events("sensor.temperature.room2", 3);
+    counter.record_event("sensor.temperature");
+    counter.record_events("sensor.temperatures", 7);
+    counter.record_events("sensor.motion.room1", 4);
+
+    assert_eq!(counter.get_count_prefix("sensor.temperature"), 6);
+    assert_eq!(counter.get_count_prefix("sensor.temperature.room1"), 2);
+    assert_eq!(counter.get_count_prefix("sensor"), 17);
+    assert_eq!(counter.get_count_prefix("sens"), 0);
+    assert_eq!(counter.get_count_prefix(""), 17);
+}
+
+#[test]
+fn counts_are_broken_down_by_source() {
+    let counter = EventCounter::new();
+    counter.record_event_from("power_loss", Some("sensor_1"));
+    counter.record_event_from("power_loss", Some("sensor_2"));
+    counter.record_event_from("power_loss", Some("sensor_1"));
+    counter.record_event_from("power_loss", None);
+    counter.record_event("power_loss");
+
+    let mut by_source = counter.get_count_by_source("power_loss");
+    by_source.sort();
+    assert_eq!(by_source, vec![("sensor_1".to_string(), 2), ("sensor_2".to_string(), 1)]);
+    assert_eq!(counter.get_count("power_loss"), 5);
+    assert!(counter.get_count_by_source("motion").is_empty());
+
+    counter.reset("power_loss");
+    assert!(counter.get_count_by_source("power_loss").is_empty());
+}
+
+#[test]
+fn json_checkpoint_restores_counts_and_sources() {
+    let counter = EventCounter::new();
+    counter.record_events("motion", 3);
+    counter.record_event_from("power_loss", Some("sensor_1"));
+    counter.record_event_from("power_loss", None);
+
+    let json = counter.to_json().unwrap();
+    let restored = EventCounter::from_json(&json).unwrap();
+
+    let mut snapshot = restored.snapshot();
+    snapshot.sort();
+    assert_eq!(snapshot, vec![("motion".to_string(), 3), ("power_loss".to_string(), 2)]);
+    assert_eq!(restored.get_count_by_source("power_loss"), vec![("sensor_1".to_string(), 1)]);
+
+    restored.record_event("motion");
+    assert_eq!(restored.get_count("motion"), 4);
+    assert!(EventCounter::from_json("not json").is_err());
+}
+
+#[test]
+fn restore_into_keeps_the_configuration() {
+    let original = EventCounter::new();
+    original.record_events("motion", 3);
+    original.record_event("power_loss");
+    original.record_event_from("temperature", Some("sensor_1"));
+    let json = original.to_json().unwrap();
+
+    let restored = EventCounter::with_history(Duration::from_secs(1), Duration::from_secs(10))
+        .only_categories(&["motion", "temperature"]);
+    restored.restore_json(&json).unwrap();
+    assert_eq!(restored.get_count("motion"), 3);
+    assert_eq!(restored.get_count(OTHER_CATEGORY), 1);
+    assert_eq!(restored.get_count_by_source("temperature"), vec![("sensor_1".to_string(), 1)]);
+
+    // limiti e storico valgono anche dopo il ripristino
+    restored.record_event("power_loss");
+    assert_eq!(restored.get_count(OTHER_CATEGORY), 2);
+    let start = Instant::now() - Duration::from_secs(1);
+    assert!(!restored.get_series("motion", start, Instant::now()).is_empty());
+    assert!(restored.restore_json("not json").is_err());
+}
+
+#[test]
+fn subscribers_receive_every_update() {
+    let counter = EventCounter::new();
+    let rx = counter.subscribe();
+    counter.record_event("motion");
+    counter.record_events("motion", 2);
+    counter.record_event_from("power_loss", Some("sensor_1"));
+    counter.reset("motion");
+
+    let updates: Vec<(String, usize)> = rx.try_iter().collect();
+    assert_eq!(
+        updates,
+        vec![
+            ("motion".to_string(), 1),
+            ("motion".to_string(), 3),
+            ("power_loss".to_string(), 1),
+            ("motion".to_string(), 0),
+        ]
+    );
+}
+
+#[test]
+fn closed_subscribers_are_dropped() {
+    let counter = EventCounter::new();
+    drop(counter.subscribe());
+    let rx = counter.subscribe();
+    counter.record_event("motion");
+    counter.record_event("motion");
+    assert_eq!(counter.feed.subscribers.read().unwrap().len(), 1);
+    assert_eq!(rx.try_iter().last(), Some(("motion".to_string(), 2)));
+}
+
+#[test]
+fn history_series_is_bucketed() {
+    let counter = EventCounter::with_history(Duration::from_millis(100), Duration::from_millis(300));
+    let start = Instant::now();
+    counter.record_events("motion", 2);
+    std::thread::sleep(Duration::from_millis(120));
+    counter.record_event("motion");
+    std::thread::sleep(Duration::from_millis(100));
+
+    let series: Vec<usize> = counter
+        .get_series("motion", start - Duration::from_secs(1), Instant::now())
+        .iter()
+        .map(|(_, n)| *n)
+        .collect();
+    assert_eq!(series[..2], [2, 1]);
+    assert!(series[2..].iter().all(|n| *n == 0));
+    assert!(series.len() <= 3);
+
+    std::thread::sleep(Duration::from_millis(300));
+    counter.record_event("motion");
+    let series = counter.get_series("motion", start, Instant::now());
+    assert_eq!(series.len(), 3);
+    assert_eq!(series.iter().map(|(_, n)| n).sum::<usize>(), 1);
+    assert!(EventCounter::new().get_series("motion", start, Instant::now()).is_empty());
+}
+
+#[test]
+fn out_of_order_buckets_are_merged() {
+    // due record a cavallo di un bucket possono prendere il lock in ordine inverso
+    let mut buckets = VecDeque::new();
+    push_bucket(&mut buckets, 5, 1, 3);
+    push_bucket(&mut buckets, 4, 2, 3);
+    push_bucket(&mut buckets, 5, 1, 3);
+    push_bucket(&mut buckets, 4, 1, 3);
+    assert_eq!(buckets, [(4, 3), (5, 2)]);
+    push_bucket(&mut buckets, 7, 1, 3);
+    push_bucket(&mut buckets, 6, 1, 3);
+    push_bucket(&mut buckets, 3, 1, 3); // già fuori dalla finestra conservata
+    assert_eq!(buckets, [(5, 2), (6, 1), (7, 1)]);
+}
+
+#[test]
+fn categories_over_the_limit_go_to_other() {
+    let counter = EventCounter::new().limit_categories(2);
+    counter.record_event("a");
+    counter.record_event("b");
+    counter.record_event("c");
+    counter.record_events("d", 2);
+    counter.record_event("a");
+
+    let mut snapshot = counter.snapshot();
+    snapshot.sort();
+    assert_eq!(
+        snapshot,
+        vec![("a".to_string(), 2), ("b".to_string(), 1), (OTHER_CATEGORY.to_string(), 3)]
+    );
+    assert_eq!(counter.get_count("c"), 0);
+}
+
+#[test]
+fn only_registered_categories_are_created() {
+    let counter = EventCounter::new().only_categories(&["motion", "power_loss"]);
+    let rx = counter.subscribe();
+    counter.record_event("motion");
+    counter.record_event("../../etc/passwd");
+
+    let mut snapshot = counter.snapshot();
+    snapshot.sort();
+    assert_eq!(snapshot, vec![("motion".to_string(), 1), (OTHER_CATEGORY.to_string(), 1)]);
+    assert_eq!(rx.try_iter().last(), Some((OTHER_CATEGORY.to_string(), 1)));
+}
+
+#[test]
+fn drain_returns_counts_and_zeroes_them() {
+    let counter = EventCounter::new();
+    counter.record_events("a", 2);
+    counter.record_event("b");
+
+    let mut drained = counter.drain();
+    drained.sort();
+    assert_eq!(drained, vec![("a".to_string(), 2), ("b".to_string(), 1)]);
+    assert_eq!(counter.get_count("a"), 0);
+
+    counter.record_event("a");
+    let mut drained = counter.drain();
+    drained.sort();
+    assert_eq!(drained, vec![("a".to_string(), 1), ("b".to_string(), 0)]);
+}
+
+#[test]
+fn drain_does_not_lose_concurrent_events() {
+    use std::sync::Arc;
+    use std::thread;
+
+    let counter = Arc::new(EventCounter::new());
+    let producers: Vec<_> = (0..4)
+        .map(|_| {
+            let c = Arc::clone(&counter);
+            thread::spawn(move || {
+                for _ in 0..10_000 {
+                    c.record_event("event");
+                }
+            })
+        })
+        .collect();
+
+    let mut total = 0;
+    while !producers.iter().all(|p| p.is_finished()) {
+        total += counter.drain().iter().map(|(_, n)| n).sum::<usize>();
+    }
+    for p in producers {
+        p.join().unwrap();
+    }
+    total += counter.drain().iter().map(|(_, n)| n).sum::<usize>();
+    assert_eq!(total, 40_000);
+}
+
 #[test]
 fn concurrent_recording_is_safe() {
     use std::sync::Arc;
@@ -102,4 +933,67 @@ fn concurrent_recording_is_safe() {
     }
 
     assert_eq!(counter.get_count("event"), 10_000);
-}
\ No newline at end of file
+}
+
+#[test]
+fn concurrent_recording_on_many_categories_is_safe() {
+    use std::sync::Arc;
+    use std::thread;
+
+    let counter = Arc::new(EventCounter::new());
+    let handles: Vec<_> = (0..8)
+        .map(|t| {
+            let c = Arc::clone(&counter);
+            thread::spawn(move || {
+                for i in 0..1000 {
+                    c.record_event(&format!("cat{}", (i + t) % 50));
+                }
+            })
+        })
+        .collect();
+
+    for h in handles {
+        h.join().unwrap();
+    }
+
+    let snapshot = counter.snapshot();
+    assert_eq!(snapshot.len(), 50);
+    assert_eq!(snapshot.iter().map(|(_, n)| n).sum::<usize>(), 8000);
+    assert_eq!(counter.get_count("cat0"), 160);
+}
+
+// benchmark: cargo test --release -- --ignored --nocapture
+#[test]
+#[ignore]
+fn bench_record_event_with_many_categories() {
+    use std::time::Instant;
+
+    let categories: Vec<String> = (0..5_000).map(|i| format!("category_{i}")).collect();
+
+    // riferimento: la vecchia implementazione a scansione lineare
+    let start = Instant::now();
+    let mut linear: Vec<(String, usize)> = Vec::new();
+    for _ in 0..10 {
+        for category in &categories {
+            if let Some((_, count)) = linear.iter_mut().find(|(cat, _)| cat == category) {
+                *count += 1;
+            } else {
+                linear.push((category.clone(), 1));
+            }
+        }
+    }
+    let linear_time = start.elapsed();
+
+    let counter = EventCounter::new();
+    let start = Instant::now();
+    for _ in 0..10 {
+        for category in &categories {
+            counter.record_event(category);
+        }
+    }
+    let map_time = start.elapsed();
+
+    println!("Vec: {linear_time:?}, HashMap: {map_time:?}");
+    assert_eq!(counter.get_count("category_4999"), 10);
+    assert!(map_time < linear_time);
+}
@@ -0,0 +1,655 @@
+// Devi implementare una struct PermitManager che gestisce un numero limitato di permessi simultanei utilizzabili 
+// da più thread in parallelo. Essa modella una risorsa condivisa a capacità limitata (come un semaforo) e permette di:
+// Richiedere un permesso (eventualmente aspettando se non ce ne sono disponibili)
+// Rilasciare un permesso
+// Tentarne l'acquisizione in modo non bloccante o con timeout
+
+use std::{sync::{Condvar, Mutex, MutexGuard}, thread::{self, ThreadId}, time::{Duration, Instant}};
+#[cfg(test)]
+use std::sync::Arc;
+
+pub struct PermitManager {
+    permits: Mutex<Permits>,
+    cv: Condvar
+}
+
+struct Permits {
+    available: usize, // permessi acquisibili in questo momento
+    capacity: usize,  // numero massimo di permessi (al netto delle riduzioni richieste)
+    to_reduce: usize, // permessi da ritirare non appena vengono rilasciati
+    waiting: usize,   // thread attualmente bloccati in attesa di permessi
+    acquisitions: u64,
+    timeouts: u64,
+    total_wait: Duration,
+    holders: Option<Vec<PermitHolder>>, // presente solo in modalità debug (with_tracking)
+}
+
+#[derive(Debug, Clone)]
+pub struct PermitStats {
+    pub available: usize,
+    pub waiting_threads: usize,
+    pub total_acquisitions: u64,
+    pub timeouts: u64,
+    pub average_wait: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct PermitHolder {
+    pub thread: ThreadId,
+    pub thread_name: Option<String>,
+    pub permits: usize,
+    pub acquired_at: Instant,
+}
+
+impl Permits {
+    fn record_acquisition(&mut self, n: usize, waited: Duration) {
+        self.acquisitions += 1;
+        self.total_wait += waited;
+        if let Some(holders) = &mut self.holders {
+            let current = thread::current();
+            holders.push(PermitHolder {
+                thread: current.id(),
+                thread_name: current.name().map(|s| s.to_string()),
+                permits: n,
+                acquired_at: Instant::now(),
+            });
+        }
+    }
+
+    fn record_release(&mut self, mut n: usize) {
+        // scarta prima le acquisizioni più vecchie del thread corrente, poi (se i permessi
+        // sono stati rilasciati da un altro thread) quelle più vecchie in assoluto
+        let Some(holders) = &mut self.holders else { return };
+        let current = thread::current().id();
+        for only_current in [true, false] {
+            for holder in holders.iter_mut() {
+                if n == 0 {
+                    break;
+                }
+                if only_current && holder.thread != current {
+                    continue;
+                }
+                let released = n.min(holder.permits);
+                holder.permits -= released;
+                n -= released;
+            }
+        }
+        holders.retain(|h| h.permits > 0);
+    }
+}
+
+impl PermitManager {
+    pub fn new(max_permits: usize) -> Self {
+        // inizializza la struttura con un numero massimo di permessi disponibili
+        Self::build(max_permits, false)
+    }
+
+    pub fn with_tracking(max_permits: usize) -> Self {
+        // come new, ma in modalità debug: registra quale thread detiene ciascun permesso e da quando
+        Self::build(max_permits, true)
+    }
+
+    fn build(max_permits: usize, tracking: bool) -> Self {
+        PermitManager {
+            permits: Mutex::new(Permits {
+                available: max_permits,
+                capacity: max_permits,
+                to_reduce: 0,
+                waiting: 0,
+                acquisitions: 0,
+                timeouts: 0,
+                total_wait: Duration::ZERO,
+                holders: if tracking { Some(Vec::new()) } else { None },
+            }),
+            cv: Condvar::new()
+        }
+    }
+
+    pub fn acquire(&self) {
+        // blocca finché un permesso non è disponibile, e poi lo acquisisce
+        // (il predicato viene ricontrollato a ogni risveglio, quindi un risveglio spurio non
+        // consegna mai un permesso che non c'è)
+        let start = Instant::now();
+        let mut permits = self.permits.lock().unwrap();
+        permits.waiting += 1;
+        let mut permits = self.cv.wait_while(permits, |p| p.available == 0).unwrap();
+        permits.waiting -= 1;
+        permits.available -= 1;
+        permits.record_acquisition(1, start.elapsed());
+    }
+
+    pub fn try_acquire(&self) -> bool {
+        // tenta di acquisire un permesso: ritorna true se ci riesce, false altrimenti
+        let mut permits = self.permits.lock().unwrap();
+        if permits.available == 0 {
+            return false;
+        } else {
+            permits.available -= 1;
+            permits.record_acquisition(1, Duration::ZERO);
+            return true;
+        }
+    }
+
+    pub fn acquire_timeout(&self, dur: Duration) -> bool {
+        // prova ad acquisire un permesso aspettando al massimo dur. Se riesce in tempo ritorna true, altrimenti false
+        let start = Instant::now();
+        let mut permits = self.permits.lock().unwrap();
+        permits.waiting += 1;
+        let (mut permits, result) = self.cv.wait_timeout_while(permits, dur, |p| {p.available==0}).unwrap();
+        permits.waiting -= 1;
+        if result.timed_out() || permits.available == 0 {
+            permits.timeouts += 1;
+            false
+        } else {
+            permits.available -= 1;
+            permits.record_acquisition(1, start.elapsed());
+            true
+        }
+    }
+
+    pub fn try_acquire_for(&self, dur: Duration, every: Duration, mut on_progress: impl FnMut(Duration) -> bool) -> bool {
+        // come acquire_timeout, ma durante l'attesa invoca on_progress ogni `every` passandogli il tempo
+        // già trascorso; se la callback ritorna false l'attesa viene abbandonata e si ritorna false.
+        // La callback è invocata senza tenere il lock, quindi può usare liberamente il PermitManager.
+        // Un intervallo nullo trasformerebbe l'attesa in un ciclo attivo: è un errore del chiamante (panic)
+        assert!(!every.is_zero(), "l'intervallo di notifica deve essere maggiore di zero");
+        let start = Instant::now();
+        let deadline = start + dur;
+        loop {
+            let now = Instant::now();
+            let slice = every.min(deadline.saturating_duration_since(now));
+            let mut permits = self.permits.lock().unwrap();
+            permits.waiting += 1;
+            let (mut permits, _) = self.cv.wait_timeout_while(permits, slice, |p| p.available == 0).unwrap();
+            permits.waiting -= 1;
+            if permits.available > 0 {
+                permits.available -= 1;
+                permits.record_acquisition(1, start.elapsed());
+                return true;
+            }
+            if Instant::now() >= deadline {
+                permits.timeouts += 1;
+                return false;
+            }
+            drop(permits);
+            if !on_progress(start.elapsed()) {
+                return false;
+            }
+        }
+    }
+
+    pub fn release(&self) {
+        // rilascia un permesso precedentemente acquisito
+        self.release_many(1);
+    }
+
+    fn lock_for(&self, n: usize) -> MutexGuard<'_, Permits> {
+        // chiedere più permessi della capacità attuale bloccherebbe per sempre: è un errore del chiamante
+        check_capacity(self.permits.lock().unwrap(), n)
+    }
+
+    pub fn acquire_many(&self, n: usize) {
+        // blocca finché non sono disponibili n permessi, e poi li acquisisce tutti insieme (tutto o niente)
+        // panic se n supera la capacità
+        let start = Instant::now();
+        let mut permits = self.lock_for(n);
+        permits.waiting += 1;
+        // anche una riduzione di capacità durante l'attesa sveglia il thread
+        let mut permits = self.cv.wait_while(permits, |p| p.available < n && n <= p.capacity).unwrap();
+        permits.waiting -= 1;
+        let mut permits = check_capacity(permits, n);
+        permits.available -= n;
+        permits.record_acquisition(n, start.elapsed());
+    }
+
+    pub fn try_acquire_many(&self, n: usize) -> bool {
+        // tenta di acquisire n permessi insieme: se non sono tutti disponibili non ne acquisisce nessuno
+        let mut permits = self.permits.lock().unwrap();
+        if permits.available < n {
+            false
+        } else {
+            permits.available -= n;
+            permits.record_acquisition(n, Duration::ZERO);
+            true
+        }
+    }
+
+    pub fn acquire_many_timeout(&self, n: usize, dur: Duration) -> bool {
+        // come acquire_many, ma aspetta al massimo dur. Se allo scadere i permessi non sono tutti disponibili ritorna false
+        // panic se n supera la capacità, come acquire_many
+        let start = Instant::now();
+        let mut permits = self.lock_for(n);
+        permits.waiting += 1;
+        let (mut permits, _) = self.cv.wait_timeout_while(permits, dur, |p| p.available < n && n <= p.capacity).unwrap();
+        permits.waiting -= 1;
+        let mut permits = check_capacity(permits, n);
+        if permits.available < n {
+            permits.timeouts += 1;
+            false
+        } else {
+            permits.available -= n;
+            permits.record_acquisition(n, start.elapsed());
+            true
+        }
+    }
+
+    pub fn release_many(&self, n: usize) {
+        // rilascia n permessi precedentemente acquisiti e risveglia chi è in attesa
+        // (i permessi da ritirare per una riduzione di capacità non tornano disponibili)
+        let mut permits = self.permits.lock().unwrap();
+        permits.record_release(n);
+        let retired = n.min(permits.to_reduce);
+        permits.to_reduce -= retired;
+        permits.available += n - retired;
+        self.cv.notify_all();
+    }
+
+    pub fn add_permits(&self, n: usize) {
+        // aumenta la capacità di n permessi, annullando prima eventuali riduzioni non ancora applicate
+        let mut permits = self.permits.lock().unwrap();
+        let canceled = n.min(permits.to_reduce);
+        permits.to_reduce -= canceled;
+        permits.available += n - canceled;
+        permits.capacity += n;
+        self.cv.notify_all();
+    }
+
+    pub fn reduce_permits(&self, n: usize) {
+        // riduce la capacità di n permessi (al massimo fino a zero): quelli liberi vengono ritirati subito,
+        // quelli attualmente in uso vengono ritirati man mano che sono rilasciati. Chi attende più permessi
+        // della nuova capacità viene svegliato e va in panic, come se li avesse chiesti adesso
+        let mut permits = self.permits.lock().unwrap();
+        let n = n.min(permits.capacity);
+        let immediate = n.min(permits.available);
+        permits.available -= immediate;
+        permits.to_reduce += n - immediate;
+        permits.capacity -= n;
+        self.cv.notify_all();
+    }
+
+    pub fn available(&self) -> usize {
+        // numero di permessi acquisibili in questo momento
+        self.permits.lock().unwrap().available
+    }
+
+    pub fn waiting_threads(&self) -> usize {
+        // numero di thread attualmente bloccati in attesa di permessi
+        self.permits.lock().unwrap().waiting
+    }
+
+    pub fn leaked_permits(&self, older_than: Duration) -> Vec<PermitHolder> {
+        // in modalità debug restituisce i detentori di permessi acquisiti da più di older_than;
+        // senza tracciamento restituisce sempre un vettore vuoto
+        let permits = self.permits.lock().unwrap();
+        match &permits.holders {
+            Some(holders) => holders.iter().filter(|h| h.acquired_at.elapsed() > older_than).cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn stats(&self) -> PermitStats {
+        // fotografia dei contatori cumulativi; l'attesa media è calcolata sulle acquisizioni riuscite
+        let permits = self.permits.lock().unwrap();
+        let average_wait = if permits.acquisitions == 0 {
+            Duration::ZERO
+        } else {
+            // la divisione è fatta in nanosecondi: convertire acquisitions in u32 la troncherebbe
+            let nanos = permits.total_wait.as_nanos() / permits.acquisitions as u128;
+            Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+        };
+        PermitStats {
+            available: permits.available,
+            waiting_threads: permits.waiting,
+            total_acquisitions: permits.acquisitions,
+            timeouts: permits.timeouts,
+            average_wait,
+        }
+    }
+}
+
+fn check_capacity(permits: MutexGuard<'_, Permits>, n: usize) -> MutexGuard<'_, Permits> {
+    // il lock viene rilasciato prima del panic per non avvelenare il mutex
+    if n > permits.capacity {
+        let capacity = permits.capacity;
+        drop(permits);
+        panic!("richiesti {} permessi, ma la capacità è {}", n, capacity);
+    }
+    permits
+}
+
+
+// -------------------------- TESTS ------------------------------------
+#[test]
+fn new_manager_allows_max_permits() {
+    let manager = PermitManager::new(3);
+    assert!(manager.try_acquire());
+    assert!(manager.try_acquire());
+    assert!(manager.try_acquire());
+    assert!(!manager.try_acquire()); // Esauriti
+}
+
+#[test]
+fn acquire_blocks_until_permit_is_available() {
+    let manager = Arc::new(PermitManager::new(1));
+    assert!(manager.try_acquire());
+
+    let m_clone = Arc::clone(&manager);
+    let handle = thread::spawn(move || {
+        m_clone.acquire(); // deve aspettare
+        m_clone.release();
+    });
+
+    thread::sleep(Duration::from_millis(100));
+    manager.release(); // sblocca il thread
+
+    handle.join().unwrap();
+}
+
+#[test]
+fn acquire_timeout_works_correctly() {
+    let manager = PermitManager::new(1);
+    assert!(manager.try_acquire());
+    let start = Instant::now();
+    let acquired = manager.acquire_timeout(Duration::from_millis(200));
+    let elapsed = start.elapsed();
+    assert!(!acquired);
+    assert!(elapsed >= Duration::from_millis(200));
+}
+
+#[test]
+fn permits_are_reusable() {
+    let manager = PermitManager::new(2);
+    assert!(manager.try_acquire());
+    assert!(manager.try_acquire());
+    assert!(!manager.try_acquire());
+    manager.release();
+    assert!(manager.try_acquire());
+}
+
+#[test]
+fn acquire_many_is_all_or_nothing() {
+    let manager = PermitManager::new(3);
+    assert!(manager.try_acquire());
+    assert!(!manager.try_acquire_many(3)); // ne restano solo 2
+    assert!(manager.try_acquire_many(2)); // nessun permesso consumato dal tentativo fallito
+    assert!(!manager.try_acquire());
+    manager.release_many(3);
+    assert!(manager.try_acquire_many(3));
+}
+
+#[test]
+fn acquire_many_blocks_until_all_permits_are_available() {
+    let manager = Arc::new(PermitManager::new(2));
+    assert!(manager.try_acquire());
+
+    let m_clone = Arc::clone(&manager);
+    let handle = thread::spawn(move || {
+        m_clone.acquire_many(2); // deve aspettare il secondo permesso
+        m_clone.release_many(2);
+    });
+
+    thread::sleep(Duration::from_millis(100));
+    assert!(!handle.is_finished());
+    manager.release();
+
+    handle.join().unwrap();
+    assert!(manager.try_acquire_many(2));
+}
+
+#[test]
+fn acquire_many_timeout_does_not_take_partial_permits() {
+    let manager = PermitManager::new(2);
+    assert!(manager.try_acquire());
+    let start = Instant::now();
+    assert!(!manager.acquire_many_timeout(2, Duration::from_millis(100)));
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    assert!(manager.try_acquire()); // il permesso rimasto è ancora disponibile
+}
+
+#[test]
+#[should_panic(expected = "capacità è 2")]
+fn acquire_many_panics_above_capacity() {
+    let manager = PermitManager::new(2);
+    manager.acquire_many(3);
+}
+
+#[test]
+fn acquire_many_timeout_panics_above_capacity() {
+    let manager = Arc::new(PermitManager::new(2));
+    let m_clone = Arc::clone(&manager);
+    let result = thread::spawn(move || m_clone.acquire_many_timeout(3, Duration::from_secs(5))).join();
+    assert!(result.is_err());
+    // il mutex non è avvelenato e i permessi sono intatti
+    assert!(manager.try_acquire_many(2));
+}
+
+#[test]
+fn add_permits_increases_capacity() {
+    let manager = PermitManager::new(1);
+    assert!(manager.try_acquire());
+    assert!(!manager.try_acquire());
+    manager.add_permits(2);
+    assert!(manager.try_acquire());
+    assert!(manager.try_acquire());
+    assert!(!manager.try_acquire());
+}
+
+#[test]
+fn reduce_permits_takes_effect_as_permits_are_released() {
+    let manager = PermitManager::new(3);
+    assert!(manager.try_acquire_many(2));
+    manager.reduce_permits(2); // uno libero viene ritirato subito, l'altro al primo rilascio
+    assert!(!manager.try_acquire());
+    manager.release();
+    assert!(!manager.try_acquire());
+    manager.release();
+    assert!(manager.try_acquire());
+    assert!(!manager.try_acquire());
+}
+
+#[test]
+fn reduce_permits_fails_waiters_above_the_new_capacity() {
+    let manager = Arc::new(PermitManager::new(3));
+    assert!(manager.try_acquire_many(2));
+    let m_clone = Arc::clone(&manager);
+    let waiter = thread::spawn(move || m_clone.acquire_many(3)); // possibile finché la capacità è 3
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(manager.waiting_threads(), 1);
+    manager.reduce_permits(1); // ora 3 permessi non basteranno mai
+    let err = waiter.join().unwrap_err();
+    assert_eq!(err.downcast_ref::<String>().unwrap(), "richiesti 3 permessi, ma la capacità è 2");
+    assert_eq!(manager.waiting_threads(), 0);
+    manager.release_many(2);
+    assert!(manager.try_acquire_many(2));
+}
+
+#[test]
+fn add_permits_cancels_pending_reduction() {
+    let manager = PermitManager::new(2);
+    assert!(manager.try_acquire_many(2));
+    manager.reduce_permits(1);
+    manager.add_permits(1); // annulla la riduzione non ancora applicata
+    manager.release_many(2);
+    assert!(manager.try_acquire_many(2));
+}
+
+#[test]
+fn stats_track_acquisitions_and_timeouts() {
+    let manager = PermitManager::new(2);
+    assert!(manager.try_acquire());
+    assert!(manager.acquire_timeout(Duration::from_millis(10)));
+    assert_eq!(manager.available(), 0);
+    assert!(!manager.acquire_timeout(Duration::from_millis(10)));
+    assert!(!manager.acquire_many_timeout(2, Duration::from_millis(10)));
+
+    let stats = manager.stats();
+    assert_eq!(stats.available, 0);
+    assert_eq!(stats.waiting_threads, 0);
+    assert_eq!(stats.total_acquisitions, 2);
+    assert_eq!(stats.timeouts, 2);
+}
+
+#[test]
+fn waiting_threads_counts_blocked_acquirers() {
+    let manager = Arc::new(PermitManager::new(1));
+    assert!(manager.try_acquire());
+
+    let m_clone = Arc::clone(&manager);
+    let handle = thread::spawn(move || {
+        m_clone.acquire_many(1);
+    });
+
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(manager.waiting_threads(), 1);
+    manager.release();
+    handle.join().unwrap();
+    assert_eq!(manager.waiting_threads(), 0);
+    assert!(manager.stats().average_wait >= Duration::from_millis(50));
+}
+
+#[test]
+fn leaked_permits_reports_old_holders() {
+    let manager = Arc::new(PermitManager::with_tracking(3));
+    let m_clone = Arc::clone(&manager);
+    thread::Builder::new()
+        .name("leaker".to_string())
+        .spawn(move || assert!(m_clone.try_acquire_many(2)))
+        .unwrap()
+        .join()
+        .unwrap();
+
+    thread::sleep(Duration::from_millis(50));
+    assert!(manager.try_acquire());
+
+    let leaked = manager.leaked_permits(Duration::from_millis(30));
+    assert_eq!(leaked.len(), 1);
+    assert_eq!(leaked[0].thread_name.as_deref(), Some("leaker"));
+    assert_eq!(leaked[0].permits, 2);
+
+    // il rilascio da parte di un altro thread scarta prima le proprie acquisizioni, poi le più vecchie
+    manager.release_many(2);
+    let leaked = manager.leaked_permits(Duration::ZERO);
+    assert_eq!(leaked.len(), 1);
+    assert_eq!(leaked[0].permits, 1);
+}
+
+#[test]
+fn leaked_permits_is_empty_without_tracking() {
+    let manager = PermitManager::new(1);
+    assert!(manager.try_acquire());
+    thread::sleep(Duration::from_millis(10));
+    assert!(manager.leaked_permits(Duration::ZERO).is_empty());
+}
+
+#[test]
+fn acquire_never_exceeds_max_permits_under_contention() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let manager = Arc::new(PermitManager::new(3));
+    let in_use = Arc::new(AtomicUsize::new(0));
+    let max_in_use = Arc::new(AtomicUsize::new(0));
+
+    let handles: Vec<_> = (0..16)
+        .map(|_| {
+            let manager = Arc::clone(&manager);
+            let in_use = Arc::clone(&in_use);
+            let max_in_use = Arc::clone(&max_in_use);
+            thread::spawn(move || {
+                for _ in 0..200 {
+                    manager.acquire();
+                    let now = in_use.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_use.fetch_max(now, Ordering::SeqCst);
+                    thread::yield_now();
+                    in_use.fetch_sub(1, Ordering::SeqCst);
+                    manager.release();
+                }
+            })
+        })
+        .collect();
+
+    for h in handles {
+        h.join().unwrap();
+    }
+
+    assert!(max_in_use.load(Ordering::SeqCst) <= 3);
+    assert_eq!(manager.available(), 3);
+    assert_eq!(manager.waiting_threads(), 0);
+    assert_eq!(manager.stats().total_acquisitions, 16 * 200);
+}
+
+#[test]
+fn acquire_mixed_with_acquire_many_under_contention() {
+    let manager = Arc::new(PermitManager::new(4));
+
+    let handles: Vec<_> = (0..12)
+        .map(|i| {
+            let manager = Arc::clone(&manager);
+            thread::spawn(move || {
+                for _ in 0..100 {
+                    if i % 3 == 0 {
+                        manager.acquire_many(3);
+                        manager.release_many(3);
+                    } else {
+                        manager.acquire();
+                        manager.release();
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for h in handles {
+        h.join().unwrap();
+    }
+
+    assert_eq!(manager.available(), 4);
+}
+
+#[test]
+fn try_acquire_for_reports_progress_while_waiting() {
+    let manager = PermitManager::new(1);
+    assert!(manager.try_acquire());
+    let mut calls = 0;
+    let acquired = manager.try_acquire_for(Duration::from_millis(200), Duration::from_millis(50), |_elapsed| {
+        calls += 1;
+        true
+    });
+    assert!(!acquired);
+    assert!(calls >= 2);
+    assert_eq!(manager.stats().timeouts, 1);
+}
+
+#[test]
+fn try_acquire_for_can_be_aborted_by_the_callback() {
+    let manager = PermitManager::new(1);
+    assert!(manager.try_acquire());
+    let start = Instant::now();
+    let acquired = manager.try_acquire_for(Duration::from_secs(10), Duration::from_millis(20), |elapsed| {
+        elapsed < Duration::from_millis(60)
+    });
+    assert!(!acquired);
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+#[should_panic(expected = "maggiore di zero")]
+fn try_acquire_for_rejects_a_zero_interval() {
+    let manager = PermitManager::new(0);
+    manager.try_acquire_for(Duration::from_millis(50), Duration::ZERO, |_| true);
+}
+
+#[test]
+fn try_acquire_for_acquires_when_a_permit_is_released() {
+    let manager = Arc::new(PermitManager::new(1));
+    assert!(manager.try_acquire());
+
+    let m_clone = Arc::clone(&manager);
+    let handle = thread::spawn(move || {
+        m_clone.try_acquire_for(Duration::from_secs(5), Duration::from_millis(20), |_| true)
+    });
+
+    thread::sleep(Duration::from_millis(100));
+    manager.release();
+    assert!(handle.join().unwrap());
+    assert_eq!(manager.available(), 0);
+}